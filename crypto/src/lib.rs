@@ -2,6 +2,8 @@
 extern crate sodiumoxide;
 
 use core::fmt;
+use curve25519_dalek::edwards::CompressedEdwardsY;
+use ed25519_dalek::Signer;
 
 type HexString = String;
 type Buffer = Vec<u8>;
@@ -66,6 +68,18 @@ impl ShardusCrypto {
         KeyPair { public_key, secret_key }
     }
 
+    /// Derives a key pair like `get_key_pair_using_sk`, returning `None` instead of panicking on malformed input.
+    pub fn try_get_key_pair_using_sk(&self, sk: &HexStringOrBuffer) -> Option<KeyPair> {
+        let secret_key = match sk {
+            HexStringOrBuffer::Hex(hex) => sodiumoxide::crypto::sign::SecretKey::from_slice(&sodiumoxide::hex::decode(hex).ok()?)?,
+            HexStringOrBuffer::Buffer(buf) => sodiumoxide::crypto::sign::SecretKey::from_slice(buf)?,
+        };
+
+        let public_key = secret_key.public_key();
+
+        Some(KeyPair { public_key, secret_key })
+    }
+
     pub fn get_pk(&self, pk: &HexStringOrBuffer) -> sodiumoxide::crypto::sign::PublicKey {
         match pk {
             HexStringOrBuffer::Hex(hex) => sodiumoxide::crypto::sign::PublicKey::from_slice(&sodiumoxide::hex::decode(hex).unwrap()).expect("Invalid public key"),
@@ -73,6 +87,47 @@ impl ShardusCrypto {
         }
     }
 
+    /// Parses a public key like `get_pk`, returning `None` instead of panicking on malformed input.
+    pub fn try_get_pk(&self, pk: &HexStringOrBuffer) -> Option<sodiumoxide::crypto::sign::PublicKey> {
+        match pk {
+            HexStringOrBuffer::Hex(hex) => sodiumoxide::crypto::sign::PublicKey::from_slice(&sodiumoxide::hex::decode(hex).ok()?),
+            HexStringOrBuffer::Buffer(buf) => sodiumoxide::crypto::sign::PublicKey::from_slice(buf),
+        }
+    }
+
+    /// Converts an ed25519 signing key pair into its x25519 (curve25519) equivalent.
+    ///
+    /// # Arguments
+    ///
+    /// * `key_pair` - The ed25519 key pair to convert.
+    ///
+    /// # Returns
+    ///
+    /// A `(public_key, secret_key)` tuple of 32 byte curve25519 keys.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the key pair is not a valid ed25519 key pair.
+    pub fn get_curve25519_key_pair(&self, key_pair: &KeyPair) -> (Buffer, Buffer) {
+        let public_key = sodiumoxide::crypto::sign::ed25519::to_curve25519_pk(&key_pair.public_key).expect("Invalid ed25519 public key");
+        let secret_key = sodiumoxide::crypto::sign::ed25519::to_curve25519_sk(&key_pair.secret_key).expect("Invalid ed25519 secret key");
+
+        (public_key.0.to_vec(), secret_key.0.to_vec())
+    }
+
+    /// Converts an ed25519 public key into its x25519 (curve25519) equivalent.
+    ///
+    /// Returns `None` if the input is not a valid ed25519 public key.
+    pub fn get_curve25519_pk(&self, pk: &HexStringOrBuffer) -> Option<Buffer> {
+        let pk = match pk {
+            HexStringOrBuffer::Hex(hex) => sodiumoxide::crypto::sign::PublicKey::from_slice(&sodiumoxide::hex::decode(hex).ok()?)?,
+            HexStringOrBuffer::Buffer(buf) => sodiumoxide::crypto::sign::PublicKey::from_slice(buf)?,
+        };
+        let public_key = sodiumoxide::crypto::sign::ed25519::to_curve25519_pk(&pk).ok()?;
+
+        Some(public_key.0.to_vec())
+    }
+
     /// Hashes the input string using the specified format.
     ///
     /// # Arguments
@@ -119,6 +174,96 @@ impl ShardusCrypto {
         Ok(signed_message)
     }
 
+    /// Signs several inputs with the same secret key in one pass. The output is identical to calling
+    /// `sign` on each input, but the signing key is expanded only once for the whole batch.
+    ///
+    /// # Arguments
+    ///
+    /// * `inputs` - The data to be signed (in Hex or Buffer format).
+    /// * `sk` - The secret key used for signing.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any input is not valid hexadecimal data.
+    pub fn sign_batch(&self, inputs: Vec<HexStringOrBuffer>, sk: &sodiumoxide::crypto::sign::SecretKey) -> Result<Vec<Buffer>, Box<dyn std::error::Error>> {
+        // A libsodium secret key is the 32 byte seed followed by the public key
+        let mut seed = [0u8; 32];
+        seed.copy_from_slice(&sk.0[..32]);
+        let signing_key = ed25519_dalek::SigningKey::from_bytes(&seed);
+
+        inputs
+            .into_iter()
+            .map(|input| {
+                let input_buf = match input {
+                    HexStringOrBuffer::Hex(hex) => sodiumoxide::hex::decode(hex).map_err(|_| "Invalid hex format for input.")?,
+                    HexStringOrBuffer::Buffer(buf) => buf,
+                };
+
+                // Same combined layout as libsodium: the 64 byte signature followed by the message
+                let mut signed_message = signing_key.sign(&input_buf).to_bytes().to_vec();
+                signed_message.extend_from_slice(&input_buf);
+
+                Ok(signed_message)
+            })
+            .collect()
+    }
+
+    /// Verifies several signatures at once using ed25519 batch verification.
+    ///
+    /// # Arguments
+    ///
+    /// * `entries` - `(message, signature, public_key)` triples, signatures in the combined format produced by `sign`.
+    ///
+    /// # Returns
+    ///
+    /// `true` only if every signature is valid. A `false` result does not say which entries failed,
+    /// so callers should fall back to `verify` on each entry to find them. An entry whose message is
+    /// not valid hexadecimal data fails the batch.
+    ///
+    /// The batch equation is weighted by random coefficients, which can cancel out the small order
+    /// components of points that the strict libsodium `verify` rejects. Entries whose `R` or public
+    /// key is of small order or not canonically encoded therefore fail the batch, leaving the
+    /// decision to `verify`.
+    pub fn verify_batch(&self, entries: &[(HexStringOrBuffer, &Buffer, &sodiumoxide::crypto::sign::PublicKey)]) -> bool {
+        let mut messages = Vec::with_capacity(entries.len());
+        let mut signatures = Vec::with_capacity(entries.len());
+        let mut verifying_keys = Vec::with_capacity(entries.len());
+
+        for (msg, sig, pk) in entries {
+            let msg_buf = match msg {
+                HexStringOrBuffer::Hex(hex) => match sodiumoxide::hex::decode(hex) {
+                    Ok(msg_buf) => msg_buf,
+                    Err(_) => return false,
+                },
+                HexStringOrBuffer::Buffer(buf) => buf.clone(),
+            };
+
+            if sig.len() < ed25519_dalek::SIGNATURE_LENGTH || sig[ed25519_dalek::SIGNATURE_LENGTH..] != msg_buf[..] {
+                return false;
+            }
+
+            let signature = match ed25519_dalek::Signature::from_slice(&sig[..ed25519_dalek::SIGNATURE_LENGTH]) {
+                Ok(signature) => signature,
+                Err(_) => return false,
+            };
+            let verifying_key = match ed25519_dalek::VerifyingKey::from_bytes(&pk.0) {
+                Ok(verifying_key) => verifying_key,
+                Err(_) => return false,
+            };
+            if !is_strict_point(signature.r_bytes()) || !is_strict_point(&pk.0) {
+                return false;
+            }
+
+            messages.push(msg_buf);
+            signatures.push(signature);
+            verifying_keys.push(verifying_key);
+        }
+
+        let messages: Vec<&[u8]> = messages.iter().map(|msg| msg.as_slice()).collect();
+
+        ed25519_dalek::verify_batch(&messages, &signatures, &verifying_keys).is_ok()
+    }
+
     /// Verifies a signature using the provided public key.
     ///
     /// # Arguments
@@ -146,6 +291,14 @@ impl ShardusCrypto {
     }
 }
 
+// is_strict_point: a canonically encoded point that is not of small order, as libsodium requires of R and A
+fn is_strict_point(bytes: &[u8; 32]) -> bool {
+    match CompressedEdwardsY(*bytes).decompress() {
+        Some(point) => point.compress().as_bytes() == bytes && !point.is_small_order(),
+        None => false,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use log::info;
@@ -239,4 +392,79 @@ mod tests {
 
         assert!(result);
     }
+
+    #[test]
+    fn test_sign_and_verify_batch() {
+        let sc = ShardusCrypto::new("64f152869ca2d473e4ba64ab53f49ccdb2edae22da192c126850970e788af347");
+        let key_pair = sc.get_key_pair_using_sk(&HexStringOrBuffer::Hex(
+            "c3774b92cc8850fb4026b073081290b82cab3c0f66cac250b4d710ee9aaf83ed8088b37f6f458104515ae18c2a05bde890199322f62ab5114d20c77bde5e6c9d".to_string(),
+        ));
+
+        let messages: Vec<Buffer> = (0..8u8).map(|i| vec![i; 32]).collect();
+        let sigs = sc
+            .sign_batch(messages.iter().cloned().map(HexStringOrBuffer::Buffer).collect(), &key_pair.secret_key)
+            .expect("Couldn't sign batch");
+
+        for (message, sig) in messages.iter().zip(sigs.iter()) {
+            assert_eq!(sig, &sc.sign(HexStringOrBuffer::Buffer(message.clone()), &key_pair.secret_key).unwrap());
+        }
+
+        let entries: Vec<_> = messages
+            .iter()
+            .zip(sigs.iter())
+            .map(|(message, sig)| (HexStringOrBuffer::Buffer(message.clone()), sig, &key_pair.public_key))
+            .collect();
+        assert!(sc.verify_batch(&entries));
+
+        let mut tampered = sigs.clone();
+        tampered[3][0] ^= 0xff;
+        let entries: Vec<_> = messages
+            .iter()
+            .zip(tampered.iter())
+            .map(|(message, sig)| (HexStringOrBuffer::Buffer(message.clone()), sig, &key_pair.public_key))
+            .collect();
+        assert!(!sc.verify_batch(&entries));
+
+        let entries = vec![
+            (HexStringOrBuffer::Buffer(messages[0].clone()), &sigs[0], &key_pair.public_key),
+            (HexStringOrBuffer::Hex("not hex".to_string()), &sigs[1], &key_pair.public_key),
+        ];
+        assert!(!sc.verify_batch(&entries));
+    }
+
+    #[test]
+    fn test_verify_batch_rejects_small_order() {
+        let sc = ShardusCrypto::new("64f152869ca2d473e4ba64ab53f49ccdb2edae22da192c126850970e788af347");
+
+        // R and A are the identity, a point of small order, and s = 0. The batch equation holds for any
+        // message, libsodium rejects it.
+        let identity = sodiumoxide::hex::decode("0100000000000000000000000000000000000000000000000000000000000000").unwrap();
+        let message = b"any message".to_vec();
+        let pk = sodiumoxide::crypto::sign::PublicKey::from_slice(&identity).unwrap();
+        let mut sig = identity.clone();
+        sig.extend_from_slice(&[0u8; 32]);
+        sig.extend_from_slice(&message);
+
+        let signature = ed25519_dalek::Signature::from_slice(&sig[..ed25519_dalek::SIGNATURE_LENGTH]).unwrap();
+        let verifying_key = ed25519_dalek::VerifyingKey::from_bytes(&pk.0).unwrap();
+        assert!(ed25519_dalek::verify_batch(&[message.as_slice()], &[signature], &[verifying_key]).is_ok());
+
+        assert!(!sc.verify(&HexStringOrBuffer::Buffer(message.clone()), &sig, &pk));
+        assert!(!sc.verify_batch(&[(HexStringOrBuffer::Buffer(message), &sig, &pk)]));
+    }
+
+    #[test]
+    fn test_curve25519_conversion() {
+        let sc = ShardusCrypto::new("64f152869ca2d473e4ba64ab53f49ccdb2edae22da192c126850970e788af347");
+        let key_pair = sc.get_key_pair_using_sk(&HexStringOrBuffer::Hex(
+            "c3774b92cc8850fb4026b073081290b82cab3c0f66cac250b4d710ee9aaf83ed8088b37f6f458104515ae18c2a05bde890199322f62ab5114d20c77bde5e6c9d".to_string(),
+        ));
+
+        let (curve_pk, curve_sk) = sc.get_curve25519_key_pair(&key_pair);
+        let derived_pk = sodiumoxide::crypto::scalarmult::curve25519::scalarmult_base(&sodiumoxide::crypto::scalarmult::curve25519::Scalar::from_slice(&curve_sk).unwrap());
+
+        assert_eq!(curve_pk, derived_pk.0.to_vec());
+        assert_eq!(Some(curve_pk), sc.get_curve25519_pk(&HexStringOrBuffer::Buffer(key_pair.public_key.0.to_vec())));
+        assert_eq!(None, sc.get_curve25519_pk(&HexStringOrBuffer::Buffer(vec![1, 2, 3])));
+    }
 }
@@ -0,0 +1,238 @@
+use crate::cpu_pool::CpuPool;
+use crate::events::{ConnectionEvent, EventKind, Events};
+use crate::message::Message;
+use crate::rate_limit::RateLimiter;
+use crate::shardus_net_listener::{DecodedFrame, Inbox, ListenerError, ShardusNetListener};
+use crate::transport::PeerAddress;
+
+use super::runtime::RUNTIME;
+
+use crypto::ShardusCrypto;
+use log::error;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc::error::SendError;
+use tokio::sync::mpsc::{channel, Receiver, Sender};
+use tokio::time::Instant;
+use tracing::{info_span, Span};
+
+// Full batches that may wait while one is verified. Once they are queued, connection tasks wait in
+// submit instead of reading more frames.
+const QUEUED_BATCHES: usize = 4;
+
+/// Collects decoded frames from every connection of a listener and verifies their signatures in
+/// batches. A batch closes once it holds `max_batch_size` frames or `window` has passed since its
+/// first frame. Verified frames over the rate limit of their signer are dropped, the rest are
+/// delivered in the order they were submitted, so per-connection ordering is kept. Verification
+/// and delivery of a batch run on the `CpuPool`.
+#[derive(Clone)]
+pub(crate) struct BatchVerifier {
+    frame_tx: Sender<(DecodedFrame, PeerAddress, Span)>,
+}
+
+impl BatchVerifier {
+    // spawn: verify_batch holds the max frames and the max wait of a batch
+    pub(crate) fn spawn(verify_batch: (usize, Duration), inbox: Inbox, cpu_pool: Arc<CpuPool>, crypto: Arc<ShardusCrypto>, events: Arc<Events>, rate_limiter: Arc<RateLimiter>) -> Self {
+        let (frame_tx, frame_rx) = channel(verify_batch.0.max(1) * QUEUED_BATCHES);
+        RUNTIME.spawn(Self::run(frame_rx, verify_batch, inbox, cpu_pool, crypto, events, rate_limiter));
+
+        Self { frame_tx }
+    }
+
+    // submit: span is the receive_frame span the frame is verified and delivered under. Waits while the
+    // queue is full, so a slow verifier slows down the connections feeding it.
+    pub(crate) async fn submit(&self, frame: DecodedFrame, remote_addr: PeerAddress, span: Span) -> Result<(), ListenerError> {
+        self.frame_tx.send((frame, remote_addr, span)).await.map_err(|_| SendError(()))?;
+
+        Ok(())
+    }
+
+    async fn run(
+        mut frame_rx: Receiver<(DecodedFrame, PeerAddress, Span)>,
+        (max_batch_size, window): (usize, Duration),
+        inbox: Inbox,
+        cpu_pool: Arc<CpuPool>,
+        crypto: Arc<ShardusCrypto>,
+        events: Arc<Events>,
+        rate_limiter: Arc<RateLimiter>,
+    ) {
+        let max_batch_size = max_batch_size.max(1);
+        while let Some(first) = frame_rx.recv().await {
+            let mut batch = vec![first];
+            let deadline = Instant::now() + window;
+
+            while batch.len() < max_batch_size {
+                match tokio::time::timeout_at(deadline, frame_rx.recv()).await {
+                    Ok(Some(frame)) => batch.push(frame),
+                    Ok(None) | Err(_) => break,
+                }
+            }
+
+            let (batch, spans) = batch.into_iter().map(|(frame, remote_addr, span)| ((frame, remote_addr), span)).unzip();
+            let inbox = inbox.clone();
+            let crypto = crypto.clone();
+            let events = events.clone();
+            let rate_limiter = rate_limiter.clone();
+            if !cpu_pool.run(move || verify_and_deliver(batch, spans, &inbox, &crypto, &events, &rate_limiter)).await {
+                return;
+            }
+        }
+    }
+}
+
+// verify_and_deliver: false once the JS side stopped listening and there is nothing left to deliver to.
+// Each frame is delivered under its own span from spans.
+fn verify_and_deliver(batch: Vec<(DecodedFrame, PeerAddress)>, spans: Vec<Span>, inbox: &Inbox, crypto: &ShardusCrypto, events: &Events, rate_limiter: &RateLimiter) -> bool {
+    let verified = info_span!("verify_batch", frames = batch.len()).in_scope(|| verify_frames(&batch, crypto));
+
+    for (((frame, remote_addr), span), verified) in batch.into_iter().zip(spans).zip(verified) {
+        let _entered = span.enter();
+        if !verified {
+            error!("Failed to verify message signature from {}", remote_addr);
+            events.emit(ConnectionEvent::new(EventKind::SignatureRejected, &remote_addr).inbound().because("invalid signature"));
+            continue;
+        }
+
+        if let Some((signer, bytes)) = frame.signer() {
+            if let Err(exceeded) = rate_limiter.check_signer(signer, bytes) {
+                rate_limiter.reject(&remote_addr, exceeded);
+                continue;
+            }
+        }
+
+        match ShardusNetListener::deliver_frame(frame, &remote_addr, inbox) {
+            Ok(_) => {}
+            Err(ListenerError::SendCompleteError(_)) => return false,
+            Err(err) => error!("Failed to deliver message from {} with Error: {}", remote_addr, err),
+        }
+    }
+
+    true
+}
+
+// verify_frames: one result per frame. The whole batch is checked at once and only when that fails
+// is each signature checked on its own to find the bad ones.
+fn verify_frames(batch: &[(DecodedFrame, PeerAddress)], crypto: &ShardusCrypto) -> Vec<bool> {
+    let signed: Vec<&Message> = batch.iter().filter_map(|(frame, _)| frame.signed_message()).collect();
+
+    if signed.is_empty() || Message::verify_batch(&signed, crypto) {
+        return vec![true; batch.len()];
+    }
+
+    batch.iter().map(|(frame, _)| frame.signed_message().is_none_or(|message| message.verify(crypto))).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::Compression;
+    use crate::header::header_v1::HeaderV1;
+    use crate::rate_limit::RateLimits;
+    use crate::routes::RouteTable;
+    use crypto::HexStringOrBuffer;
+    use tokio::sync::mpsc::unbounded_channel;
+    use uuid::Uuid;
+
+    const SK: &str = "c3774b92cc8850fb4026b073081290b82cab3c0f66cac250b4d710ee9aaf83ed8088b37f6f458104515ae18c2a05bde890199322f62ab5114d20c77bde5e6c9d";
+
+    const HASH_KEY: &str = "69fa4195670576c0160d660c3be36556ff8d504725be8a59b5a96509e0c994bc";
+
+    fn signed_frames(crypto: &ShardusCrypto, count: u8) -> Vec<(DecodedFrame, PeerAddress)> {
+        let key_pair = crypto.get_key_pair_using_sk(&HexStringOrBuffer::Hex(SK.to_string()));
+
+        let mut messages: Vec<Message> = (0..count).map(|i| Message::new_unsigned(0, vec![], vec![i; 16])).collect();
+        Message::sign_batch(&mut messages, crypto, &key_pair);
+
+        messages
+            .into_iter()
+            .map(|message| (DecodedFrame::Headered { message, authenticated: false }, PeerAddress::Ip("127.0.0.1:9001".parse().unwrap())))
+            .collect()
+    }
+
+    #[test]
+    fn test_verify_frames() {
+        let crypto = ShardusCrypto::new(HASH_KEY);
+        let batch = signed_frames(&crypto, 4);
+        assert_eq!(verify_frames(&batch, &crypto), vec![true; 4]);
+
+        let mut batch = signed_frames(&crypto, 4);
+        if let DecodedFrame::Headered { message, .. } = &mut batch[2].0 {
+            message.data[0] ^= 0xff;
+        }
+        batch.push((DecodedFrame::Raw(b"raw".to_vec()), PeerAddress::Ip("127.0.0.1:9001".parse().unwrap())));
+        assert_eq!(verify_frames(&batch, &crypto), vec![true, true, false, true, true]);
+    }
+
+    #[test]
+    fn test_verify_frames_uses_own_hash_key() {
+        let crypto = ShardusCrypto::new(HASH_KEY);
+        let other = ShardusCrypto::new("1111111111111111111111111111111111111111111111111111111111111111");
+
+        let batch = signed_frames(&crypto, 2);
+        assert_eq!(verify_frames(&batch, &other), vec![false, false]);
+        assert_eq!(verify_frames(&batch, &crypto), vec![true, true]);
+    }
+
+    #[test]
+    fn test_malformed_frame_does_not_stop_delivery() {
+        let crypto = Arc::new(ShardusCrypto::new(HASH_KEY));
+        let key_pair = crypto.get_key_pair_using_sk(&HexStringOrBuffer::Hex(SK.to_string()));
+        let remote_addr = PeerAddress::Ip("127.0.0.1:9001".parse().unwrap());
+        let signed = |header_version: u8, header: Vec<u8>, data: &[u8]| {
+            let mut message = Message::new_unsigned(header_version, header, data.to_vec());
+            message.sign(&crypto, &key_pair);
+            DecodedFrame::Headered { message, authenticated: false }
+        };
+        let header = |message_length: u32| {
+            HeaderV1 {
+                uuid: Uuid::new_v4(),
+                message_length,
+                sender_id: String::new(),
+                tracker_id: String::new(),
+                verification_data: String::new(),
+                compression: Compression::None,
+            }
+            .serialize()
+        };
+
+        RUNTIME.block_on(async {
+            let (tx, mut rx) = unbounded_channel();
+            let inbox = Inbox {
+                received_msg_tx: tx,
+                routes: Arc::new(RouteTable::new(&[])),
+            };
+            let rate_limiter = Arc::new(RateLimiter::new(RateLimits::default()));
+            let verifier = BatchVerifier::spawn(
+                (1, Duration::from_millis(1)),
+                inbox,
+                Arc::new(CpuPool::new(1, &[])),
+                crypto.clone(),
+                Arc::new(Events::new()),
+                rate_limiter,
+            );
+
+            // An unknown header version and a truncated header, both correctly signed
+            verifier.submit(signed(3, vec![0; 8], b"data"), remote_addr.clone(), Span::none()).await.unwrap();
+            verifier.submit(signed(1, header(4)[..10].to_vec(), b"data"), remote_addr.clone(), Span::none()).await.unwrap();
+            verifier.submit(signed(1, header(5), b"after"), remote_addr.clone(), Span::none()).await.unwrap();
+
+            let (msg, ..) = tokio::time::timeout(Duration::from_secs(5), rx.recv()).await.unwrap().unwrap();
+            assert_eq!(msg, "after");
+        });
+    }
+
+    #[test]
+    fn test_sign_batch_matches_sign() {
+        let crypto = ShardusCrypto::new(HASH_KEY);
+        let key_pair = crypto.get_key_pair_using_sk(&HexStringOrBuffer::Hex(SK.to_string()));
+
+        let mut batched = vec![Message::new_unsigned(0, vec![1], vec![2]), Message::new_unsigned(0, vec![3], vec![4])];
+        Message::sign_batch(&mut batched, &crypto, &key_pair);
+
+        for message in batched {
+            let mut single = Message::new_unsigned(message.header_version, message.header.clone(), message.data.clone());
+            single.sign(&crypto, &key_pair);
+            assert_eq!(single.sign.sig, message.sign.sig);
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum CidrError {
+    #[error("{0} is not an IP address or CIDR block")]
+    InvalidAddressError(String),
+    #[error("The prefix length of {0} is out of range")]
+    InvalidPrefixError(String),
+}
+
+/// An IPv4 or IPv6 block such as `10.0.0.0/8`. A bare address is a block of one. IPv4 addresses
+/// mapped into IPv6 match the IPv4 blocks that contain them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Cidr {
+    network: IpAddr,
+    prefix_len: u8,
+}
+
+impl Cidr {
+    pub(crate) fn contains(&self, ip: &IpAddr) -> bool {
+        match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => prefix_matches(u32::from(network) as u128, u32::from(ip) as u128, self.prefix_len, 32),
+            (IpAddr::V6(network), IpAddr::V6(ip)) => prefix_matches(u128::from(network), u128::from(ip), self.prefix_len, 128),
+            _ => false,
+        }
+    }
+}
+
+fn prefix_matches(network: u128, ip: u128, prefix_len: u8, bits: u8) -> bool {
+    let host_bits = (bits - prefix_len) as u32;
+    network.checked_shr(host_bits).unwrap_or(0) == ip.checked_shr(host_bits).unwrap_or(0)
+}
+
+impl FromStr for Cidr {
+    type Err = CidrError;
+
+    fn from_str(block: &str) -> Result<Self, Self::Err> {
+        let (address, prefix_len) = match block.trim().split_once('/') {
+            Some((address, prefix_len)) => (address, Some(prefix_len)),
+            None => (block.trim(), None),
+        };
+        let network = IpAddr::from_str(address).map_err(|_| CidrError::InvalidAddressError(block.to_string()))?.to_canonical();
+        let bits = if network.is_ipv4() { 32 } else { 128 };
+
+        let prefix_len = match prefix_len {
+            Some(prefix_len) => prefix_len
+                .parse::<u8>()
+                .ok()
+                .filter(|prefix_len| *prefix_len <= bits)
+                .ok_or_else(|| CidrError::InvalidPrefixError(block.to_string()))?,
+            None => bits,
+        };
+
+        Ok(Self { network, prefix_len })
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix_len)
+    }
+}
+
+/// Which remote IPs may connect. A denied IP is rejected even when it is also allowed, and with a
+/// non-empty allowlist every IP outside of it is rejected.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct IpFilter {
+    pub allow: Vec<Cidr>,
+    pub deny: Vec<Cidr>,
+}
+
+impl IpFilter {
+    // check: Err with why the IP is rejected
+    pub(crate) fn check(&self, ip: &IpAddr) -> Result<(), &'static str> {
+        if self.deny.iter().any(|block| block.contains(ip)) {
+            return Err("denied by the IP filter");
+        }
+        if !self.allow.is_empty() && !self.allow.iter().any(|block| block.contains(ip)) {
+            return Err("not in the IP allowlist");
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(ip: &str) -> IpAddr {
+        ip.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_and_contains() {
+        let block: Cidr = "10.1.0.0/16".parse().unwrap();
+        assert!(block.contains(&ip("10.1.255.3")));
+        assert!(block.contains(&ip("::ffff:10.1.0.9")));
+        assert!(!block.contains(&ip("10.2.0.1")));
+        assert!(!block.contains(&ip("fd00::1")));
+
+        assert!("0.0.0.0/0".parse::<Cidr>().unwrap().contains(&ip("203.0.113.7")));
+        assert!("fd00::/8".parse::<Cidr>().unwrap().contains(&ip("fd12:3456::1")));
+        assert_eq!("192.168.1.1".parse::<Cidr>().unwrap().to_string(), "192.168.1.1/32");
+
+        assert_eq!("10.0.0.0/33".parse::<Cidr>(), Err(CidrError::InvalidPrefixError("10.0.0.0/33".to_string())));
+        assert_eq!("archiver".parse::<Cidr>(), Err(CidrError::InvalidAddressError("archiver".to_string())));
+    }
+
+    #[test]
+    fn test_deny_wins_over_allow() {
+        let filter = IpFilter {
+            allow: vec!["10.0.0.0/8".parse().unwrap()],
+            deny: vec!["10.6.6.0/24".parse().unwrap()],
+        };
+
+        assert!(filter.check(&ip("10.1.2.3")).is_ok());
+        assert_eq!(filter.check(&ip("10.6.6.6")), Err("denied by the IP filter"));
+        assert_eq!(filter.check(&ip("192.0.2.1")), Err("not in the IP allowlist"));
+        assert!(IpFilter::default().check(&ip("192.0.2.1")).is_ok());
+    }
+}
@@ -35,8 +35,10 @@ impl Compression {
             Compression::None => data.to_vec(),
             Compression::Gzip => {
                 // info!("Compressing data with Gzip");
-                let e = GzEncoder::new(data, GzipCompression::default());
-                e.bytes().collect::<Result<Vec<u8>, _>>().unwrap()
+                let mut e = GzEncoder::new(data, GzipCompression::default());
+                let mut result = Vec::new();
+                e.read_to_end(&mut result).unwrap();
+                result
             }
             Compression::Brotli => {
                 // info!("Compressing data with Brotli");
@@ -0,0 +1,268 @@
+use crate::cidr::{Cidr, IpFilter};
+use crate::connection_limit::{ConnectionLimits, OnLimit};
+use crate::cpu_pool::CpuPool;
+use crate::histogram::DEFAULT_LATENCY_WINDOWS;
+use crate::proxy::Proxy;
+use crate::rate_limit::{Limit, RateLimits};
+use crate::resolver::DEFAULT_DNS_CACHE_TTL;
+use crate::socket_options::SocketOptions;
+use neon::prelude::*;
+use std::time::Duration;
+
+/// Optional settings passed to `Sn` as a trailing options object. Every field falls back to the
+/// previous default behaviour when it is left undefined on the JS side.
+pub(crate) struct SnConfig {
+    pub use_noise_sessions: bool,
+    pub enable_quic: bool,
+    // frames per batched signature check, 0 or 1 verifies each frame on its own
+    pub verify_batch_size: usize,
+    pub verify_batch_window_us: u64,
+    // threads of the pool that verifies, signs and (de)compresses messages
+    pub cpu_pool_threads: usize,
+    // recent windows latency percentiles are reported over, next to the lifetime ones
+    pub latency_windows: Vec<Duration>,
+    // localhost port serving GET /metrics, no endpoint when unset
+    pub metrics_port: Option<u16>,
+    pub rate_limits: RateLimits,
+    pub connection_limits: ConnectionLimits,
+    pub ip_filter: IpFilter,
+    // TCP tuning of the listener, accepted streams and outbound connections
+    pub socket_options: SocketOptions,
+    // how long resolved addresses of peers given by host name are reused, 0 resolves on every connect
+    pub dns_cache_ttl: Duration,
+    // egress proxy outbound TCP connections are dialed through, direct when unset
+    pub proxy: Option<Proxy>,
+    // load balancers whose connections open with a PROXY protocol header naming the actual client
+    pub trusted_proxies: Vec<Cidr>,
+}
+
+impl Default for SnConfig {
+    fn default() -> Self {
+        Self {
+            use_noise_sessions: false,
+            enable_quic: false,
+            verify_batch_size: 0,
+            verify_batch_window_us: 500,
+            cpu_pool_threads: CpuPool::default_threads(),
+            latency_windows: DEFAULT_LATENCY_WINDOWS.to_vec(),
+            metrics_port: None,
+            rate_limits: RateLimits::default(),
+            connection_limits: ConnectionLimits::default(),
+            ip_filter: IpFilter::default(),
+            socket_options: SocketOptions::default(),
+            dns_cache_ttl: DEFAULT_DNS_CACHE_TTL,
+            proxy: None,
+            trusted_proxies: Vec::new(),
+        }
+    }
+}
+
+impl SnConfig {
+    // verify_batch: the listener batching settings, None when batching is disabled
+    pub(crate) fn verify_batch(&self) -> Option<(usize, Duration)> {
+        if self.verify_batch_size > 1 {
+            Some((self.verify_batch_size, Duration::from_micros(self.verify_batch_window_us)))
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn from_js_object<'a>(cx: &mut impl Context<'a>, opts: Option<Handle<'a, JsObject>>) -> NeonResult<Self> {
+        let mut config = Self::default();
+
+        let opts = match opts {
+            Some(opts) => opts,
+            None => return Ok(config),
+        };
+
+        if let Some(use_noise_sessions) = opts.get_opt::<JsBoolean, _, _>(cx, "use_noise_sessions")? {
+            config.use_noise_sessions = use_noise_sessions.value(cx);
+        }
+
+        if let Some(enable_quic) = opts.get_opt::<JsBoolean, _, _>(cx, "enable_quic")? {
+            config.enable_quic = enable_quic.value(cx);
+        }
+
+        if let Some(verify_batch_size) = opts.get_opt::<JsNumber, _, _>(cx, "verify_batch_size")? {
+            config.verify_batch_size = verify_batch_size.value(cx) as usize;
+        }
+
+        if let Some(verify_batch_window_us) = opts.get_opt::<JsNumber, _, _>(cx, "verify_batch_window_us")? {
+            config.verify_batch_window_us = verify_batch_window_us.value(cx) as u64;
+        }
+
+        if let Some(cpu_pool_threads) = opts.get_opt::<JsNumber, _, _>(cx, "cpu_pool_threads")? {
+            config.cpu_pool_threads = cpu_pool_threads.value(cx) as usize;
+        }
+
+        if let Some(latency_windows_ms) = opts.get_opt::<JsArray, _, _>(cx, "latency_windows_ms")? {
+            let mut latency_windows = Vec::new();
+            for window_ms in latency_windows_ms.to_vec(cx)? {
+                let window_ms = window_ms.downcast_or_throw::<JsNumber, _>(cx)?.value(cx);
+                latency_windows.push(Duration::from_millis(window_ms.max(0.0) as u64));
+            }
+            config.latency_windows = latency_windows;
+        }
+
+        if let Some(metrics_port) = opts.get_opt::<JsNumber, _, _>(cx, "metrics_port")? {
+            config.metrics_port = Some(metrics_port.value(cx) as u16);
+        }
+
+        if let Some(rate_limits) = opts.get_opt::<JsObject, _, _>(cx, "rate_limits")? {
+            config.rate_limits = rate_limits_from_js_object(cx, rate_limits)?;
+        }
+
+        if let Some(connection_limits) = opts.get_opt::<JsObject, _, _>(cx, "connection_limits")? {
+            if let Some(max_connections) = connection_limits.get_opt::<JsNumber, _, _>(cx, "max_connections")? {
+                config.connection_limits.max_connections = Some(max_connections.value(cx) as usize);
+            }
+
+            if let Some(max_per_ip) = connection_limits.get_opt::<JsNumber, _, _>(cx, "max_per_ip")? {
+                config.connection_limits.max_per_ip = Some(max_per_ip.value(cx) as usize);
+            }
+
+            if let Some(on_limit) = connection_limits.get_opt::<JsString, _, _>(cx, "on_limit")? {
+                let on_limit = on_limit.value(cx);
+                config.connection_limits.on_limit = match OnLimit::from_name(&on_limit) {
+                    Some(on_limit) => on_limit,
+                    None => return cx.throw_type_error(format!("Unknown on_limit {}, expected refuse or evict_idle", on_limit)),
+                };
+            }
+
+            if let Some(idle_timeout_ms) = connection_limits.get_opt::<JsNumber, _, _>(cx, "idle_timeout_ms")? {
+                config.connection_limits.idle_timeout = Some(Duration::from_millis(idle_timeout_ms.value(cx).max(0.0) as u64));
+            }
+
+            if let Some(frame_timeout_ms) = connection_limits.get_opt::<JsNumber, _, _>(cx, "frame_timeout_ms")? {
+                config.connection_limits.frame_timeout = Some(Duration::from_millis(frame_timeout_ms.value(cx).max(0.0) as u64));
+            }
+        }
+
+        config.trusted_proxies = cidrs_from_js_array(cx, opts, "trusted_proxies")?;
+
+        if let Some(ip_filter) = opts.get_opt::<JsObject, _, _>(cx, "ip_filter")? {
+            config.ip_filter = ip_filter_from_js_object(cx, ip_filter)?;
+        }
+
+        if let Some(socket_options) = opts.get_opt::<JsObject, _, _>(cx, "socket_options")? {
+            config.socket_options = socket_options_from_js_object(cx, socket_options)?;
+        }
+
+        if let Some(dns_cache_ttl_ms) = opts.get_opt::<JsNumber, _, _>(cx, "dns_cache_ttl_ms")? {
+            config.dns_cache_ttl = Duration::from_millis(dns_cache_ttl_ms.value(cx).max(0.0) as u64);
+        }
+
+        if let Some(proxy) = opts.get_opt::<JsObject, _, _>(cx, "proxy")? {
+            config.proxy = Some(proxy_from_js_object(cx, proxy)?);
+        }
+
+        Ok(config)
+    }
+}
+
+// rate_limits_from_js_object: { per_ip, per_signer, disconnect }, each limit { messages_per_second, bytes_per_second }
+pub(crate) fn rate_limits_from_js_object<'a>(cx: &mut impl Context<'a>, opts: Handle<'a, JsObject>) -> NeonResult<RateLimits> {
+    let mut rate_limits = RateLimits::default();
+
+    if let Some(per_ip) = opts.get_opt::<JsObject, _, _>(cx, "per_ip")? {
+        rate_limits.per_ip = limit_from_js_object(cx, per_ip)?;
+    }
+
+    if let Some(per_signer) = opts.get_opt::<JsObject, _, _>(cx, "per_signer")? {
+        rate_limits.per_signer = limit_from_js_object(cx, per_signer)?;
+    }
+
+    if let Some(disconnect) = opts.get_opt::<JsBoolean, _, _>(cx, "disconnect")? {
+        rate_limits.disconnect = disconnect.value(cx);
+    }
+
+    Ok(rate_limits)
+}
+
+// proxy_from_js_object: { url, bypass }, bypass an array of CIDR blocks, addresses and host names
+fn proxy_from_js_object<'a>(cx: &mut impl Context<'a>, opts: Handle<'a, JsObject>) -> NeonResult<Proxy> {
+    let url = opts.get::<JsString, _, _>(cx, "url")?.value(cx);
+    let mut bypass = Vec::new();
+    if let Some(entries) = opts.get_opt::<JsArray, _, _>(cx, "bypass")? {
+        for entry in entries.to_vec(cx)? {
+            bypass.push(entry.downcast_or_throw::<JsString, _>(cx)?.value(cx));
+        }
+    }
+
+    match Proxy::new(&url, &bypass) {
+        Ok(proxy) => Ok(proxy),
+        Err(err) => cx.throw_type_error(err.to_string()),
+    }
+}
+
+// socket_options_from_js_object: { backlog, reuse_address, reuse_port, only_v6, nodelay, keepalive_ms, send_buffer_size, recv_buffer_size }
+fn socket_options_from_js_object<'a>(cx: &mut impl Context<'a>, opts: Handle<'a, JsObject>) -> NeonResult<SocketOptions> {
+    let mut socket_options = SocketOptions::default();
+
+    if let Some(backlog) = opts.get_opt::<JsNumber, _, _>(cx, "backlog")? {
+        socket_options.backlog = backlog.value(cx).max(0.0) as u32;
+    }
+
+    if let Some(reuse_address) = opts.get_opt::<JsBoolean, _, _>(cx, "reuse_address")? {
+        socket_options.reuse_address = reuse_address.value(cx);
+    }
+
+    if let Some(reuse_port) = opts.get_opt::<JsBoolean, _, _>(cx, "reuse_port")? {
+        socket_options.reuse_port = reuse_port.value(cx);
+    }
+
+    if let Some(only_v6) = opts.get_opt::<JsBoolean, _, _>(cx, "only_v6")? {
+        socket_options.only_v6 = Some(only_v6.value(cx));
+    }
+
+    if let Some(nodelay) = opts.get_opt::<JsBoolean, _, _>(cx, "nodelay")? {
+        socket_options.nodelay = nodelay.value(cx);
+    }
+
+    if let Some(keepalive_ms) = opts.get_opt::<JsNumber, _, _>(cx, "keepalive_ms")? {
+        socket_options.keepalive = Some(Duration::from_millis(keepalive_ms.value(cx).max(0.0) as u64));
+    }
+
+    if let Some(send_buffer_size) = opts.get_opt::<JsNumber, _, _>(cx, "send_buffer_size")? {
+        socket_options.send_buffer_size = Some(send_buffer_size.value(cx).max(0.0) as usize);
+    }
+
+    if let Some(recv_buffer_size) = opts.get_opt::<JsNumber, _, _>(cx, "recv_buffer_size")? {
+        socket_options.recv_buffer_size = Some(recv_buffer_size.value(cx).max(0.0) as usize);
+    }
+
+    Ok(socket_options)
+}
+
+// ip_filter_from_js_object: { allow, deny }, each an array of CIDR blocks or single addresses
+pub(crate) fn ip_filter_from_js_object<'a>(cx: &mut impl Context<'a>, opts: Handle<'a, JsObject>) -> NeonResult<IpFilter> {
+    Ok(IpFilter {
+        allow: cidrs_from_js_array(cx, opts, "allow")?,
+        deny: cidrs_from_js_array(cx, opts, "deny")?,
+    })
+}
+
+fn cidrs_from_js_array<'a>(cx: &mut impl Context<'a>, opts: Handle<'a, JsObject>, key: &str) -> NeonResult<Vec<Cidr>> {
+    let blocks = match opts.get_opt::<JsArray, _, _>(cx, key)? {
+        Some(blocks) => blocks.to_vec(cx)?,
+        None => return Ok(Vec::new()),
+    };
+
+    let mut cidrs = Vec::with_capacity(blocks.len());
+    for block in blocks {
+        let block = block.downcast_or_throw::<JsString, _>(cx)?.value(cx);
+        match block.parse::<Cidr>() {
+            Ok(cidr) => cidrs.push(cidr),
+            Err(err) => return cx.throw_type_error(err.to_string()),
+        }
+    }
+
+    Ok(cidrs)
+}
+
+fn limit_from_js_object<'a>(cx: &mut impl Context<'a>, opts: Handle<'a, JsObject>) -> NeonResult<Limit> {
+    Ok(Limit {
+        messages_per_second: opts.get_opt::<JsNumber, _, _>(cx, "messages_per_second")?.map(|rate| rate.value(cx)),
+        bytes_per_second: opts.get_opt::<JsNumber, _, _>(cx, "bytes_per_second")?.map(|rate| rate.value(cx)),
+    })
+}
@@ -0,0 +1,473 @@
+use crate::cidr::{Cidr, IpFilter};
+use crate::transport::PeerAddress;
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tokio::time::error::Elapsed;
+
+/// What the listener does with a new connection once a cap is reached.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum OnLimit {
+    Refuse,
+    // close the least recently active connection that counts against the cap to make room
+    EvictIdle,
+}
+
+impl OnLimit {
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "refuse" => Some(OnLimit::Refuse),
+            "evict_idle" => Some(OnLimit::EvictIdle),
+            _ => None,
+        }
+    }
+}
+
+/// Caps on inbound connections across both transports and on how long they may stall. Unset caps
+/// are unlimited. Unix socket peers have no IP and only count against `max_connections`, as do
+/// connections from trusted proxies until the PROXY protocol header names their client.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct ConnectionLimits {
+    pub max_connections: Option<usize>,
+    pub max_per_ip: Option<usize>,
+    pub on_limit: OnLimit,
+    // longest wait for the length prefix of the next frame
+    pub idle_timeout: Option<Duration>,
+    // longest time to receive a frame once its length prefix has been read
+    pub frame_timeout: Option<Duration>,
+}
+
+impl Default for ConnectionLimits {
+    fn default() -> Self {
+        Self {
+            max_connections: None,
+            max_per_ip: None,
+            on_limit: OnLimit::Refuse,
+            idle_timeout: None,
+            frame_timeout: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct InboundConnectionStats {
+    pub open: usize,
+    // distinct remote IPs with an open connection
+    pub ips: usize,
+    // refused by the IP filter, these are not counted in refused
+    pub filtered: u64,
+    pub refused: u64,
+    pub evicted: u64,
+    pub idle_timeouts: u64,
+    pub frame_timeouts: u64,
+}
+
+type Close = Box<dyn FnOnce() + Send>;
+
+struct Entry {
+    address: PeerAddress,
+    // the IP counted against max_per_ip
+    ip: Option<IpAddr>,
+    // milliseconds since the tracker started, written by the connection task on every frame
+    last_activity: Arc<AtomicU64>,
+    close: Option<Close>,
+}
+
+#[derive(Default)]
+struct State {
+    next_id: u64,
+    entries: HashMap<u64, Entry>,
+    per_ip: HashMap<IpAddr, usize>,
+}
+
+impl State {
+    fn remove(&mut self, id: u64) -> Option<Entry> {
+        let entry = self.entries.remove(&id)?;
+        if let Some(ip) = entry.ip {
+            if let Some(count) = self.per_ip.get_mut(&ip) {
+                *count -= 1;
+                if *count == 0 {
+                    self.per_ip.remove(&ip);
+                }
+            }
+        }
+
+        Some(entry)
+    }
+}
+
+/// The inbound connections of a listener, admitted against its `ConnectionLimits`.
+pub(crate) struct InboundConnections {
+    limits: ConnectionLimits,
+    filter: RwLock<IpFilter>,
+    trusted_proxies: Vec<Cidr>,
+    started_at: Instant,
+    state: Mutex<State>,
+    filtered: AtomicU64,
+    refused: AtomicU64,
+    evicted: AtomicU64,
+    idle_timeouts: AtomicU64,
+    frame_timeouts: AtomicU64,
+}
+
+impl InboundConnections {
+    // new: connections from trusted_proxies open with a PROXY protocol header naming their client
+    pub(crate) fn new(limits: ConnectionLimits, filter: IpFilter, trusted_proxies: Vec<Cidr>) -> Self {
+        Self {
+            limits,
+            filter: RwLock::new(filter),
+            trusted_proxies,
+            started_at: Instant::now(),
+            state: Mutex::new(State::default()),
+            filtered: AtomicU64::new(0),
+            refused: AtomicU64::new(0),
+            evicted: AtomicU64::new(0),
+            idle_timeouts: AtomicU64::new(0),
+            frame_timeouts: AtomicU64::new(0),
+        }
+    }
+
+    // update_filter: applies to connections admitted from now on, open ones are left alone
+    pub(crate) fn update_filter(&self, filter: IpFilter) {
+        *self.filter.write().expect("IP filter lock poisoned") = filter;
+    }
+
+    // check_filter: Err with why the IP is refused by the filter. Admitting a connection checks it, connections
+    // proxied through a load balancer check it again for the IP of the client once it is known.
+    pub(crate) fn check_filter(&self, ip: &IpAddr) -> Result<(), &'static str> {
+        self.filter.read().expect("IP filter lock poisoned").check(ip).inspect_err(|_| {
+            self.filtered.fetch_add(1, Ordering::Relaxed);
+        })
+    }
+
+    pub(crate) fn is_trusted_proxy(&self, address: &PeerAddress) -> bool {
+        ip_of(address).is_some_and(|ip| self.trusted_proxies.iter().any(|block| block.contains(&ip)))
+    }
+
+    // until_idle: await the next length prefix, Err once the idle timeout passed and the connection has to close
+    pub(crate) async fn until_idle<F: Future>(&self, read: F) -> Result<F::Output, Elapsed> {
+        within(self.limits.idle_timeout, read).await.inspect_err(|_| {
+            self.idle_timeouts.fetch_add(1, Ordering::Relaxed);
+        })
+    }
+
+    // within_frame_timeout: await the rest of a frame, Err once the frame timeout passed
+    pub(crate) async fn within_frame_timeout<F: Future>(&self, read: F) -> Result<F::Output, Elapsed> {
+        within(self.limits.frame_timeout, read).await.inspect_err(|_| {
+            self.frame_timeouts.fetch_add(1, Ordering::Relaxed);
+        })
+    }
+
+    // admit: a permit to keep for as long as the connection is open, along with the address of the
+    // connection evicted to make room for it. Err with why the connection is refused.
+    pub(crate) fn admit(self: &Arc<Self>, address: &PeerAddress) -> Result<(ConnectionPermit, Option<PeerAddress>), &'static str> {
+        let ip = ip_of(address);
+        if let Some(ip) = ip {
+            self.check_filter(&ip)?;
+        }
+        // Every client behind a proxy would share its cap, they count against it once attributed
+        let ip = ip.filter(|_| !self.is_trusted_proxy(address));
+
+        let mut state = self.lock();
+        let evicted = self.make_room(&mut state, ip, true)?;
+
+        let id = state.next_id;
+        state.next_id += 1;
+        let last_activity = Arc::new(AtomicU64::new(self.now_ms()));
+        state.entries.insert(
+            id,
+            Entry {
+                address: address.clone(),
+                ip,
+                last_activity: last_activity.clone(),
+                close: None,
+            },
+        );
+        if let Some(ip) = ip {
+            *state.per_ip.entry(ip).or_insert(0) += 1;
+        }
+        drop(state);
+
+        let permit = ConnectionPermit {
+            id,
+            last_activity,
+            connections: self.clone(),
+        };
+        Ok((permit, Self::close_evicted(evicted)))
+    }
+
+    // make_room: the connection evicted to fit one more from ip, counting it against max_connections as well
+    // when global. Err with why the connection is refused when there is no room and none can be made.
+    fn make_room(&self, state: &mut State, ip: Option<IpAddr>, global: bool) -> Result<Option<Entry>, &'static str> {
+        let ip_full = match (ip, self.limits.max_per_ip) {
+            (Some(ip), Some(max_per_ip)) => state.per_ip.get(&ip).copied().unwrap_or(0) >= max_per_ip,
+            _ => false,
+        };
+        let full = ip_full || (global && self.limits.max_connections.is_some_and(|max_connections| state.entries.len() >= max_connections));
+        if !full {
+            return Ok(None);
+        }
+
+        let reason = if ip_full { "too many connections from this IP" } else { "too many connections" };
+
+        // Making room for a full IP has to close one of its own connections, that also frees a global slot
+        let victim = match self.limits.on_limit {
+            OnLimit::Refuse => None,
+            OnLimit::EvictIdle => state
+                .entries
+                .iter()
+                .filter(|(_, entry)| entry.close.is_some() && (!ip_full || entry.ip == ip))
+                .min_by_key(|(_, entry)| entry.last_activity.load(Ordering::Relaxed))
+                .map(|(id, _)| *id),
+        };
+        match victim.and_then(|victim| state.remove(victim)) {
+            Some(entry) => {
+                self.evicted.fetch_add(1, Ordering::Relaxed);
+                Ok(Some(entry))
+            }
+            None => {
+                self.refused.fetch_add(1, Ordering::Relaxed);
+                Err(reason)
+            }
+        }
+    }
+
+    // close_evicted: must be called without the lock, closing may drop the permit of the evicted connection
+    fn close_evicted(evicted: Option<Entry>) -> Option<PeerAddress> {
+        let mut evicted = evicted?;
+        if let Some(close) = evicted.close.take() {
+            close();
+        }
+
+        Some(evicted.address)
+    }
+
+    pub(crate) fn stats(&self) -> InboundConnectionStats {
+        let state = self.lock();
+
+        InboundConnectionStats {
+            open: state.entries.len(),
+            ips: state.per_ip.len(),
+            filtered: self.filtered.load(Ordering::Relaxed),
+            refused: self.refused.load(Ordering::Relaxed),
+            evicted: self.evicted.load(Ordering::Relaxed),
+            idle_timeouts: self.idle_timeouts.load(Ordering::Relaxed),
+            frame_timeouts: self.frame_timeouts.load(Ordering::Relaxed),
+        }
+    }
+
+    fn now_ms(&self) -> u64 {
+        self.started_at.elapsed().as_millis() as u64
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().expect("Inbound connections lock poisoned")
+    }
+}
+
+/// Holds a slot of an admitted connection until it is dropped.
+pub(crate) struct ConnectionPermit {
+    id: u64,
+    last_activity: Arc<AtomicU64>,
+    connections: Arc<InboundConnections>,
+}
+
+impl ConnectionPermit {
+    // on_evict: how to close the connection when it is evicted. Connections without one are never evicted.
+    pub(crate) fn on_evict(&self, close: impl FnOnce() + Send + 'static) {
+        if let Some(entry) = self.connections.lock().entries.get_mut(&self.id) {
+            entry.close = Some(Box::new(close));
+        }
+    }
+
+    // attribute: count a connection from a trusted proxy against the cap of the client its PROXY protocol
+    // header names, making room as admit does. Ok with the connection evicted for it.
+    pub(crate) fn attribute(&self, client: SocketAddr) -> Result<Option<PeerAddress>, &'static str> {
+        let connections = &self.connections;
+        let mut state = connections.lock();
+        let evicted = connections.make_room(&mut state, Some(client.ip()), false)?;
+
+        if let Some(entry) = state.entries.get_mut(&self.id) {
+            entry.address = PeerAddress::Ip(client);
+            entry.ip = Some(client.ip());
+            *state.per_ip.entry(client.ip()).or_insert(0) += 1;
+        }
+        drop(state);
+
+        Ok(InboundConnections::close_evicted(evicted))
+    }
+
+    // touch: the connection received a frame
+    pub(crate) fn touch(&self) {
+        self.last_activity.store(self.connections.now_ms(), Ordering::Relaxed);
+    }
+}
+
+impl Drop for ConnectionPermit {
+    fn drop(&mut self) {
+        self.connections.lock().remove(self.id);
+    }
+}
+
+async fn within<F: Future>(limit: Option<Duration>, future: F) -> Result<F::Output, Elapsed> {
+    match limit {
+        Some(limit) => tokio::time::timeout(limit, future).await,
+        None => Ok(future.await),
+    }
+}
+
+fn ip_of(address: &PeerAddress) -> Option<IpAddr> {
+    match address {
+        PeerAddress::Ip(address) => Some(address.ip()),
+        PeerAddress::Unix(_) | PeerAddress::Host(..) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::RUNTIME;
+    use std::sync::atomic::AtomicBool;
+
+    fn peer(ip: [u8; 4], port: u16) -> PeerAddress {
+        PeerAddress::Ip((ip, port).into())
+    }
+
+    #[test]
+    fn test_refuse_over_caps() {
+        let connections = Arc::new(InboundConnections::new(
+            ConnectionLimits {
+                max_connections: Some(3),
+                max_per_ip: Some(2),
+                on_limit: OnLimit::Refuse,
+                ..Default::default()
+            },
+            IpFilter::default(),
+            Vec::new(),
+        ));
+
+        let first = connections.admit(&peer([10, 0, 0, 1], 1)).unwrap().0;
+        let _second = connections.admit(&peer([10, 0, 0, 1], 2)).unwrap();
+        assert_eq!(connections.admit(&peer([10, 0, 0, 1], 3)).err(), Some("too many connections from this IP"));
+
+        let _third = connections.admit(&peer([10, 0, 0, 2], 1)).unwrap();
+        assert_eq!(connections.admit(&peer([10, 0, 0, 3], 1)).err(), Some("too many connections"));
+
+        drop(first);
+        let _fourth = connections.admit(&peer([10, 0, 0, 1], 4)).unwrap();
+        assert_eq!(
+            connections.stats(),
+            InboundConnectionStats {
+                open: 3,
+                ips: 2,
+                refused: 2,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_proxied_connections_count_against_their_client() {
+        let connections = Arc::new(InboundConnections::new(
+            ConnectionLimits {
+                max_per_ip: Some(1),
+                ..Default::default()
+            },
+            IpFilter::default(),
+            vec!["10.9.0.0/16".parse().unwrap()],
+        ));
+
+        let proxied: Vec<ConnectionPermit> = (1..=3).map(|port| connections.admit(&peer([10, 9, 0, 1], port)).unwrap().0).collect();
+        assert_eq!(connections.stats().ips, 0);
+
+        assert_eq!(proxied[0].attribute("203.0.113.7:5000".parse().unwrap()), Ok(None));
+        assert_eq!(proxied[1].attribute("203.0.113.7:5001".parse().unwrap()), Err("too many connections from this IP"));
+        assert_eq!(proxied[2].attribute("203.0.113.8:5000".parse().unwrap()), Ok(None));
+
+        // Connections that are not proxied keep their own cap
+        let _direct = connections.admit(&peer([10, 0, 0, 1], 1)).unwrap();
+        assert_eq!(connections.admit(&peer([10, 0, 0, 1], 2)).err(), Some("too many connections from this IP"));
+
+        drop(proxied);
+        let stats = connections.stats();
+        assert_eq!((stats.open, stats.ips, stats.refused), (1, 1, 2));
+    }
+
+    #[test]
+    fn test_filter_before_limits() {
+        let filter = IpFilter {
+            allow: Vec::new(),
+            deny: vec!["10.6.0.0/16".parse().unwrap()],
+        };
+        let connections = Arc::new(InboundConnections::new(ConnectionLimits::default(), filter, Vec::new()));
+
+        assert_eq!(connections.admit(&peer([10, 6, 1, 1], 1)).err(), Some("denied by the IP filter"));
+        let _allowed = connections.admit(&peer([10, 7, 1, 1], 1)).unwrap();
+        assert!(connections.admit(&PeerAddress::Unix("/tmp/sn.sock".into())).is_ok());
+
+        connections.update_filter(IpFilter::default());
+        let _unblocked = connections.admit(&peer([10, 6, 1, 1], 2)).unwrap();
+
+        let stats = connections.stats();
+        assert_eq!((stats.open, stats.filtered, stats.refused), (2, 1, 0));
+    }
+
+    #[test]
+    fn test_timeouts_are_counted() {
+        let connections = InboundConnections::new(
+            ConnectionLimits {
+                idle_timeout: Some(Duration::from_millis(20)),
+                ..Default::default()
+            },
+            IpFilter::default(),
+            Vec::new(),
+        );
+
+        RUNTIME.block_on(async {
+            assert!(connections.until_idle(std::future::pending::<()>()).await.is_err());
+            assert_eq!(connections.until_idle(async { 7 }).await.unwrap(), 7);
+            // Without a frame timeout a frame may take as long as it takes
+            assert!(connections.within_frame_timeout(tokio::time::sleep(Duration::from_millis(30))).await.is_ok());
+        });
+
+        let stats = connections.stats();
+        assert_eq!((stats.idle_timeouts, stats.frame_timeouts), (1, 0));
+    }
+
+    #[test]
+    fn test_evict_least_recently_active() {
+        let connections = Arc::new(InboundConnections::new(
+            ConnectionLimits {
+                max_connections: Some(2),
+                max_per_ip: None,
+                on_limit: OnLimit::EvictIdle,
+                ..Default::default()
+            },
+            IpFilter::default(),
+            Vec::new(),
+        ));
+
+        let closed = Arc::new(AtomicBool::new(false));
+        let (idle, _) = connections.admit(&peer([10, 0, 0, 1], 1)).unwrap();
+        let closed_by_eviction = closed.clone();
+        idle.on_evict(move || closed_by_eviction.store(true, Ordering::Relaxed));
+
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        let (busy, _) = connections.admit(&peer([10, 0, 0, 2], 1)).unwrap();
+        busy.on_evict(|| panic!("The busy connection must not be evicted"));
+        busy.touch();
+
+        let (_newest, evicted) = connections.admit(&peer([10, 0, 0, 3], 1)).unwrap();
+        assert_eq!(evicted, Some(peer([10, 0, 0, 1], 1)));
+        assert!(closed.load(Ordering::Relaxed));
+
+        // Dropping the permit of an evicted connection frees nothing more
+        drop(idle);
+        assert_eq!(connections.stats().open, 2);
+        assert_eq!(connections.stats().evicted, 1);
+    }
+}
@@ -0,0 +1,171 @@
+use crate::histogram::LatencyHistogram;
+use crate::ring_buffer::RingBuffer;
+
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio::sync::oneshot;
+
+const RING_BUFFER_SIZE: usize = 100;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Dedicated threads for CPU heavy work (signature checks, signing, (de)compression) so it does not
+/// run on the tokio workers that drive socket I/O. Jobs are taken from a shared queue in order.
+pub(crate) struct CpuPool {
+    job_tx: mpsc::Sender<Job>,
+    stats: Arc<CpuPoolStats>,
+}
+
+impl CpuPool {
+    // latency_windows: the recent windows queue wait and run time percentiles are reported over
+    pub(crate) fn new(threads: usize, latency_windows: &[Duration]) -> Self {
+        let (job_tx, job_rx) = mpsc::channel::<Job>();
+        let job_rx = Arc::new(Mutex::new(job_rx));
+
+        for i in 0..threads.max(1) {
+            let job_rx = job_rx.clone();
+            thread::Builder::new()
+                .name(format!("shardus-net-cpu-{}", i))
+                .spawn(move || loop {
+                    // The lock is only held while waiting for the next job, never while running one
+                    let job = match job_rx.lock().expect("CPU pool queue lock poisoned").recv() {
+                        Ok(job) => job,
+                        // Every sender is gone, the pool has been dropped
+                        Err(_) => return,
+                    };
+                    job();
+                })
+                .expect("Failed to spawn CPU pool thread");
+        }
+
+        Self {
+            job_tx,
+            stats: Arc::new(CpuPoolStats::new(latency_windows)),
+        }
+    }
+
+    // default_threads: one per core, leaving one core for the tokio runtime when there are several
+    pub(crate) fn default_threads() -> usize {
+        thread::available_parallelism().map(|cores| cores.get().saturating_sub(1).max(1)).unwrap_or(1)
+    }
+
+    pub(crate) fn stats(&self) -> Arc<CpuPoolStats> {
+        self.stats.clone()
+    }
+
+    // run: execute work on the pool and wait for its result. A panic in work is resumed in the caller
+    // so it fails the same task it would have failed had it run inline, and the worker thread survives.
+    pub(crate) async fn run<F, R>(&self, work: F) -> R
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let (result_tx, result_rx) = oneshot::channel();
+        let stats = self.stats.clone();
+        let queued_at = Instant::now();
+        stats.job_queued();
+
+        self.job_tx
+            .send(Box::new(move || {
+                stats.job_started(queued_at.elapsed());
+                let started_at = Instant::now();
+                let result = panic::catch_unwind(AssertUnwindSafe(work));
+                stats.job_finished(started_at.elapsed());
+                result_tx.send(result).ok();
+            }))
+            .expect("Unexpected! CPU pool threads have exited.");
+
+        match result_rx.await.expect("CPU pool job dropped before completing") {
+            Ok(result) => result,
+            Err(panic) => panic::resume_unwind(panic),
+        }
+    }
+}
+
+/// Queue depth and latencies of a `CpuPool`, written by the pool threads and read by `Stats`.
+pub(crate) struct CpuPoolStats {
+    queue_depth: AtomicUsize,
+    buffers: Mutex<CpuPoolBuffers>,
+}
+
+pub(crate) struct CpuPoolBuffers {
+    pub queue_depth_buffer: RingBuffer<usize>,
+    pub queue_wait: LatencyHistogram,
+    pub run_elapsed: LatencyHistogram,
+}
+
+impl CpuPoolStats {
+    fn new(latency_windows: &[Duration]) -> Self {
+        Self {
+            queue_depth: AtomicUsize::new(0),
+            buffers: Mutex::new(CpuPoolBuffers {
+                queue_depth_buffer: RingBuffer::new(RING_BUFFER_SIZE),
+                queue_wait: LatencyHistogram::new(latency_windows),
+                run_elapsed: LatencyHistogram::new(latency_windows),
+            }),
+        }
+    }
+
+    fn job_queued(&self) {
+        let queue_depth = self.queue_depth.fetch_add(1, Ordering::Relaxed) + 1;
+        self.buffers().queue_depth_buffer.put(queue_depth);
+    }
+
+    fn job_started(&self, queue_wait: Duration) {
+        self.queue_depth.fetch_sub(1, Ordering::Relaxed);
+        self.buffers().queue_wait.record(queue_wait);
+    }
+
+    fn job_finished(&self, elapsed: Duration) {
+        self.buffers().run_elapsed.record(elapsed);
+    }
+
+    pub(crate) fn queue_depth(&self) -> usize {
+        self.queue_depth.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn buffers(&self) -> std::sync::MutexGuard<'_, CpuPoolBuffers> {
+        self.buffers.lock().expect("CPU pool stats lock poisoned")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::RUNTIME;
+
+    #[test]
+    fn test_run_returns_result_and_records_stats() {
+        let pool = Arc::new(CpuPool::new(2, &[]));
+
+        let handles: Vec<_> = (0..10u64)
+            .map(|i| {
+                let pool = pool.clone();
+                RUNTIME.spawn(async move { pool.run(move || i * i).await })
+            })
+            .collect();
+        let results: Vec<u64> = handles.into_iter().map(|handle| RUNTIME.block_on(handle).unwrap()).collect();
+
+        assert_eq!(results, (0..10u64).map(|i| i * i).collect::<Vec<_>>());
+        assert_eq!(pool.stats().queue_depth(), 0);
+        assert_eq!(pool.stats().buffers().run_elapsed.snapshot()[0].1.count, 10);
+    }
+
+    #[test]
+    fn test_panic_is_resumed_in_caller() {
+        let pool = Arc::new(CpuPool::new(1, &[]));
+
+        let panicked = RUNTIME.block_on(RUNTIME.spawn({
+            let pool = pool.clone();
+            async move { pool.run(|| panic!("boom")).await }
+        }));
+        assert!(panicked.unwrap_err().is_panic());
+
+        // The worker thread survived the panic
+        assert_eq!(RUNTIME.block_on(pool.run(|| 7)), 7);
+    }
+}
@@ -0,0 +1,175 @@
+use crate::transport::{PeerAddress, Transport};
+
+use std::sync::Mutex;
+use std::time::SystemTime;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum EventKind {
+    Opened,
+    // an inbound connection was turned away before it was opened
+    Refused,
+    Closed,
+    // an outbound connect or handshake failed before the connection was opened
+    ConnectFailed,
+    // a cached outbound connection failed a write and is being replaced
+    Reconnect,
+    Evicted,
+    Bound,
+    // the listener is bound again after a bind or accept failure
+    Rebound,
+    BindFailed,
+    // accepting failed and the listener is torn down, it rebinds after a pause
+    ListenerFailed,
+    SignatureRejected,
+}
+
+impl EventKind {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            EventKind::Opened => "opened",
+            EventKind::Refused => "refused",
+            EventKind::Closed => "closed",
+            EventKind::ConnectFailed => "connect_failed",
+            EventKind::Reconnect => "reconnect",
+            EventKind::Evicted => "evicted",
+            EventKind::Bound => "bound",
+            EventKind::Rebound => "rebound",
+            EventKind::BindFailed => "bind_failed",
+            EventKind::ListenerFailed => "listener_failed",
+            EventKind::SignatureRejected => "signature_rejected",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Direction {
+    Inbound,
+    Outbound,
+}
+
+impl Direction {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Direction::Inbound => "inbound",
+            Direction::Outbound => "outbound",
+        }
+    }
+}
+
+/// One connection lifecycle event. `address` is the remote peer, or the local address for the
+/// listener events (bound, rebound, bind_failed and listener_failed).
+#[derive(Clone, Debug)]
+pub(crate) struct ConnectionEvent {
+    pub kind: EventKind,
+    pub direction: Option<Direction>,
+    pub transport: Transport,
+    pub address: PeerAddress,
+    pub reason: Option<String>,
+    pub timestamp: SystemTime,
+}
+
+impl ConnectionEvent {
+    pub(crate) fn new(kind: EventKind, address: &PeerAddress) -> Self {
+        Self {
+            kind,
+            direction: None,
+            transport: Transport::Tcp,
+            address: address.clone(),
+            reason: None,
+            timestamp: SystemTime::now(),
+        }
+    }
+
+    pub(crate) fn inbound(self) -> Self {
+        Self {
+            direction: Some(Direction::Inbound),
+            ..self
+        }
+    }
+
+    pub(crate) fn outbound(self) -> Self {
+        Self {
+            direction: Some(Direction::Outbound),
+            ..self
+        }
+    }
+
+    pub(crate) fn over_quic(self) -> Self {
+        Self { transport: Transport::Quic, ..self }
+    }
+
+    pub(crate) fn because(self, reason: impl ToString) -> Self {
+        Self {
+            reason: Some(reason.to_string()),
+            ..self
+        }
+    }
+
+    // transport_name: unix socket peers are reported as unix rather than tcp
+    pub(crate) fn transport_name(&self) -> &'static str {
+        match (&self.address, self.transport) {
+            (PeerAddress::Unix(_), _) => "unix",
+            (_, Transport::Tcp) => "tcp",
+            (_, Transport::Quic) => "quic",
+        }
+    }
+}
+
+/// Fans connection events out to every subscriber. Events emitted while nobody is subscribed are
+/// dropped, and subscribers whose receiver is gone are forgotten on the next event.
+pub(crate) struct Events {
+    subscribers: Mutex<Vec<UnboundedSender<ConnectionEvent>>>,
+}
+
+impl Events {
+    pub(crate) fn new() -> Self {
+        Self { subscribers: Mutex::new(Vec::new()) }
+    }
+
+    pub(crate) fn subscribe(&self) -> UnboundedReceiver<ConnectionEvent> {
+        let (tx, rx) = unbounded_channel();
+        self.lock().push(tx);
+
+        rx
+    }
+
+    pub(crate) fn emit(&self, event: ConnectionEvent) {
+        let mut subscribers = self.lock();
+        if subscribers.is_empty() {
+            return;
+        }
+
+        subscribers.retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<UnboundedSender<ConnectionEvent>>> {
+        self.subscribers.lock().expect("Events lock poisoned")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_emit_to_every_subscriber() {
+        let events = Events::new();
+        let address = PeerAddress::Ip(([127, 0, 0, 1], 9001).into());
+        events.emit(ConnectionEvent::new(EventKind::Opened, &address));
+
+        let mut first = events.subscribe();
+        let second = events.subscribe();
+        events.emit(ConnectionEvent::new(EventKind::Closed, &address).inbound().because("closed by peer"));
+
+        let event = first.try_recv().unwrap();
+        assert_eq!((event.kind, event.direction, event.transport_name()), (EventKind::Closed, Some(Direction::Inbound), "tcp"));
+        assert_eq!(event.reason.as_deref(), Some("closed by peer"));
+        assert!(first.try_recv().is_err());
+
+        drop(second);
+        events.emit(ConnectionEvent::new(EventKind::Evicted, &address).outbound().over_quic());
+        assert_eq!(first.try_recv().unwrap().transport_name(), "quic");
+        assert_eq!(events.lock().len(), 1);
+    }
+}
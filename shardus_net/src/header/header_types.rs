@@ -1,33 +1,62 @@
 use crate::{compression::Compression, ENABLE_COMPRESSION};
 
 use super::header_v1::HeaderV1;
+use super::header_v2::HeaderV2;
+use uuid::Uuid;
 
 pub enum Header {
     V1(HeaderV1),
+    V2(HeaderV2),
 }
 
 impl Header {
     pub fn to_json_string(&self) -> String {
         match self {
             Header::V1(header_v1) => header_v1.to_json_string(),
+            Header::V2(header_v2) => header_v2.to_json_string(),
+        }
+    }
+
+    pub fn uuid(&self) -> Uuid {
+        match self {
+            Header::V1(header_v1) => header_v1.uuid,
+            Header::V2(header_v2) => header_v2.v1.uuid,
+        }
+    }
+
+    pub fn tracker_id(&self) -> &str {
+        match self {
+            Header::V1(header_v1) => &header_v1.tracker_id,
+            Header::V2(header_v2) => &header_v2.v1.tracker_id,
+        }
+    }
+
+    // route: where the receiving side dispatches the message, empty for the listen callback
+    pub fn route(&self) -> &str {
+        match self {
+            Header::V1(_) => "",
+            Header::V2(header_v2) => &header_v2.route,
         }
     }
 
     pub fn validate(&self, message: Vec<u8>) -> bool {
         match self {
             Header::V1(header_v1) => header_v1.validate(message),
+            Header::V2(header_v2) => header_v2.v1.validate(message),
         }
     }
 
     pub fn set_message_length(&mut self, message_length: u32) {
         match self {
             Header::V1(header_v1) => header_v1.message_length = message_length,
+            Header::V2(header_v2) => header_v2.v1.message_length = message_length,
         }
     }
 
     pub fn set_compression(&mut self, compression: Compression) {
         match self {
             Header::V1(header_v1) => header_v1.compression = compression,
+            Header::V2(header_v2) => header_v2.v1.compression = compression,
         }
     }
 
@@ -37,6 +66,7 @@ impl Header {
         }
         match self {
             Header::V1(header_v1) => header_v1.compression.compress(&message),
+            Header::V2(header_v2) => header_v2.v1.compression.compress(&message),
         }
     }
 
@@ -46,6 +76,7 @@ impl Header {
         }
         match self {
             Header::V1(header_v1) => header_v1.compression.decompress(message),
+            Header::V2(header_v2) => header_v2.v1.compression.decompress(message),
         }
     }
 }
@@ -55,4 +86,6 @@ pub struct RequestMetadata {
     pub version: u8,
     pub header_json_string: String,
     pub sign_json_string: String,
+    // the route the message is dispatched to, empty for the listen callback
+    pub route: String,
 }
@@ -0,0 +1,111 @@
+use std::io::{Cursor, Read, Write};
+extern crate serde;
+extern crate serde_json;
+
+use super::header_v1::HeaderV1;
+use serde::Deserialize;
+
+// HeaderV2: the fields of HeaderV1 followed by the route the message is dispatched to on the receiving
+// side. An empty route goes to the listen callback, as every message without a V2 header does.
+#[derive(Deserialize)]
+pub struct HeaderV2 {
+    #[serde(flatten)]
+    pub v1: HeaderV1,
+    #[serde(default)]
+    pub route: String,
+}
+
+impl HeaderV2 {
+    // Serialize the struct into a Vec<u8>
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut buffer = self.v1.serialize();
+
+        // Serialize route as its length (4 bytes) and then its bytes
+        let route_bytes = self.route.as_bytes();
+        let route_len = route_bytes.len() as u32;
+        buffer.write_all(&route_len.to_le_bytes()).unwrap();
+        buffer.write_all(route_bytes).unwrap();
+
+        buffer
+    }
+
+    // Deserialize a Vec<u8> cursor into a HeaderV2 struct
+    pub fn deserialize(cursor: &mut Cursor<Vec<u8>>) -> Option<Self> {
+        let v1 = HeaderV1::deserialize(cursor)?;
+
+        // Deserialize route
+        let mut route_len_bytes = [0u8; 4];
+        cursor.read_exact(&mut route_len_bytes).ok()?;
+        let route_len = u32::from_le_bytes(route_len_bytes);
+
+        let mut route_bytes = vec![0u8; route_len as usize];
+        cursor.read_exact(&mut route_bytes).ok()?;
+        let route = String::from_utf8(route_bytes).ok()?;
+
+        Some(Self { v1, route })
+    }
+
+    pub fn from_json_string(json_str: &str) -> Option<Self> {
+        serde_json::from_str(json_str).ok()
+    }
+
+    pub fn to_json_string(&self) -> String {
+        let v1 = self.v1.to_json_string();
+        // Route names come from JS, so unlike the other fields they are escaped
+        format!(r#"{}, "route": {}}}"#, &v1[..v1.len() - 1], serde_json::Value::from(self.route.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::Compression;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_serialize_and_deserialize() {
+        let header = HeaderV2 {
+            v1: HeaderV1 {
+                uuid: Uuid::new_v4(),
+                message_length: 42,
+                sender_id: "sender_1".to_string(),
+                tracker_id: "tracker_1".to_string(),
+                verification_data: "verification_data_1".to_string(),
+                compression: Compression::None,
+            },
+            route: "gossip".to_string(),
+        };
+
+        let mut cursor = Cursor::new(header.serialize());
+        let deserialized = HeaderV2::deserialize(&mut cursor).unwrap();
+
+        assert_eq!(header.v1.uuid, deserialized.v1.uuid);
+        assert_eq!(header.v1.tracker_id, deserialized.v1.tracker_id);
+        assert_eq!(header.route, deserialized.route);
+
+        // A V1 header lacks the route
+        let mut cursor = Cursor::new(header.v1.serialize());
+        assert!(HeaderV2::deserialize(&mut cursor).is_none());
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let json_str = r#"{
+            "uuid": "550e8400-e29b-41d4-a716-446655440000",
+            "message_length": 42,
+            "sender_id": "127.0.0.1",
+            "route": "sync/\"accounts\""
+        }"#;
+
+        let header = HeaderV2::from_json_string(json_str).unwrap();
+        assert_eq!(header.v1.message_length, 42);
+        assert_eq!(header.route, r#"sync/"accounts""#);
+        assert_eq!(
+            header.to_json_string(),
+            r#"{"uuid": "550e8400-e29b-41d4-a716-446655440000", "message_length": 42, "sender_id": "127.0.0.1", "tracker_id": "", "verification_data": "", "route": "sync/\"accounts\""}"#
+        );
+
+        // Without a route the message goes to the listen callback
+        assert_eq!(HeaderV2::from_json_string(r#"{"uuid": "550e8400-e29b-41d4-a716-446655440000"}"#).unwrap().route, "");
+    }
+}
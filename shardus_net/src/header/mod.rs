@@ -1,2 +1,3 @@
 pub mod header_types;
 pub mod header_v1;
+pub mod header_v2;
@@ -2,6 +2,7 @@ use std::io::Cursor;
 
 use crate::header::header_types::Header;
 use crate::header::header_v1::HeaderV1;
+use crate::header::header_v2::HeaderV2;
 
 pub fn wrap_serialized_message(mut serialized_message: Vec<u8>) -> Vec<u8> {
     let mut buffer = Vec::new();
@@ -16,15 +17,18 @@ pub fn header_deserialize_factory(version: u8, serialized_header_cursor: &mut Cu
             let deserialized = HeaderV1::deserialize(serialized_header_cursor)?;
             Some(Header::V1(deserialized))
         }
+        2 => {
+            let deserialized = HeaderV2::deserialize(serialized_header_cursor)?;
+            Some(Header::V2(deserialized))
+        }
         _ => None,
     }
 }
 
 pub fn header_serialize_factory(header_version: u8, header: Header) -> Option<Vec<u8>> {
-    match header_version {
-        1 => match header {
-            Header::V1(header_v1) => Some(header_v1.serialize()),
-        },
+    match (header_version, header) {
+        (1, Header::V1(header_v1)) => Some(header_v1.serialize()),
+        (2, Header::V2(header_v2)) => Some(header_v2.serialize()),
         _ => None,
     }
 }
@@ -32,6 +36,7 @@ pub fn header_serialize_factory(header_version: u8, header: Header) -> Option<Ve
 pub fn header_from_json_string(json_str: &str, version: &u8) -> Option<Header> {
     match version {
         1 => HeaderV1::from_json_string(json_str).map(Header::V1),
+        2 => HeaderV2::from_json_string(json_str).map(Header::V2),
         _ => None,
     }
 }
@@ -0,0 +1,268 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+// Values below this are counted exactly, above it each power of two is split into SUB_BUCKETS equal
+// buckets, so a reported percentile is never more than 1/SUB_BUCKETS above the recorded value.
+const EXACT_LIMIT: u64 = 128;
+const SUB_BUCKETS: u64 = 64;
+const SUB_BUCKET_BITS: u32 = 6;
+
+// Each window is made of this many slices and slides forward one slice at a time
+const WINDOW_SLICES: u64 = 6;
+
+pub(crate) const DEFAULT_LATENCY_WINDOWS: [Duration; 2] = [Duration::from_secs(10), Duration::from_secs(60)];
+
+/// An HDR style histogram of microsecond values with log-linear buckets. Buckets are only
+/// allocated up to the largest value seen.
+#[derive(Clone, Default)]
+pub(crate) struct Histogram {
+    counts: Vec<u64>,
+    count: u64,
+    min: u64,
+    max: u64,
+    sum: u128,
+}
+
+impl Histogram {
+    pub(crate) fn record(&mut self, value: u64) {
+        let index = bucket_index(value);
+        if index >= self.counts.len() {
+            self.counts.resize(index + 1, 0);
+        }
+        self.counts[index] += 1;
+
+        self.min = if self.count == 0 { value } else { self.min.min(value) };
+        self.max = self.max.max(value);
+        self.count += 1;
+        self.sum += value as u128;
+    }
+
+    fn merge(&mut self, other: &Histogram) {
+        if other.count == 0 {
+            return;
+        }
+        if other.counts.len() > self.counts.len() {
+            self.counts.resize(other.counts.len(), 0);
+        }
+        for (count, other_count) in self.counts.iter_mut().zip(&other.counts) {
+            *count += other_count;
+        }
+
+        self.min = if self.count == 0 { other.min } else { self.min.min(other.min) };
+        self.max = self.max.max(other.max);
+        self.count += other.count;
+        self.sum += other.sum;
+    }
+
+    // value_at_quantile: the highest value that falls in the same bucket as the quantile, capped at max
+    pub(crate) fn value_at_quantile(&self, quantile: f64) -> u64 {
+        if self.count == 0 {
+            return 0;
+        }
+
+        let rank = ((quantile.clamp(0.0, 1.0) * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (index, count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return bucket_upper_bound(index).min(self.max);
+            }
+        }
+
+        self.max
+    }
+
+    pub(crate) fn summary(&self) -> HistogramSummary {
+        HistogramSummary {
+            count: self.count,
+            min: self.min,
+            max: self.max,
+            mean: if self.count == 0 { 0.0 } else { self.sum as f64 / self.count as f64 },
+            p50: self.value_at_quantile(0.5),
+            p90: self.value_at_quantile(0.9),
+            p99: self.value_at_quantile(0.99),
+            p999: self.value_at_quantile(0.999),
+        }
+    }
+}
+
+fn bucket_index(value: u64) -> usize {
+    if value < EXACT_LIMIT {
+        return value as usize;
+    }
+
+    let exponent = 63 - value.leading_zeros();
+    let shift = exponent - SUB_BUCKET_BITS;
+    let sub_bucket = (value >> shift) - SUB_BUCKETS;
+
+    (EXACT_LIMIT + (exponent as u64 - 7) * SUB_BUCKETS + sub_bucket) as usize
+}
+
+fn bucket_upper_bound(index: usize) -> u64 {
+    let index = index as u64;
+    if index < EXACT_LIMIT {
+        return index;
+    }
+
+    let exponent = (index - EXACT_LIMIT) / SUB_BUCKETS + 7;
+    let sub_bucket = (index - EXACT_LIMIT) % SUB_BUCKETS + SUB_BUCKETS;
+    let shift = exponent as u32 - SUB_BUCKET_BITS;
+
+    // Computed wide, the last bucket ends exactly at u64::MAX
+    ((((sub_bucket + 1) as u128) << shift) - 1) as u64
+}
+
+/// Percentiles of a histogram, all values in microseconds.
+pub(crate) struct HistogramSummary {
+    pub count: u64,
+    pub min: u64,
+    pub max: u64,
+    pub mean: f64,
+    pub p50: u64,
+    pub p90: u64,
+    pub p99: u64,
+    pub p999: u64,
+}
+
+/// A latency histogram over the recent `windows` (each sliding forward in sixths of its length)
+/// and over the lifetime of the process.
+pub(crate) struct LatencyHistogram {
+    started_at: Instant,
+    windows: Vec<Window>,
+    lifetime: Histogram,
+}
+
+struct Window {
+    length: Duration,
+    slice_length: Duration,
+    slices: VecDeque<(u64, Histogram)>,
+}
+
+impl Window {
+    fn new(length: Duration) -> Self {
+        let length = length.max(Duration::from_millis(WINDOW_SLICES));
+
+        Self {
+            length,
+            slice_length: length / WINDOW_SLICES as u32,
+            slices: VecDeque::with_capacity(WINDOW_SLICES as usize),
+        }
+    }
+
+    fn slice_at(&self, elapsed: Duration) -> u64 {
+        (elapsed.as_nanos() / self.slice_length.as_nanos()) as u64
+    }
+
+    // expire: drop the slices that have slid out of the window
+    fn expire(&mut self, slice: u64) {
+        while self.slices.front().is_some_and(|(front, _)| front + WINDOW_SLICES <= slice) {
+            self.slices.pop_front();
+        }
+    }
+
+    fn record(&mut self, elapsed: Duration, value: u64) {
+        let slice = self.slice_at(elapsed);
+        self.expire(slice);
+
+        if self.slices.back().is_none_or(|(back, _)| *back != slice) {
+            self.slices.push_back((slice, Histogram::default()));
+        }
+        self.slices.back_mut().expect("Unexpected! The current slice has just been pushed.").1.record(value);
+    }
+
+    fn snapshot(&mut self, elapsed: Duration) -> Histogram {
+        self.expire(self.slice_at(elapsed));
+
+        let mut histogram = Histogram::default();
+        for (_, slice) in &self.slices {
+            histogram.merge(slice);
+        }
+
+        histogram
+    }
+}
+
+impl LatencyHistogram {
+    pub(crate) fn new(windows: &[Duration]) -> Self {
+        Self {
+            started_at: Instant::now(),
+            windows: windows.iter().map(|length| Window::new(*length)).collect(),
+            lifetime: Histogram::default(),
+        }
+    }
+
+    pub(crate) fn record(&mut self, latency: Duration) {
+        let value = latency.as_micros().min(u64::MAX as u128) as u64;
+        let elapsed = self.started_at.elapsed();
+
+        for window in &mut self.windows {
+            window.record(elapsed, value);
+        }
+        self.lifetime.record(value);
+    }
+
+    // snapshot: one summary per window, labelled like "10s", followed by "lifetime"
+    pub(crate) fn snapshot(&mut self) -> Vec<(String, HistogramSummary)> {
+        let elapsed = self.started_at.elapsed();
+
+        let mut summaries: Vec<(String, HistogramSummary)> = self.windows.iter_mut().map(|window| (window_label(window.length), window.snapshot(elapsed).summary())).collect();
+        summaries.push(("lifetime".to_string(), self.lifetime.summary()));
+
+        summaries
+    }
+}
+
+fn window_label(length: Duration) -> String {
+    if length.subsec_millis() == 0 {
+        format!("{}s", length.as_secs())
+    } else {
+        format!("{}ms", length.as_millis())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buckets_stay_within_precision() {
+        for value in (0..1_000_000u64).step_by(37).chain([u64::MAX / 2, u64::MAX]) {
+            let index = bucket_index(value);
+            let upper_bound = bucket_upper_bound(index);
+
+            assert!(upper_bound >= value);
+            assert!(upper_bound - value <= value / SUB_BUCKETS, "{} reported as {}", value, upper_bound);
+            assert!(index == 0 || bucket_upper_bound(index - 1) < value);
+        }
+    }
+
+    #[test]
+    fn test_percentiles() {
+        let mut histogram = Histogram::default();
+        for value in 1..=10_000 {
+            histogram.record(value);
+        }
+
+        let summary = histogram.summary();
+        assert_eq!((summary.count, summary.min, summary.max), (10_000, 1, 10_000));
+        assert_eq!(summary.mean, 5000.5);
+        for (reported, expected) in [(summary.p50, 5_000), (summary.p90, 9_000), (summary.p99, 9_900), (summary.p999, 9_990)] {
+            assert!(reported >= expected && reported - expected <= expected / SUB_BUCKETS, "{} for {}", reported, expected);
+        }
+    }
+
+    #[test]
+    fn test_windows_forget_old_samples() {
+        let mut latencies = LatencyHistogram::new(&[Duration::from_millis(60)]);
+        latencies.record(Duration::from_micros(500));
+
+        std::thread::sleep(Duration::from_millis(80));
+        latencies.record(Duration::from_micros(20));
+
+        let summaries = latencies.snapshot();
+        assert_eq!(summaries[0].0, "60ms");
+        assert_eq!((summaries[0].1.count, summaries[0].1.max), (1, 20));
+        assert_eq!(summaries[1].0, "lifetime");
+        assert_eq!((summaries[1].1.count, summaries[1].1.max), (2, 500));
+    }
+}
@@ -0,0 +1,124 @@
+use crate::noise::NoiseIdentity;
+
+use crypto::{HexStringOrBuffer, ShardusCrypto};
+use once_cell::sync::OnceCell;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum KeyRingError {
+    #[error("The provided signing key is not a valid hex encoded secret key")]
+    InvalidSigningKeyError,
+}
+
+/// The key pair this node signs with and the noise identity derived from it. They are swapped
+/// together so a message is never signed with one key while its session is keyed with another.
+pub struct LocalIdentity {
+    pub key_pair: crypto::KeyPair,
+    pub noise_identity: Option<NoiseIdentity>,
+    retired_until: OnceCell<Instant>,
+}
+
+impl LocalIdentity {
+    fn new(crypto: &Arc<ShardusCrypto>, key_pair: crypto::KeyPair, use_noise_sessions: bool) -> Self {
+        let noise_identity = if use_noise_sessions { Some(NoiseIdentity::new(crypto.clone(), &key_pair)) } else { None };
+
+        Self {
+            key_pair,
+            noise_identity,
+            retired_until: OnceCell::new(),
+        }
+    }
+
+    // is_expired: noise sessions established with a replaced identity are kept until its grace period ends
+    pub fn is_expired(&self) -> bool {
+        self.retired_until.get().is_some_and(|retired_until| Instant::now() >= *retired_until)
+    }
+}
+
+/// The signing identity shared by the sender and listener of one `Sn`. `update` swaps it atomically;
+/// callers take a snapshot with `current` and keep using it for the message or session at hand.
+pub struct KeyRing {
+    crypto: Arc<ShardusCrypto>,
+    use_noise_sessions: bool,
+    current: RwLock<Arc<LocalIdentity>>,
+}
+
+impl KeyRing {
+    pub fn new(crypto: Arc<ShardusCrypto>, key_pair: crypto::KeyPair, use_noise_sessions: bool) -> Self {
+        let current = RwLock::new(Arc::new(LocalIdentity::new(&crypto, key_pair, use_noise_sessions)));
+
+        Self { crypto, use_noise_sessions, current }
+    }
+
+    pub fn crypto(&self) -> &Arc<ShardusCrypto> {
+        &self.crypto
+    }
+
+    pub fn use_noise_sessions(&self) -> bool {
+        self.use_noise_sessions
+    }
+
+    pub fn current(&self) -> Arc<LocalIdentity> {
+        self.current.read().expect("Key ring lock poisoned").clone()
+    }
+
+    // update: sign with the new key from now on. Noise sessions keyed with the old one are torn down
+    // once the grace period has passed, see LocalIdentity::is_expired.
+    pub fn update(&self, hex_signing_sk: String, grace_period: Duration) -> Result<(), KeyRingError> {
+        let key_pair = self
+            .crypto
+            .try_get_key_pair_using_sk(&HexStringOrBuffer::Hex(hex_signing_sk))
+            .ok_or(KeyRingError::InvalidSigningKeyError)?;
+        let identity = Arc::new(LocalIdentity::new(&self.crypto, key_pair, self.use_noise_sessions));
+
+        let previous = std::mem::replace(&mut *self.current.write().expect("Key ring lock poisoned"), identity);
+        previous.retired_until.set(Instant::now() + grace_period).ok();
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH_KEY: &str = "69fa4195670576c0160d660c3be36556ff8d504725be8a59b5a96509e0c994bc";
+    const SK: &str = "c3774b92cc8850fb4026b073081290b82cab3c0f66cac250b4d710ee9aaf83ed8088b37f6f458104515ae18c2a05bde890199322f62ab5114d20c77bde5e6c9d";
+    const NEXT_SK: &str = "d4e7ab21cd6644d639131a100df8cae3cfdb623e009d565fd96029e8438c76fa1163f642da600d31299755f6c567e661e402faa8f4e4b999c0c9ddfdca5d43e8";
+
+    fn key_ring() -> KeyRing {
+        let crypto = Arc::new(ShardusCrypto::new(HASH_KEY));
+        let key_pair = crypto.get_key_pair_using_sk(&HexStringOrBuffer::Hex(SK.to_string()));
+
+        KeyRing::new(crypto, key_pair, true)
+    }
+
+    #[test]
+    fn test_update_swaps_identity_and_retires_previous() {
+        let key_ring = key_ring();
+        let previous = key_ring.current();
+
+        key_ring.update(NEXT_SK.to_string(), Duration::from_secs(60)).unwrap();
+        let current = key_ring.current();
+
+        assert_ne!(previous.key_pair.public_key, current.key_pair.public_key);
+        assert_eq!(current.noise_identity.as_ref().unwrap().public_key(), current.key_pair.public_key.0);
+        assert!(!previous.is_expired());
+        assert!(!current.is_expired());
+
+        key_ring.update(SK.to_string(), Duration::ZERO).unwrap();
+        assert!(current.is_expired());
+    }
+
+    #[test]
+    fn test_update_rejects_invalid_key() {
+        let key_ring = key_ring();
+        let previous = key_ring.current();
+
+        assert!(matches!(key_ring.update("not hex".to_string(), Duration::ZERO), Err(KeyRingError::InvalidSigningKeyError)));
+        assert!(matches!(key_ring.update("abcd".to_string(), Duration::ZERO), Err(KeyRingError::InvalidSigningKeyError)));
+        assert_eq!(key_ring.current().key_pair.public_key, previous.key_pair.public_key);
+    }
+}
@@ -1,39 +1,70 @@
 #![deny(warnings)]
-use std::cell::RefCell;
 use std::collections::HashMap;
-use std::net::SocketAddr;
 use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex as StdMutex};
 use std::time::Duration;
-use std::time::Instant;
-use std::{net::ToSocketAddrs, sync::Arc};
+use std::time::{Instant, UNIX_EPOCH};
 
+use config::SnConfig;
+use connection_limit::InboundConnectionStats;
+use cpu_pool::CpuPool;
+use crypto::ShardusCrypto;
 use header_factory::header_from_json_string;
+use histogram::HistogramSummary;
 #[cfg(debug)]
 use log::info;
 //use log::LevelFilter;
 use lru::LruCache;
-use neon::{prelude::*, result::Throw};
-
+use neon::prelude::*;
+
+mod batch_verify;
+mod cidr;
+mod config;
+mod connection_limit;
+mod cpu_pool;
+mod events;
+mod histogram;
+mod key_ring;
+mod logger;
 mod message;
+mod metrics;
+mod noise;
+mod peer_stats;
+mod proxy;
+mod proxy_protocol;
+mod quic;
+mod rate_limit;
+mod resolver;
 mod ring_buffer;
+mod routes;
 mod runtime;
-mod shardus_crypto;
 mod shardus_net_listener;
 mod shardus_net_sender;
+mod socket_options;
 mod stats;
+mod telemetry;
+mod token_bucket;
+mod transport;
 
 pub mod compression;
 pub mod header;
 mod header_factory;
 
+use events::{ConnectionEvent, Events};
+use key_ring::KeyRing;
+use logger::LogRecord;
+use peer_stats::{PeerCounters, PeerStats, PeerStatsOrder};
 use ring_buffer::Stats as RingBufferStats;
-use runtime::RUNTIME;
+use routes::{RouteOptions, RouteStatsSnapshot};
+use runtime::{RuntimeConfig, RuntimeStats, RUNTIME};
 use shardus_net_listener::ShardusNetListener;
 use shardus_net_sender::ConnectionCache;
 use shardus_net_sender::{SendResult, ShardusNetSender};
-use stats::{Incrementers, Stats, StatsResult};
+use stats::{Incrementers, SharedStats, Stats, StatsResult};
+use telemetry::TracingConfig;
 use tokio::sync::oneshot;
 use tokio::sync::Mutex;
+use transport::{PeerAddress, Transport};
 
 use crate::shardus_net_sender::Connection;
 
@@ -50,18 +81,62 @@ fn create_shardus_net(mut cx: FunctionContext) -> JsResult<JsObject> {
     let lru_size = cx.argument::<JsNumber>(3)?.value(cx);
     let hash_key = cx.argument::<JsString>(4)?.value(cx);
 
-    shardus_crypto::initialize_shardus_crypto_instance(&hash_key);
+    // Each Sn owns its crypto context so instances with different hash keys can live in one process
+    let shardus_crypto = Arc::new(ShardusCrypto::new(&hash_key));
 
     let hex_signing_sk = cx.argument::<JsString>(5)?.value(cx);
-    let key_pair = shardus_crypto::get_shardus_crypto_instance().get_key_pair_using_sk(&crypto::HexStringOrBuffer::Hex(hex_signing_sk));
+    let key_pair = shardus_crypto.get_key_pair_using_sk(&crypto::HexStringOrBuffer::Hex(hex_signing_sk));
+
+    let opts = match cx.argument_opt(6) {
+        Some(opts) if !opts.is_a::<JsUndefined, _>(cx) => Some(opts.downcast_or_throw::<JsObject, _>(cx)?),
+        _ => None,
+    };
+    let config = SnConfig::from_js_object(cx, opts)?;
+
+    let key_ring = Arc::new(KeyRing::new(shardus_crypto, key_pair, config.use_noise_sessions));
+
+    let cpu_pool = Arc::new(CpuPool::new(config.cpu_pool_threads, &config.latency_windows));
+    let peer_stats = Arc::new(PeerStats::new());
+    let events = Arc::new(Events::new());
+
+    let shardus_net_listener = match create_shardus_net_listener(port, host, &config, key_ring.clone(), cpu_pool.clone(), peer_stats.clone(), events.clone()) {
+        Some(shardus_net_listener) => shardus_net_listener,
+        None => return cx.throw_type_error("The provided address is not valid"),
+    };
+    let shardus_net_sender = create_shardus_net_sender(
+        use_lru,
+        NonZeroUsize::new(lru_size as usize).unwrap(),
+        key_ring,
+        cpu_pool.clone(),
+        peer_stats.clone(),
+        events.clone(),
+        &config,
+    );
+    let (stats, stats_incrementers) = Stats::new(
+        cpu_pool.stats(),
+        peer_stats,
+        shardus_net_listener.rate_limiter(),
+        shardus_net_listener.inbound_connections(),
+        shardus_net_listener.routes().stats(),
+        &config.latency_windows,
+    );
+    let stats = Arc::new(StdMutex::new(stats));
+
+    if let Some(metrics_port) = config.metrics_port {
+        match metrics::bind(metrics_port) {
+            Ok(metrics_listener) => {
+                RUNTIME.spawn(metrics::serve(metrics_listener, Arc::downgrade(&stats)));
+            }
+            Err(err) => return cx.throw_error(format!("Failed to bind the metrics port {}: {}", metrics_port, err)),
+        }
+    }
 
-    let shardus_net_listener = create_shardus_net_listener(cx, port, host)?;
-    let shardus_net_sender = create_shardus_net_sender(use_lru, NonZeroUsize::new(lru_size as usize).unwrap(), key_pair);
-    let (stats, stats_incrementers) = Stats::new();
     let shardus_net_listener = cx.boxed(shardus_net_listener);
     let shardus_net_sender = cx.boxed(shardus_net_sender);
-    let stats = cx.boxed(RefCell::new(stats));
+    let stats = cx.boxed(stats);
     let stats_incrementers = cx.boxed(stats_incrementers);
+    let events = cx.boxed(events);
+    let route_handlers = cx.boxed(RouteHandlers::default());
 
     let shardus_net = cx.empty_object();
 
@@ -69,26 +144,57 @@ fn create_shardus_net(mut cx: FunctionContext) -> JsResult<JsObject> {
     let send = JsFunction::new(cx, send)?;
     let send_with_header = JsFunction::new(cx, send_with_header)?;
     let multi_send_with_header = JsFunction::new(cx, multi_send_with_header)?;
+    let multi_send_with_distinct_payloads = JsFunction::new(cx, multi_send_with_distinct_payloads)?;
     let get_stats: Handle<'_, JsFunction> = JsFunction::new(cx, get_stats)?;
+    let get_peer_stats = JsFunction::new(cx, get_peer_stats)?;
+    let get_route_stats = JsFunction::new(cx, get_route_stats)?;
+    let get_metrics = JsFunction::new(cx, get_metrics)?;
+    let subscribe_events = JsFunction::new(cx, subscribe_events)?;
     let evict_socket = JsFunction::new(cx, evict_socket)?;
+    let set_peer_transport = JsFunction::new(cx, set_peer_transport)?;
+    let update_signing_key = JsFunction::new(cx, update_signing_key)?;
+    let update_rate_limits = JsFunction::new(cx, update_rate_limits)?;
+    let update_ip_filter = JsFunction::new(cx, update_ip_filter)?;
+    let register_route = JsFunction::new(cx, register_route)?;
+    let unregister_route = JsFunction::new(cx, unregister_route)?;
 
     shardus_net.set(cx, "_listener", shardus_net_listener)?;
     shardus_net.set(cx, "_sender", shardus_net_sender)?;
     shardus_net.set(cx, "_stats", stats)?;
     shardus_net.set(cx, "_stats_incrementers", stats_incrementers)?;
+    shardus_net.set(cx, "_events", events)?;
+    shardus_net.set(cx, "_route_handlers", route_handlers)?;
     shardus_net.set(cx, "listen", listen)?;
     shardus_net.set(cx, "send", send)?;
     shardus_net.set(cx, "send_with_header", send_with_header)?;
     shardus_net.set(cx, "multi_send_with_header", multi_send_with_header)?;
+    shardus_net.set(cx, "multi_send_with_distinct_payloads", multi_send_with_distinct_payloads)?;
     shardus_net.set(cx, "evict_socket", evict_socket)?;
+    shardus_net.set(cx, "set_peer_transport", set_peer_transport)?;
+    shardus_net.set(cx, "update_signing_key", update_signing_key)?;
+    shardus_net.set(cx, "update_rate_limits", update_rate_limits)?;
+    shardus_net.set(cx, "update_ip_filter", update_ip_filter)?;
+    shardus_net.set(cx, "register_route", register_route)?;
+    shardus_net.set(cx, "unregister_route", unregister_route)?;
     shardus_net.set(cx, "stats", get_stats)?;
+    shardus_net.set(cx, "peer_stats", get_peer_stats)?;
+    shardus_net.set(cx, "route_stats", get_route_stats)?;
+    shardus_net.set(cx, "metrics", get_metrics)?;
+    shardus_net.set(cx, "subscribe_events", subscribe_events)?;
 
     Ok(shardus_net)
 }
 
+// listen: callback(message, ...) for every message received, after on_listening(error, addresses) has
+// been called once with the addresses bound or why binding failed. ports and hosts list additional addresses.
+// Messages sent to a route go to the handler registered for it with register_route instead.
 fn listen(mut cx: FunctionContext) -> JsResult<JsUndefined> {
     let cx = &mut cx;
     let callback = cx.argument::<JsFunction>(0)?.root(cx);
+    let ports = port_array_argument(cx, 1)?;
+    let hosts = string_array_argument(cx, 2)?;
+    let on_listening = cx.argument::<JsFunction>(3)?.root(cx);
+    let additional_addresses = hosts.iter().zip(ports.iter()).map(|(host, port)| PeerAddress::parse(host, *port)).collect();
     let shardus_net_listener = cx.this().get::<JsBox<Arc<ShardusNetListener>>, _, _>(cx, "_listener")?;
     let stats_incrementers = cx.this().get::<JsBox<Incrementers>, _, _>(cx, "_stats_incrementers")?;
     let stats_incrementers = (**stats_incrementers).clone();
@@ -98,14 +204,42 @@ fn listen(mut cx: FunctionContext) -> JsResult<JsUndefined> {
     let channel = cx.channel();
 
     RUNTIME.spawn(async move {
-        let mut rx = shardus_net_listener.listen();
+        let result = shardus_net_listener.listen(additional_addresses).await;
+        let listening = result.as_ref().map(|(addresses, _)| addresses.clone()).map_err(|err| err.to_string());
+        channel.send(move |mut cx| {
+            let cx = &mut cx;
+            let this = cx.undefined();
+            let args: [Handle<JsValue>; 2] = match listening {
+                Ok(addresses) => {
+                    let bound = cx.empty_array();
+                    for (i, address) in addresses.iter().enumerate() {
+                        let obj = cx.empty_object();
+                        let host = cx.string(address.host());
+                        obj.set(cx, "address", host)?;
+                        let port = cx.number(address.port());
+                        obj.set(cx, "port", port)?;
+                        bound.set(cx, i as u32, obj)?;
+                    }
+                    [cx.null().upcast(), bound.upcast()]
+                }
+                Err(err) => [cx.error(err)?.upcast(), cx.undefined().upcast()],
+            };
+            on_listening.to_inner(cx).call(cx, this, args)?;
+
+            Ok(())
+        });
+
+        let mut rx = match result {
+            Ok((_, rx)) => rx,
+            Err(_) => return,
+        };
         let callback = Arc::new(callback);
         let this = Arc::new(this);
 
         // rx is the UnboundedReceiver<(String, SocketAddr)> that is returned from listen.
         // all received messages are sent to the UnboundedSender.  here we call recv to
         // get messages from the UnboundedReceiver.  recv is a blocking call
-        while let Some((msg, remote_address, optional_request_metadata)) = rx.recv().await {
+        while let Some((msg, remote_address, optional_request_metadata, span)) = rx.recv().await {
             let callback = callback.clone();
             let this = this.clone();
             let channel = channel.clone();
@@ -116,19 +250,22 @@ fn listen(mut cx: FunctionContext) -> JsResult<JsUndefined> {
                 let now = Instant::now();
                 channel.send(move |mut cx| {
                     let cx = &mut cx;
+                    let _dispatch = tracing::info_span!(parent: &span, "dispatch").entered();
 
                     let elapsed = now.elapsed();
-                    let stats = this.to_inner(cx).get::<JsBox<RefCell<Stats>>, _, _>(cx, "_stats")?;
-                    let mut stats = (**stats).borrow_mut();
+                    let route = optional_request_metadata.as_ref().map(|request_metadata| request_metadata.route.as_str()).unwrap_or("");
+                    let this_object = this.to_inner(cx);
+                    let stats = this_object.get::<JsBox<SharedStats>, _, _>(cx, "_stats")?;
+                    let mut stats = (**stats).lock().expect("Stats lock poisoned");
 
                     stats.decrement_outstanding_receives();
-                    stats.put_elapsed_receive(elapsed);
+                    stats.put_elapsed_receive(route, elapsed);
 
                     drop(stats);
 
                     let this = cx.undefined();
                     let message = cx.string(msg);
-                    let remote_ip = cx.string(remote_address.ip().to_string());
+                    let remote_ip = cx.string(remote_address.host());
                     let remote_port = cx.number(remote_address.port());
                     let optional_header_version: neon::handle::Handle<'_, neon::prelude::JsValue> = match &optional_request_metadata {
                         Some(request_metadata) => cx.number(request_metadata.version as f64).upcast(),
@@ -154,7 +291,17 @@ fn listen(mut cx: FunctionContext) -> JsResult<JsUndefined> {
                         optional_sign_json_string,
                     ];
 
-                    callback.to_inner(cx).call(cx, this, args)?;
+                    if route.is_empty() {
+                        callback.to_inner(cx).call(cx, this, args)?;
+                        return Ok(());
+                    }
+
+                    // The route may have been unregistered since the listener checked it
+                    let route_handlers = this_object.get::<JsBox<RouteHandlers>, _, _>(cx, "_route_handlers")?;
+                    let handler = route_handlers.handlers.lock().expect("Route handlers lock poisoned").get(route).cloned();
+                    if let Some(handler) = handler {
+                        handler.to_inner(cx).call(cx, this, args)?;
+                    }
 
                     Ok(())
                 });
@@ -165,6 +312,32 @@ fn listen(mut cx: FunctionContext) -> JsResult<JsUndefined> {
     Ok(cx.undefined())
 }
 
+// subscribe_events: call back with every connection lifecycle event from now on
+fn subscribe_events(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let cx = &mut cx;
+    let callback = Arc::new(cx.argument::<JsFunction>(0)?.root(cx));
+    let events = cx.this().get::<JsBox<Arc<Events>>, _, _>(cx, "_events")?;
+    let mut rx = events.subscribe();
+    let channel = cx.channel();
+
+    RUNTIME.spawn(async move {
+        while let Some(event) = rx.recv().await {
+            let callback = callback.clone();
+
+            channel.send(move |mut cx| {
+                let cx = &mut cx;
+                let this = cx.undefined();
+                let event = event.to_object(cx)?;
+                callback.to_inner(cx).call(cx, this, [event.upcast::<JsValue>()])?;
+
+                Ok(())
+            });
+        }
+    });
+
+    Ok(cx.undefined())
+}
+
 fn send(mut cx: FunctionContext) -> JsResult<JsUndefined> {
     let cx = &mut cx;
     let port = cx.argument::<JsNumber>(0)?.value(cx);
@@ -179,15 +352,20 @@ fn send(mut cx: FunctionContext) -> JsResult<JsUndefined> {
     let (complete_tx, complete_rx) = oneshot::channel::<SendResult>();
 
     stats_incrementers.increment_outstanding_sends();
+    let stats_incrementers = (**stats_incrementers).clone();
+    let started_at = Instant::now();
+    let bytes = data.len();
 
     RUNTIME.spawn(async move {
         let result = complete_rx.await.expect("Complete send tx dropped before notify");
+        stats_incrementers.record_send_latency(started_at.elapsed());
+        stats_incrementers.record_route_send("", bytes, result.is_ok());
 
         RUNTIME.spawn_blocking(move || {
             channel.send(move |mut cx| {
                 let cx = &mut cx;
-                let stats = this.to_inner(cx).get::<JsBox<RefCell<Stats>>, _, _>(cx, "_stats")?;
-                (**stats).borrow_mut().decrement_outstanding_sends();
+                let stats = this.to_inner(cx).get::<JsBox<SharedStats>, _, _>(cx, "_stats")?;
+                (**stats).lock().expect("Stats lock poisoned").decrement_outstanding_sends();
 
                 let this = cx.undefined();
 
@@ -203,15 +381,10 @@ fn send(mut cx: FunctionContext) -> JsResult<JsUndefined> {
         });
     });
 
-    match (host, port as u16).to_socket_addrs() {
-        Ok(mut address) => {
-            let address = address.next().expect("Expected at least one address");
-            shardus_net_sender.send(address, data, complete_tx);
+    // Host names are resolved on the runtime, a failed lookup is reported to complete_cb
+    shardus_net_sender.send(PeerAddress::parse(&host, port as u16), data, complete_tx);
 
-            Ok(cx.undefined())
-        }
-        Err(_) => cx.throw_type_error("The provided address is not valid"),
-    }
+    Ok(cx.undefined())
 }
 
 pub fn send_with_header(mut cx: FunctionContext) -> JsResult<JsUndefined> {
@@ -231,6 +404,8 @@ pub fn send_with_header(mut cx: FunctionContext) -> JsResult<JsUndefined> {
     let (complete_tx, complete_rx) = oneshot::channel::<SendResult>();
 
     stats_incrementers.increment_outstanding_sends();
+    let stats_incrementers = (**stats_incrementers).clone();
+    let started_at = Instant::now();
 
     let header = match header_from_json_string(&header_js_string, &header_version) {
         Some(header) => header,
@@ -241,15 +416,19 @@ pub fn send_with_header(mut cx: FunctionContext) -> JsResult<JsUndefined> {
     };
 
     let data = data_js_string.into_bytes().to_vec();
+    let route = header.route().to_string();
+    let bytes = data.len();
 
     RUNTIME.spawn(async move {
         let result = complete_rx.await.expect("Complete send tx dropped before notify");
+        stats_incrementers.record_send_latency(started_at.elapsed());
+        stats_incrementers.record_route_send(&route, bytes, result.is_ok());
 
         RUNTIME.spawn_blocking(move || {
             channel.send(move |mut cx| {
                 let cx = &mut cx;
-                let stats = this.to_inner(cx).get::<JsBox<RefCell<Stats>>, _, _>(cx, "_stats")?;
-                (**stats).borrow_mut().decrement_outstanding_sends();
+                let stats = this.to_inner(cx).get::<JsBox<SharedStats>, _, _>(cx, "_stats")?;
+                (**stats).lock().expect("Stats lock poisoned").decrement_outstanding_sends();
 
                 let this = cx.undefined();
 
@@ -265,36 +444,16 @@ pub fn send_with_header(mut cx: FunctionContext) -> JsResult<JsUndefined> {
         });
     });
 
-    match (host, port).to_socket_addrs() {
-        Ok(mut address) => {
-            let address = address.next().expect("Expected at least one address");
-            shardus_net_sender.send_with_header(address, header_version, header, data, complete_tx);
+    shardus_net_sender.send_with_header(PeerAddress::parse(&host, port), header_version, header, data, complete_tx);
 
-            Ok(cx.undefined())
-        }
-        Err(_) => cx.throw_type_error("The provided address is not valid"),
-    }
+    Ok(cx.undefined())
 }
 
 pub fn multi_send_with_header(mut cx: FunctionContext) -> JsResult<JsUndefined> {
     let cx = &mut cx;
 
-    let ports_js_array = cx.argument::<JsArray>(0)?;
-    let ports_result: NeonResult<Vec<u16>> = ports_js_array
-        .to_vec(cx)?
-        .iter()
-        .map(|val| val.downcast::<JsNumber, _>(cx).or_throw(cx).map(|js_number| js_number.value(cx) as u16))
-        .collect(); // Collects into a Result<Vec<u16>, _>
-    let ports = ports_result?; // Handle error or unwrap the result
-
-    // Convert the JavaScript array of hosts to a Vec<String> in Rust
-    let hosts_js_array = cx.argument::<JsArray>(1)?;
-    let hosts_result: NeonResult<Vec<String>> = hosts_js_array
-        .to_vec(cx)?
-        .iter()
-        .map(|val| val.downcast::<JsString, _>(cx).or_throw(cx).map(|js_string| js_string.value(cx)))
-        .collect(); // Collects into a Result<Vec<String>, _>
-    let hosts = hosts_result?; // Handle error or unwrap the result
+    let ports = port_array_argument(cx, 0)?;
+    let hosts = string_array_argument(cx, 1)?;
 
     let header_version: u8 = cx.argument::<JsNumber>(2)?.value(cx) as u8;
     let header_js_string: String = cx.argument::<JsString>(3)?.value(cx) as String;
@@ -303,14 +462,6 @@ pub fn multi_send_with_header(mut cx: FunctionContext) -> JsResult<JsUndefined>
     let await_processing = cx.argument::<JsBoolean>(6)?.value(cx); // this flag lets us skip the processing on the stats and the callback
 
     let shardus_net_sender = cx.this().get::<JsBox<Arc<ShardusNetSender>>, _, _>(cx, "_sender")?;
-    let stats_incrementers = cx.this().get::<JsBox<Incrementers>, _, _>(cx, "_stats_incrementers")?;
-
-    let this = cx.this().root(cx);
-    let channel = cx.channel();
-
-    for _ in 0..ports.len() {
-        stats_incrementers.increment_outstanding_sends();
-    }
 
     let header = match header_from_json_string(&header_js_string, &header_version) {
         Some(header) => header,
@@ -322,35 +473,132 @@ pub fn multi_send_with_header(mut cx: FunctionContext) -> JsResult<JsUndefined>
 
     let data = data_js_string.into_bytes().to_vec();
 
+    // should a check be added to see if ports.len == hosts.len
+    let payloads = vec![(header.route().to_string(), data.len()); hosts.len()];
+    let senders = spawn_multi_send_completions(cx, ports.len(), payloads, complete_cb, await_processing)?;
+
+    let addresses = parse_addresses(cx, &hosts, &ports)?;
+
+    // Send each address with its corresponding sender
+    shardus_net_sender.multi_send_with_header(addresses, header_version, header, data, senders);
+
+    Ok(cx.undefined())
+}
+
+pub fn multi_send_with_distinct_payloads(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let cx = &mut cx;
+
+    let ports = port_array_argument(cx, 0)?;
+    let hosts = string_array_argument(cx, 1)?;
+    let header_version: u8 = cx.argument::<JsNumber>(2)?.value(cx) as u8;
+    let header_js_strings = string_array_argument(cx, 3)?;
+    let data_js_strings = string_array_argument(cx, 4)?;
+    let complete_cb = cx.argument::<JsFunction>(5)?.root(cx);
+    let await_processing = cx.argument::<JsBoolean>(6)?.value(cx);
+
+    if ports.len() != hosts.len() || hosts.len() != header_js_strings.len() || hosts.len() != data_js_strings.len() {
+        return cx.throw_type_error("ports, hosts, headers and payloads must all have the same length");
+    }
+
+    let shardus_net_sender = cx.this().get::<JsBox<Arc<ShardusNetSender>>, _, _>(cx, "_sender")?;
+
+    let mut payloads = Vec::with_capacity(header_js_strings.len());
+    for (header_js_string, data_js_string) in header_js_strings.iter().zip(data_js_strings) {
+        match header_from_json_string(header_js_string, &header_version) {
+            Some(header) => payloads.push((header, data_js_string.into_bytes())),
+            None => return cx.throw_error("Failed to parse header"),
+        }
+    }
+
+    let addresses = parse_addresses(cx, &hosts, &ports)?;
+    let sent = payloads.iter().map(|(header, data)| (header.route().to_string(), data.len())).collect();
+    let senders = spawn_multi_send_completions(cx, ports.len(), sent, complete_cb, await_processing)?;
+
+    shardus_net_sender.multi_send_with_distinct_payloads(addresses, header_version, payloads, senders);
+
+    Ok(cx.undefined())
+}
+
+fn port_array_argument(cx: &mut FunctionContext, i: i32) -> NeonResult<Vec<u16>> {
+    let ports_js_array = cx.argument::<JsArray>(i)?;
+    ports_js_array
+        .to_vec(cx)?
+        .iter()
+        .map(|val| val.downcast::<JsNumber, _>(cx).or_throw(cx).map(|js_number| js_number.value(cx) as u16))
+        .collect() // Collects into a Result<Vec<u16>, _>
+}
+
+fn string_array_argument(cx: &mut FunctionContext, i: i32) -> NeonResult<Vec<String>> {
+    let strings_js_array = cx.argument::<JsArray>(i)?;
+    strings_js_array
+        .to_vec(cx)?
+        .iter()
+        .map(|val| val.downcast::<JsString, _>(cx).or_throw(cx).map(|js_string| js_string.value(cx)))
+        .collect() // Collects into a Result<Vec<String>, _>
+}
+
+// parse_addresses: host names are left to be resolved on the runtime
+fn parse_addresses(cx: &mut FunctionContext, hosts: &[String], ports: &[u16]) -> NeonResult<Vec<PeerAddress>> {
+    let addresses: Vec<PeerAddress> = hosts.iter().zip(ports.iter()).map(|(host, port)| PeerAddress::parse(host, *port)).collect();
+
+    if addresses.is_empty() {
+        return cx.throw_type_error("No valid addresses provided");
+    }
+
+    Ok(addresses)
+}
+
+// spawn_multi_send_completions: one oneshot per destination, each calling complete_cb once its send
+// finishes. payloads holds the route and payload bytes sent to each destination.
+fn spawn_multi_send_completions(
+    cx: &mut FunctionContext,
+    outstanding_sends: usize,
+    payloads: Vec<(String, usize)>,
+    complete_cb: Root<JsFunction>,
+    await_processing: bool,
+) -> NeonResult<Vec<oneshot::Sender<SendResult>>> {
+    let stats_incrementers = cx.this().get::<JsBox<Incrementers>, _, _>(cx, "_stats_incrementers")?;
+
+    let this = cx.this().root(cx);
+    let channel = cx.channel();
+
+    for _ in 0..outstanding_sends {
+        stats_incrementers.increment_outstanding_sends();
+    }
+    let stats_incrementers = (**stats_incrementers).clone();
+    let started_at = Instant::now();
+
     // Create oneshot channels for each host-port pair
-    let mut senders = Vec::with_capacity(hosts.len());
-    let mut receivers = Vec::with_capacity(hosts.len());
+    let mut senders = Vec::with_capacity(payloads.len());
+    let mut receivers = Vec::with_capacity(payloads.len());
 
-    // should a check be added to see if ports.len == hosts.len
-    for _ in 0..hosts.len() {
+    for payload in payloads {
         let (sender, receiver) = oneshot::channel::<SendResult>();
         senders.push(sender);
-        receivers.push(receiver);
+        receivers.push((receiver, payload));
     }
 
     let complete_cb = Arc::new(complete_cb);
     let this = Arc::new(this);
 
     // Handle the responses asynchronously
-    for receiver in receivers {
+    for (receiver, (route, bytes)) in receivers {
         let channel = channel.clone();
         let complete_cb = complete_cb.clone();
         let this = this.clone();
+        let stats_incrementers = stats_incrementers.clone();
 
         RUNTIME.spawn(async move {
             let result = receiver.await.expect("Complete send tx dropped before notify");
+            stats_incrementers.record_send_latency(started_at.elapsed());
+            stats_incrementers.record_route_send(&route, bytes, result.is_ok());
 
             if await_processing {
                 RUNTIME.spawn_blocking(move || {
                     channel.send(move |mut cx| {
                         let cx = &mut cx;
-                        let stats = this.to_inner(cx).get::<JsBox<RefCell<Stats>>, _, _>(cx, "_stats")?;
-                        (**stats).borrow_mut().decrement_outstanding_sends();
+                        let stats = this.to_inner(cx).get::<JsBox<SharedStats>, _, _>(cx, "_stats")?;
+                        (**stats).lock().expect("Stats lock poisoned").decrement_outstanding_sends();
 
                         let this = cx.undefined();
 
@@ -368,34 +616,63 @@ pub fn multi_send_with_header(mut cx: FunctionContext) -> JsResult<JsUndefined>
         });
     }
 
-    let mut addresses = Vec::new();
-    for (host, port) in hosts.iter().zip(ports.iter()) {
-        match (host as &str, *port).to_socket_addrs() {
-            Ok(mut addr_iter) => {
-                let address = addr_iter.next().expect("Expected at least one address");
-                addresses.push(address);
+    Ok(senders)
+}
+
+fn get_stats(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let cx = &mut cx;
+    let stats = cx.this().get::<JsBox<SharedStats>, _, _>(cx, "_stats")?;
+    let stats = (**stats).lock().expect("Stats lock poisoned").get_stats();
+    let stats = stats.to_object(cx)?;
+
+    Ok(stats)
+}
+
+// get_peer_stats: counters for every tracked remote address, or with top only the N largest by order
+fn get_peer_stats(mut cx: FunctionContext) -> JsResult<JsArray> {
+    let cx = &mut cx;
+    let top = match cx.argument_opt(0) {
+        Some(top) if !top.is_a::<JsUndefined, _>(cx) => Some(top.downcast_or_throw::<JsNumber, _>(cx)?.value(cx).max(0.0) as usize),
+        _ => None,
+    };
+    let order = match cx.argument_opt(1) {
+        Some(order) if !order.is_a::<JsUndefined, _>(cx) => {
+            let order = order.downcast_or_throw::<JsString, _>(cx)?.value(cx);
+            match PeerStatsOrder::from_name(&order) {
+                Some(order) => order,
+                None => return cx.throw_type_error(format!("Unknown peer stats order {}", order)),
             }
-            Err(_) => return cx.throw_type_error(format!("The provided address {}:{} is not valid", host, port)),
         }
-    }
+        _ => PeerStatsOrder::SendErrors,
+    };
+    let stats = cx.this().get::<JsBox<SharedStats>, _, _>(cx, "_stats")?;
+    let peers = (**stats).lock().expect("Stats lock poisoned").get_peer_stats(top, order);
 
-    if addresses.is_empty() {
-        return cx.throw_type_error("No valid addresses provided");
+    let array = cx.empty_array();
+    for (i, (address, counters)) in peers.iter().enumerate() {
+        let obj = counters.to_object(cx, address)?;
+        array.set(cx, i as u32, obj)?;
     }
 
-    // Send each address with its corresponding sender
-    shardus_net_sender.multi_send_with_header(addresses, header_version, header, data, senders);
+    Ok(array)
+}
 
-    Ok(cx.undefined())
+// get_route_stats: counters and callback latency for every tracked route, "" for messages without one
+fn get_route_stats(mut cx: FunctionContext) -> JsResult<JsArray> {
+    let cx = &mut cx;
+    let stats = cx.this().get::<JsBox<SharedStats>, _, _>(cx, "_stats")?;
+    let routes = (**stats).lock().expect("Stats lock poisoned").get_route_stats();
+
+    route_stats_array(cx, &routes)
 }
 
-fn get_stats(mut cx: FunctionContext) -> JsResult<JsObject> {
+// get_metrics: all stats in the OpenMetrics text format
+fn get_metrics(mut cx: FunctionContext) -> JsResult<JsString> {
     let cx = &mut cx;
-    let stats = cx.this().get::<JsBox<RefCell<Stats>>, _, _>(cx, "_stats")?;
-    let stats = (**stats).borrow_mut().get_stats();
-    let stats = stats.to_object(cx)?;
+    let stats = cx.this().get::<JsBox<SharedStats>, _, _>(cx, "_stats")?;
+    let metrics = metrics::render(&mut (**stats).lock().expect("Stats lock poisoned"));
 
-    Ok(stats)
+    Ok(cx.string(metrics))
 }
 
 fn evict_socket(mut cx: FunctionContext) -> JsResult<JsUndefined> {
@@ -404,31 +681,141 @@ fn evict_socket(mut cx: FunctionContext) -> JsResult<JsUndefined> {
     let host = cx.argument::<JsString>(1)?.value(cx);
     let shardus_net_sender = cx.this().get::<JsBox<Arc<ShardusNetSender>>, _, _>(cx, "_sender")?;
 
-    match (host, port as u16).to_socket_addrs() {
-        Ok(mut address) => {
-            let address = address.next().expect("Expected at least one address");
+    shardus_net_sender.evict_socket(PeerAddress::parse(&host, port as u16));
 
-            shardus_net_sender.evict_socket(address);
+    Ok(cx.undefined())
+}
+
+fn set_peer_transport(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let cx = &mut cx;
+    let port = cx.argument::<JsNumber>(0)?.value(cx);
+    let host = cx.argument::<JsString>(1)?.value(cx);
+    let transport = cx.argument::<JsString>(2)?.value(cx);
+    let shardus_net_sender = cx.this().get::<JsBox<Arc<ShardusNetSender>>, _, _>(cx, "_sender")?;
+
+    let transport = match Transport::from_name(&transport) {
+        Some(transport) => transport,
+        None => return cx.throw_type_error(format!("Unknown transport {}, expected tcp or quic", transport)),
+    };
+
+    match PeerAddress::parse(&host, port as u16) {
+        PeerAddress::Unix(_) => cx.throw_type_error("The transport of a unix socket peer cannot be changed"),
+        address => {
+            shardus_net_sender.set_peer_transport(address, transport);
 
             Ok(cx.undefined())
         }
-        Err(_) => cx.throw_type_error("The provided address is not valid"),
     }
 }
 
-fn create_shardus_net_listener(cx: &mut FunctionContext, port: f64, host: String) -> Result<Arc<ShardusNetListener>, Throw> {
-    // @TODO: Verify that a javascript number properly converts here without loss.
-    let address = (host, port as u16);
+// update_signing_key: sign with a new key from now on. Noise sessions keyed with the old key stay up for
+// the optional grace period (ms, default 0) so peers can move over before they are torn down.
+fn update_signing_key(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let cx = &mut cx;
+    let hex_signing_sk = cx.argument::<JsString>(0)?.value(cx);
+    let grace_period_ms = match cx.argument_opt(1) {
+        Some(grace_period_ms) if !grace_period_ms.is_a::<JsUndefined, _>(cx) => grace_period_ms.downcast_or_throw::<JsNumber, _>(cx)?.value(cx),
+        _ => 0.0,
+    };
+    let shardus_net_sender = cx.this().get::<JsBox<Arc<ShardusNetSender>>, _, _>(cx, "_sender")?;
+
+    match shardus_net_sender.update_signing_key(hex_signing_sk, Duration::from_millis(grace_period_ms.max(0.0) as u64)) {
+        Ok(_) => Ok(cx.undefined()),
+        Err(err) => cx.throw_type_error(err.to_string()),
+    }
+}
+
+// update_rate_limits: replace the inbound rate limits, every remote starts over with a full bucket
+fn update_rate_limits(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let cx = &mut cx;
+    let opts = cx.argument::<JsObject>(0)?;
+    let rate_limits = config::rate_limits_from_js_object(cx, opts)?;
+    let shardus_net_listener = cx.this().get::<JsBox<Arc<ShardusNetListener>>, _, _>(cx, "_listener")?;
+
+    shardus_net_listener.rate_limiter().update(rate_limits);
 
-    let shardus_net = ShardusNetListener::new(address);
+    Ok(cx.undefined())
+}
+
+// update_ip_filter: replace the CIDR allow and deny lists new inbound connections are checked against
+fn update_ip_filter(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let cx = &mut cx;
+    let opts = cx.argument::<JsObject>(0)?;
+    let ip_filter = config::ip_filter_from_js_object(cx, opts)?;
+    let shardus_net_listener = cx.this().get::<JsBox<Arc<ShardusNetListener>>, _, _>(cx, "_listener")?;
 
-    match shardus_net {
-        Ok(net) => Ok(Arc::new(net)),
-        Err(_) => cx.throw_type_error("The provided address is not valid")?,
+    shardus_net_listener.inbound_connections().update_filter(ip_filter);
+
+    Ok(cx.undefined())
+}
+
+// register_route: messages sent with a V2 header naming route go to handler instead of the listen callback,
+// called with the same arguments. Messages over max_size payload bytes, or unsigned ones when require_signature
+// is set, are dropped before reaching it. Registering a route again replaces its handler and options.
+fn register_route(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let cx = &mut cx;
+    let route = cx.argument::<JsString>(0)?.value(cx);
+    let handler = cx.argument::<JsFunction>(1)?.root(cx);
+    let max_size = match cx.argument_opt(2) {
+        Some(max_size) if !max_size.is_a::<JsUndefined, _>(cx) => Some(max_size.downcast_or_throw::<JsNumber, _>(cx)?.value(cx).max(0.0) as usize),
+        _ => None,
+    };
+    let require_signature = match cx.argument_opt(3) {
+        Some(require_signature) if !require_signature.is_a::<JsUndefined, _>(cx) => require_signature.downcast_or_throw::<JsBoolean, _>(cx)?.value(cx),
+        _ => false,
+    };
+    if route.is_empty() {
+        return cx.throw_type_error("Route names cannot be empty");
     }
+
+    let shardus_net_listener = cx.this().get::<JsBox<Arc<ShardusNetListener>>, _, _>(cx, "_listener")?;
+    let route_handlers = cx.this().get::<JsBox<RouteHandlers>, _, _>(cx, "_route_handlers")?;
+
+    // The handler is in place before the listener lets messages of the route through
+    route_handlers.handlers.lock().expect("Route handlers lock poisoned").insert(route.clone(), Arc::new(handler));
+    shardus_net_listener.routes().register(&route, RouteOptions { max_size, require_signature });
+
+    Ok(cx.undefined())
+}
+
+// unregister_route: false when route was not registered. Messages of the route are dropped from now on.
+fn unregister_route(mut cx: FunctionContext) -> JsResult<JsBoolean> {
+    let cx = &mut cx;
+    let route = cx.argument::<JsString>(0)?.value(cx);
+    let shardus_net_listener = cx.this().get::<JsBox<Arc<ShardusNetListener>>, _, _>(cx, "_listener")?;
+    let route_handlers = cx.this().get::<JsBox<RouteHandlers>, _, _>(cx, "_route_handlers")?;
+
+    let unregistered = shardus_net_listener.routes().unregister(&route);
+    route_handlers.handlers.lock().expect("Route handlers lock poisoned").remove(&route);
+
+    Ok(cx.boolean(unregistered))
+}
+
+// create_shardus_net_listener: None when the host and port do not resolve to an address
+fn create_shardus_net_listener(
+    port: f64,
+    host: String,
+    config: &SnConfig,
+    key_ring: Arc<KeyRing>,
+    cpu_pool: Arc<CpuPool>,
+    peer_stats: Arc<PeerStats>,
+    events: Arc<Events>,
+) -> Option<Arc<ShardusNetListener>> {
+    // @TODO: Verify that a javascript number properly converts here without loss.
+    let shardus_net = ShardusNetListener::new(&host, port as u16, config, key_ring, cpu_pool, peer_stats, events);
+
+    shardus_net.ok().map(Arc::new)
 }
 
-fn create_shardus_net_sender(use_lru: bool, lru_size: NonZeroUsize, key_pair: crypto::KeyPair) -> Arc<ShardusNetSender> {
+fn create_shardus_net_sender(
+    use_lru: bool,
+    lru_size: NonZeroUsize,
+    key_ring: Arc<KeyRing>,
+    cpu_pool: Arc<CpuPool>,
+    peer_stats: Arc<PeerStats>,
+    events: Arc<Events>,
+    config: &SnConfig,
+) -> Arc<ShardusNetSender> {
     let connections: Arc<Mutex<dyn ConnectionCache + Send>> = if use_lru {
         #[cfg(debug)]
         info!("Using LRU cache with size {} for socket mgmt", lru_size.get());
@@ -436,23 +823,65 @@ fn create_shardus_net_sender(use_lru: bool, lru_size: NonZeroUsize, key_pair: cr
     } else {
         #[cfg(debug)]
         info!("Using hashmap for socket mgmt");
-        Arc::new(Mutex::new(HashMap::<SocketAddr, Arc<Connection>>::new()))
+        Arc::new(Mutex::new(HashMap::<PeerAddress, Arc<Connection>>::new()))
     };
 
-    Arc::new(ShardusNetSender::new(key_pair, connections))
+    Arc::new(ShardusNetSender::new(key_ring, connections, cpu_pool, peer_stats, events, config))
 }
 
 impl Finalize for ShardusNetListener {}
 impl Finalize for ShardusNetSender {}
 impl Finalize for Stats {}
 impl Finalize for Incrementers {}
+impl Finalize for Events {}
+impl Finalize for RouteHandlers {}
+
+// RouteHandlers: the JS handler of each route registered with register_route
+#[derive(Default)]
+struct RouteHandlers {
+    handlers: StdMutex<HashMap<String, Arc<Root<JsFunction>>>>,
+}
+
+impl LogRecord {
+    fn to_object<'a>(&self, cx: &mut impl Context<'a>) -> JsResult<'a, JsObject> {
+        let obj = cx.empty_object();
+
+        let level = cx.string(self.level.as_str().to_lowercase());
+        obj.set(cx, "level", level)?;
+
+        let target = cx.string(&self.target);
+        obj.set(cx, "target", target)?;
+
+        let module: Handle<JsValue> = match &self.module {
+            Some(module) => cx.string(module).upcast(),
+            None => cx.null().upcast(),
+        };
+        obj.set(cx, "module", module)?;
+
+        let message = cx.string(&self.message);
+        obj.set(cx, "message", message)?;
+
+        Ok(obj)
+    }
+}
 
 impl StatsResult {
     fn to_object<'a>(&self, cx: &mut impl Context<'a>) -> JsResult<'a, JsObject> {
         let StatsResult {
             outstanding_sends,
             outstanding_receives,
-            receive_elapsed,
+            receive_latency,
+            send_latency,
+            cpu_pool_queue_depth,
+            cpu_pool_queue_depths,
+            cpu_pool_queue_wait,
+            cpu_pool_elapsed,
+            runtime,
+            rate_limited,
+            rate_limit_disconnects,
+            inbound_connections,
+            routes,
+            unknown_route_drops,
         } = self;
 
         let obj = cx.empty_object();
@@ -463,8 +892,206 @@ impl StatsResult {
         let outstanding_sends = outstanding_sends.to_object(cx)?;
         obj.set(cx, "outstanding_sends", outstanding_sends)?;
 
-        let receive_elapsed = receive_elapsed.to_object(cx)?;
-        obj.set(cx, "receive_elapsed", receive_elapsed)?;
+        let receive_latency = latency_object(cx, receive_latency)?;
+        obj.set(cx, "receive_latency_us", receive_latency)?;
+
+        let send_latency = latency_object(cx, send_latency)?;
+        obj.set(cx, "send_latency_us", send_latency)?;
+
+        let cpu_pool_queue_depth = cx.number(*cpu_pool_queue_depth as f64);
+        obj.set(cx, "cpu_pool_queue_depth", cpu_pool_queue_depth)?;
+
+        let cpu_pool_queue_depths = cpu_pool_queue_depths.to_object(cx)?;
+        obj.set(cx, "cpu_pool_queue_depths", cpu_pool_queue_depths)?;
+
+        let cpu_pool_queue_wait = latency_object(cx, cpu_pool_queue_wait)?;
+        obj.set(cx, "cpu_pool_queue_wait_us", cpu_pool_queue_wait)?;
+
+        let cpu_pool_elapsed = latency_object(cx, cpu_pool_elapsed)?;
+        obj.set(cx, "cpu_pool_elapsed_us", cpu_pool_elapsed)?;
+
+        let runtime = runtime.to_object(cx)?;
+        obj.set(cx, "runtime", runtime)?;
+
+        let rate_limited_obj = cx.empty_object();
+        for (limit, count) in rate_limited {
+            let count = cx.number(*count as f64);
+            rate_limited_obj.set(cx, *limit, count)?;
+        }
+        let rate_limit_disconnects = cx.number(*rate_limit_disconnects as f64);
+        rate_limited_obj.set(cx, "disconnects", rate_limit_disconnects)?;
+        obj.set(cx, "rate_limited", rate_limited_obj)?;
+
+        let inbound_connections = inbound_connections.to_object(cx)?;
+        obj.set(cx, "inbound_connections", inbound_connections)?;
+
+        let routes = route_stats_array(cx, routes)?;
+        obj.set(cx, "routes", routes)?;
+
+        let unknown_route_drops = cx.number(*unknown_route_drops as f64);
+        obj.set(cx, "unknown_route_drops", unknown_route_drops)?;
+
+        Ok(obj)
+    }
+}
+
+impl PeerCounters {
+    fn to_object<'a>(&self, cx: &mut impl Context<'a>, address: &PeerAddress) -> JsResult<'a, JsObject> {
+        let obj = cx.empty_object();
+
+        let host = cx.string(address.host());
+        obj.set(cx, "address", host)?;
+
+        let port = cx.number(address.port() as f64);
+        obj.set(cx, "port", port)?;
+
+        let bytes_sent = cx.number(self.bytes_sent as f64);
+        obj.set(cx, "bytes_sent", bytes_sent)?;
+
+        let bytes_received = cx.number(self.bytes_received as f64);
+        obj.set(cx, "bytes_received", bytes_received)?;
+
+        let messages_sent = cx.number(self.messages_sent as f64);
+        obj.set(cx, "messages_sent", messages_sent)?;
+
+        let messages_received = cx.number(self.messages_received as f64);
+        obj.set(cx, "messages_received", messages_received)?;
+
+        let send_errors = cx.empty_object();
+        for (kind, count) in &self.send_errors {
+            let count = cx.number(*count as f64);
+            send_errors.set(cx, *kind, count)?;
+        }
+        obj.set(cx, "send_errors", send_errors)?;
+
+        let connects = cx.number(self.connects as f64);
+        obj.set(cx, "connects", connects)?;
+
+        let reconnects = cx.number(self.reconnects as f64);
+        obj.set(cx, "reconnects", reconnects)?;
+
+        let average_send_latency = cx.number(self.average_send_latency().as_micros() as f64);
+        obj.set(cx, "avg_send_latency_us", average_send_latency)?;
+
+        let last_activity = cx.number(self.last_activity_ms() as f64);
+        obj.set(cx, "last_activity_ms", last_activity)?;
+
+        Ok(obj)
+    }
+}
+
+impl RouteStatsSnapshot {
+    fn to_object<'a>(&self, cx: &mut impl Context<'a>) -> JsResult<'a, JsObject> {
+        let RouteStatsSnapshot { route, counters, callback_latency } = self;
+        let obj = cx.empty_object();
+
+        let route = cx.string(route);
+        obj.set(cx, "route", route)?;
+
+        for (name, value) in [
+            ("messages_received", counters.messages_received),
+            ("bytes_received", counters.bytes_received),
+            ("messages_sent", counters.messages_sent),
+            ("bytes_sent", counters.bytes_sent),
+            ("dropped", counters.dropped),
+            ("send_errors", counters.send_errors),
+        ] {
+            let value = cx.number(value as f64);
+            obj.set(cx, name, value)?;
+        }
+
+        let callback_latency = latency_object(cx, callback_latency)?;
+        obj.set(cx, "callback_latency_us", callback_latency)?;
+
+        Ok(obj)
+    }
+}
+
+fn route_stats_array<'a>(cx: &mut impl Context<'a>, routes: &[RouteStatsSnapshot]) -> JsResult<'a, JsArray> {
+    let array = cx.empty_array();
+    for (i, route) in routes.iter().enumerate() {
+        let obj = route.to_object(cx)?;
+        array.set(cx, i as u32, obj)?;
+    }
+
+    Ok(array)
+}
+
+impl ConnectionEvent {
+    fn to_object<'a>(&self, cx: &mut impl Context<'a>) -> JsResult<'a, JsObject> {
+        let obj = cx.empty_object();
+
+        let kind = cx.string(self.kind.name());
+        obj.set(cx, "type", kind)?;
+
+        if let Some(direction) = self.direction {
+            let direction = cx.string(direction.name());
+            obj.set(cx, "direction", direction)?;
+        }
+
+        let transport = cx.string(self.transport_name());
+        obj.set(cx, "transport", transport)?;
+
+        let host = cx.string(self.address.host());
+        obj.set(cx, "address", host)?;
+
+        let port = cx.number(self.address.port() as f64);
+        obj.set(cx, "port", port)?;
+
+        if let Some(reason) = &self.reason {
+            let reason = cx.string(reason);
+            obj.set(cx, "reason", reason)?;
+        }
+
+        let timestamp = self.timestamp.duration_since(UNIX_EPOCH).map_or(0, |since| since.as_millis() as u64);
+        let timestamp = cx.number(timestamp as f64);
+        obj.set(cx, "timestamp_ms", timestamp)?;
+
+        Ok(obj)
+    }
+}
+
+impl InboundConnectionStats {
+    fn to_object<'a>(&self, cx: &mut impl Context<'a>) -> JsResult<'a, JsObject> {
+        let obj = cx.empty_object();
+
+        let open = cx.number(self.open as f64);
+        obj.set(cx, "open", open)?;
+
+        let ips = cx.number(self.ips as f64);
+        obj.set(cx, "ips", ips)?;
+
+        let filtered = cx.number(self.filtered as f64);
+        obj.set(cx, "filtered", filtered)?;
+
+        let refused = cx.number(self.refused as f64);
+        obj.set(cx, "refused", refused)?;
+
+        let evicted = cx.number(self.evicted as f64);
+        obj.set(cx, "evicted", evicted)?;
+
+        let idle_timeouts = cx.number(self.idle_timeouts as f64);
+        obj.set(cx, "idle_timeouts", idle_timeouts)?;
+
+        let frame_timeouts = cx.number(self.frame_timeouts as f64);
+        obj.set(cx, "frame_timeouts", frame_timeouts)?;
+
+        Ok(obj)
+    }
+}
+
+impl RuntimeStats {
+    fn to_object<'a>(&self, cx: &mut impl Context<'a>) -> JsResult<'a, JsObject> {
+        let obj = cx.empty_object();
+
+        let workers = cx.number(self.workers as f64);
+        obj.set(cx, "workers", workers)?;
+
+        let alive_tasks = cx.number(self.alive_tasks as f64);
+        obj.set(cx, "alive_tasks", alive_tasks)?;
+
+        let global_queue_depth = cx.number(self.global_queue_depth as f64);
+        obj.set(cx, "global_queue_depth", global_queue_depth)?;
 
         Ok(obj)
     }
@@ -484,17 +1111,47 @@ impl RingBufferStats<usize> {
     }
 }
 
-impl RingBufferStats<Duration> {
+// latency_object: one percentile summary (microseconds) per window, keyed by the window label
+fn latency_object<'a>(cx: &mut impl Context<'a>, windows: &[(String, HistogramSummary)]) -> JsResult<'a, JsObject> {
+    let obj = cx.empty_object();
+
+    for (label, summary) in windows {
+        let window = summary.to_object(cx)?;
+        obj.set(cx, label.as_str(), window)?;
+    }
+
+    Ok(obj)
+}
+
+impl HistogramSummary {
     fn to_object<'a>(&self, cx: &mut impl Context<'a>) -> JsResult<'a, JsObject> {
-        to_stats_object(
-            cx,
-            self.long_term_max.as_millis() as f64,
-            self.long_term_min.as_millis() as f64,
-            self.min.as_millis() as f64,
-            self.max.as_millis() as f64,
-            self.total.as_millis() as f64,
-            self.count,
-        )
+        let obj = cx.empty_object();
+
+        let count = cx.number(self.count as f64);
+        obj.set(cx, "count", count)?;
+
+        let min = cx.number(self.min as f64);
+        obj.set(cx, "min", min)?;
+
+        let max = cx.number(self.max as f64);
+        obj.set(cx, "max", max)?;
+
+        let mean = cx.number(self.mean);
+        obj.set(cx, "mean", mean)?;
+
+        let p50 = cx.number(self.p50 as f64);
+        obj.set(cx, "p50", p50)?;
+
+        let p90 = cx.number(self.p90 as f64);
+        obj.set(cx, "p90", p90)?;
+
+        let p99 = cx.number(self.p99 as f64);
+        obj.set(cx, "p99", p99)?;
+
+        let p999 = cx.number(self.p999 as f64);
+        obj.set(cx, "p999", p999)?;
+
+        Ok(obj)
     }
 }
 
@@ -530,13 +1187,96 @@ fn set_logging_enabled(mut cx: FunctionContext) -> JsResult<JsUndefined> {
     let enabled = cx.argument::<JsBoolean>(0)?.value(&mut cx);
 
     if enabled {
-        log::set_max_level(log::LevelFilter::Info);
+        log::set_max_level(logger::level());
     } else {
         log::set_max_level(log::LevelFilter::Off);
     }
     Ok(cx.undefined())
 }
 
+// set_logger: forward Rust log records at or above level to callback, at most max_per_second a second
+fn set_logger(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let cx = &mut cx;
+    let callback = Arc::new(cx.argument::<JsFunction>(0)?.root(cx));
+    let level = cx.argument::<JsString>(1)?.value(cx);
+    let level = match level.parse::<log::LevelFilter>() {
+        Ok(level) => level,
+        Err(_) => return cx.throw_error(format!("Unknown log level {}", level)),
+    };
+    let max_per_second = match cx.argument_opt(2) {
+        Some(max_per_second) if !max_per_second.is_a::<JsUndefined, _>(cx) => max_per_second.downcast_or_throw::<JsNumber, _>(cx)?.value(cx) as u32,
+        _ => logger::DEFAULT_MAX_RECORDS_PER_SECOND,
+    };
+
+    // Logging alone must not keep the process alive
+    let mut channel = cx.channel();
+    channel.unref(cx);
+
+    logger::set_logger(
+        level,
+        max_per_second,
+        Box::new(move |record: LogRecord| {
+            let callback = callback.clone();
+
+            channel.send(move |mut cx| {
+                let cx = &mut cx;
+                let this = cx.undefined();
+                let record = record.to_object(cx)?;
+                callback.to_inner(cx).call(cx, this, [record.upcast::<JsValue>()])?;
+
+                Ok(())
+            });
+        }),
+    );
+
+    Ok(cx.undefined())
+}
+
+fn configure_runtime(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let cx = &mut cx;
+    let opts = cx.argument::<JsObject>(0)?;
+
+    let mut config = RuntimeConfig::default();
+    if let Some(worker_threads) = opts.get_opt::<JsNumber, _, _>(cx, "worker_threads")? {
+        config.worker_threads = Some(worker_threads.value(cx) as usize);
+    }
+    if let Some(max_blocking_threads) = opts.get_opt::<JsNumber, _, _>(cx, "max_blocking_threads")? {
+        config.max_blocking_threads = Some(max_blocking_threads.value(cx) as usize);
+    }
+    if let Some(thread_name_prefix) = opts.get_opt::<JsString, _, _>(cx, "thread_name_prefix")? {
+        config.thread_name_prefix = Some(thread_name_prefix.value(cx));
+    }
+    if let Some(current_thread) = opts.get_opt::<JsBoolean, _, _>(cx, "current_thread")? {
+        config.current_thread = current_thread.value(cx);
+    }
+
+    match runtime::configure_runtime(config) {
+        Ok(_) => Ok(cx.undefined()),
+        Err(err) => cx.throw_error(err.to_string()),
+    }
+}
+
+fn configure_tracing(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let cx = &mut cx;
+    let opts = cx.argument::<JsObject>(0)?;
+
+    let mut config = TracingConfig::default();
+    if let Some(json_file) = opts.get_opt::<JsString, _, _>(cx, "json_file")? {
+        config.json_file = Some(json_file.value(cx).into());
+    }
+    if let Some(otlp_endpoint) = opts.get_opt::<JsString, _, _>(cx, "otlp_endpoint")? {
+        config.otlp_endpoint = Some(otlp_endpoint.value(cx));
+    }
+    if let Some(service_name) = opts.get_opt::<JsString, _, _>(cx, "service_name")? {
+        config.service_name = Some(service_name.value(cx));
+    }
+
+    match telemetry::configure_tracing(config) {
+        Ok(_) => Ok(cx.undefined()),
+        Err(err) => cx.throw_error(err.to_string()),
+    }
+}
+
 fn get_sender_address(mut cx: FunctionContext) -> JsResult<JsObject> {
     let cx = &mut cx;
     let raw_tx = cx.argument::<JsString>(0)?.value(cx);
@@ -581,7 +1321,13 @@ fn main(mut cx: ModuleContext) -> NeonResult<()> {
 
     cx.export_function("setLoggingEnabled", set_logging_enabled)?;
 
+    cx.export_function("setLogger", set_logger)?;
+
     cx.export_function("getSenderAddress", get_sender_address)?;
 
+    cx.export_function("configureRuntime", configure_runtime)?;
+
+    cx.export_function("configureTracing", configure_tracing)?;
+
     Ok(())
 }
@@ -0,0 +1,135 @@
+use crate::token_bucket::TokenBucket;
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use once_cell::sync::Lazy;
+use std::sync::{Mutex, Once, RwLock};
+use std::time::Instant;
+
+pub(crate) const DEFAULT_MAX_RECORDS_PER_SECOND: u32 = 200;
+
+/// A log record as handed to JS.
+#[derive(Clone, Debug)]
+pub(crate) struct LogRecord {
+    pub level: Level,
+    pub target: String,
+    pub module: Option<String>,
+    pub message: String,
+}
+
+pub(crate) type ForwardRecord = Box<dyn Fn(LogRecord) + Send + Sync>;
+
+/// The `log` backend of the crate. Records are dropped until `set_logger` gives it somewhere to
+/// forward them to.
+struct JsLogger {
+    sink: RwLock<Option<Sink>>,
+}
+
+struct Sink {
+    level: LevelFilter,
+    limiter: Mutex<RateLimiter>,
+    forward: ForwardRecord,
+}
+
+// RateLimiter: lets through max_per_second records a second, with bursts of the same size,
+// and counts what it drops so the next record through can report it
+struct RateLimiter {
+    bucket: TokenBucket,
+    dropped: u64,
+}
+
+impl RateLimiter {
+    fn new(max_per_second: u32) -> Self {
+        let max_per_second = max_per_second.max(1) as f64;
+
+        Self {
+            bucket: TokenBucket::new(max_per_second, max_per_second, Instant::now()),
+            dropped: 0,
+        }
+    }
+
+    // admit: Some(records dropped since the last admitted one), or None when this one is dropped too
+    fn admit(&mut self, now: Instant) -> Option<u64> {
+        if !self.bucket.try_take(1.0, now) {
+            self.dropped += 1;
+            return None;
+        }
+
+        Some(std::mem::take(&mut self.dropped))
+    }
+}
+
+static LOGGER: Lazy<JsLogger> = Lazy::new(|| JsLogger { sink: RwLock::new(None) });
+static INSTALL: Once = Once::new();
+
+// set_logger: forward records at or above level to forward, replacing any previous destination
+pub(crate) fn set_logger(level: LevelFilter, max_per_second: u32, forward: ForwardRecord) {
+    INSTALL.call_once(|| log::set_logger(&*LOGGER).expect("Another logger has already been installed"));
+
+    *LOGGER.sink.write().expect("Logger lock poisoned") = Some(Sink {
+        level,
+        limiter: Mutex::new(RateLimiter::new(max_per_second)),
+        forward,
+    });
+    log::set_max_level(level);
+}
+
+// level: what setLoggingEnabled(true) turns logging back on to, Info until a logger is set
+pub(crate) fn level() -> LevelFilter {
+    LOGGER.sink.read().expect("Logger lock poisoned").as_ref().map_or(LevelFilter::Info, |sink| sink.level)
+}
+
+impl Log for JsLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.sink.read().expect("Logger lock poisoned").as_ref().is_some_and(|sink| metadata.level() <= sink.level)
+    }
+
+    fn log(&self, record: &Record) {
+        let sink = self.sink.read().expect("Logger lock poisoned");
+        let sink = match sink.as_ref() {
+            Some(sink) if record.level() <= sink.level => sink,
+            _ => return,
+        };
+
+        let dropped = match sink.limiter.lock().expect("Logger lock poisoned").admit(Instant::now()) {
+            Some(dropped) => dropped,
+            None => return,
+        };
+        if dropped > 0 {
+            (sink.forward)(LogRecord {
+                level: Level::Warn,
+                target: module_path!().to_string(),
+                module: Some(module_path!().to_string()),
+                message: format!("{} log records were dropped by the rate limit", dropped),
+            });
+        }
+
+        (sink.forward)(LogRecord {
+            level: record.level(),
+            target: record.target().to_string(),
+            module: record.module_path().map(str::to_string),
+            message: record.args().to_string(),
+        });
+    }
+
+    fn flush(&self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_rate_limiter_reports_drops() {
+        let start = Instant::now();
+        let mut limiter = RateLimiter::new(2);
+
+        assert_eq!(limiter.admit(start), Some(0));
+        assert_eq!(limiter.admit(start), Some(0));
+        assert_eq!(limiter.admit(start), None);
+        assert_eq!(limiter.admit(start), None);
+
+        assert_eq!(limiter.admit(start + Duration::from_millis(500)), Some(2));
+        assert_eq!(limiter.admit(start + Duration::from_millis(500)), None);
+    }
+}
@@ -46,7 +46,39 @@ impl Message {
         let unsigned = self.serialize_unsigned();
         let hash = crypto.hash(&unsigned, Buffer);
         let owner = self.sign.owner.clone();
-        crypto.verify(&hash, &self.sign.sig, &crypto.get_pk(&crypto::HexStringOrBuffer::Buffer(owner)))
+        match crypto.try_get_pk(&crypto::HexStringOrBuffer::Buffer(owner)) {
+            Some(owner) => crypto.verify(&hash, &self.sign.sig, &owner),
+            None => false,
+        }
+    }
+
+    // sign_batch: sign distinct messages with the same key pair in a single pass
+    pub fn sign_batch(messages: &mut [Message], crypto: &ShardusCrypto, key_pair: &KeyPair) {
+        let hashes = messages.iter().map(|message| crypto.hash(&message.serialize_unsigned(), Buffer)).collect();
+        let signatures = crypto.sign_batch(hashes, &key_pair.secret_key).expect("Failed to sign messages");
+        for (message, signature) in messages.iter_mut().zip(signatures) {
+            message.sign = Sign::new(key_pair.public_key.0.to_vec(), signature);
+        }
+    }
+
+    // verify_batch: true only if every message carries a valid signature. Owners that are not valid
+    // public keys fail the whole batch, callers fall back to verify to single out the bad messages.
+    pub fn verify_batch(messages: &[&Message], crypto: &ShardusCrypto) -> bool {
+        let mut owners = Vec::with_capacity(messages.len());
+        for message in messages {
+            match crypto.try_get_pk(&crypto::HexStringOrBuffer::Buffer(message.sign.owner.clone())) {
+                Some(owner) => owners.push(owner),
+                None => return false,
+            }
+        }
+
+        let entries: Vec<_> = messages
+            .iter()
+            .zip(owners.iter())
+            .map(|(message, owner)| (crypto.hash(&message.serialize_unsigned(), Buffer), &message.sign.sig, owner))
+            .collect();
+
+        crypto.verify_batch(&entries)
     }
 
     pub fn serialize_unsigned(&self) -> Vec<u8> {
@@ -90,20 +122,10 @@ impl Message {
         let header_version = u8::from_le_bytes(header_version_bytes);
 
         // Deserialize header
-        let mut header_len_bytes = [0u8; 4];
-        cursor.read_exact(&mut header_len_bytes).ok()?;
-        let header_len = u32::from_le_bytes(header_len_bytes);
-        let mut header_bytes = vec![0u8; header_len as usize];
-        cursor.read_exact(&mut header_bytes).ok()?;
-        let header = header_bytes;
+        let header = read_length_prefixed(cursor)?;
 
         // Deserialize data
-        let mut data_len_bytes = [0u8; 4];
-        cursor.read_exact(&mut data_len_bytes).ok()?;
-        let data_len = u32::from_le_bytes(data_len_bytes);
-        let mut data_bytes = vec![0u8; data_len as usize];
-        cursor.read_exact(&mut data_bytes).ok()?;
-        let data = data_bytes;
+        let data = read_length_prefixed(cursor)?;
 
         // Deserialize sign
         let sign = Sign::deserialize(cursor)?;
@@ -137,20 +159,10 @@ impl Sign {
 
     pub fn deserialize(cursor: &mut Cursor<Vec<u8>>) -> Option<Sign> {
         // Deserialize owner
-        let mut owner_len_bytes = [0u8; 4];
-        cursor.read_exact(&mut owner_len_bytes).ok()?;
-        let owner_len = u32::from_le_bytes(owner_len_bytes);
-        let mut owner_bytes = vec![0u8; owner_len as usize];
-        cursor.read_exact(&mut owner_bytes).ok()?;
-        let owner = owner_bytes;
+        let owner = read_length_prefixed(cursor)?;
 
         // Deserialize signature
-        let mut signature_len_bytes = [0u8; 4];
-        cursor.read_exact(&mut signature_len_bytes).ok()?;
-        let signature_len = u32::from_le_bytes(signature_len_bytes);
-        let mut signature_bytes = vec![0u8; signature_len as usize];
-        cursor.read_exact(&mut signature_bytes).ok()?;
-        let signature = signature_bytes;
+        let signature = read_length_prefixed(cursor)?;
 
         Some(Sign::new(owner, signature))
     }
@@ -163,6 +175,21 @@ impl Sign {
     }
 }
 
+// read_length_prefixed: read a u32 length and that many bytes, None when the cursor holds fewer so that a
+// garbage length is never allocated
+fn read_length_prefixed(cursor: &mut Cursor<Vec<u8>>) -> Option<Vec<u8>> {
+    let mut len_bytes = [0u8; 4];
+    cursor.read_exact(&mut len_bytes).ok()?;
+    let len = u32::from_le_bytes(len_bytes) as u64;
+    if len > cursor.get_ref().len() as u64 - cursor.position() {
+        return None;
+    }
+
+    let mut bytes = vec![0u8; len as usize];
+    cursor.read_exact(&mut bytes).ok()?;
+    Some(bytes)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -178,4 +205,18 @@ mod tests {
 
         assert_eq!(sign.to_json_string(), expected_json_string);
     }
+
+    #[test]
+    fn test_deserialize_truncated() {
+        let message = Message::new(1, b"header".to_vec(), b"data".to_vec(), Sign::new(vec![1; 32], vec![2; 64]));
+        let bytes = message.serialize();
+        assert!(Message::deserialize(&mut Cursor::new(bytes.clone())).is_some());
+
+        for len in [0, 1, 5, 20, bytes.len() - 1] {
+            assert!(Message::deserialize(&mut Cursor::new(bytes[..len].to_vec())).is_none());
+        }
+
+        // A garbage length is refused without allocating it
+        assert!(Message::deserialize(&mut Cursor::new(vec![1, 0xff, 0xff, 0xff, 0xff])).is_none());
+    }
 }
@@ -0,0 +1,405 @@
+use super::histogram::HistogramSummary;
+use super::peer_stats::{PeerCounters, PeerStatsOrder};
+use super::routes::RouteCounters;
+use super::stats::Stats;
+
+use std::fmt::{self, Write};
+use std::io;
+use std::net::{Ipv4Addr, TcpListener as StdTcpListener};
+use std::sync::{Mutex, Weak};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+pub(crate) const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+// A scrape request is a request line and a few headers, anything larger or slower is dropped
+const MAX_REQUEST_SIZE: usize = 8 * 1024;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+// How often an idle endpoint checks whether the Sn owning its stats is gone, releasing the port then
+const OWNER_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+type PeerCounter = fn(&PeerCounters) -> u64;
+type RouteCounter = fn(&RouteCounters) -> u64;
+
+/// Writes metric families in the OpenMetrics text format.
+#[derive(Default)]
+struct MetricsWriter {
+    out: String,
+}
+
+impl MetricsWriter {
+    fn family(&mut self, name: &str, kind: &str, help: &str) {
+        writeln!(self.out, "# TYPE {} {}", name, kind).unwrap();
+        writeln!(self.out, "# HELP {} {}", name, escape(help, false)).unwrap();
+    }
+
+    fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: impl fmt::Display) {
+        self.out.push_str(name);
+        if !labels.is_empty() {
+            let labels: Vec<String> = labels.iter().map(|(label, value)| format!("{}=\"{}\"", label, escape(value, true))).collect();
+            write!(self.out, "{{{}}}", labels.join(",")).unwrap();
+        }
+        writeln!(self.out, " {}", value).unwrap();
+    }
+
+    fn gauge(&mut self, name: &str, help: &str, value: impl fmt::Display) {
+        self.family(name, "gauge", help);
+        self.sample(name, &[], value);
+    }
+
+    // summary: latency percentiles in seconds, one set of quantiles per window label
+    fn summary(&mut self, name: &str, help: &str, windows: &[(String, HistogramSummary)]) {
+        self.family(name, "summary", help);
+        self.summary_samples(name, &[], windows);
+    }
+
+    // summary_samples: the samples of one labelled member of a summary family
+    fn summary_samples(&mut self, name: &str, labels: &[(&str, &str)], windows: &[(String, HistogramSummary)]) {
+        for (window, summary) in windows {
+            let window_labels: Vec<(&str, &str)> = labels.iter().copied().chain([("window", window.as_str())]).collect();
+            for (quantile, value) in [("0.5", summary.p50), ("0.9", summary.p90), ("0.99", summary.p99), ("0.999", summary.p999)] {
+                let quantile_labels: Vec<(&str, &str)> = window_labels.iter().copied().chain([("quantile", quantile)]).collect();
+                self.sample(name, &quantile_labels, seconds(value));
+            }
+            self.sample(&format!("{}_sum", name), &window_labels, summary.mean * summary.count as f64 / 1_000_000.0);
+            self.sample(&format!("{}_count", name), &window_labels, summary.count);
+        }
+    }
+
+    fn finish(mut self) -> String {
+        self.out.push_str("# EOF\n");
+        self.out
+    }
+}
+
+fn seconds(micros: u64) -> f64 {
+    micros as f64 / 1_000_000.0
+}
+
+// escape: label values escape quotes as well, help text only backslashes and line feeds
+fn escape(value: &str, quotes: bool) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '"' if quotes => escaped.push_str("\\\""),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// Renders every counter, gauge and latency summary of `stats`. Metric names and labels are stable
+/// so dashboards and alerts can rely on them.
+pub(crate) fn render(stats: &mut Stats) -> String {
+    let (outstanding_sends, outstanding_receives) = stats.outstanding();
+    let send_errors = stats.send_errors();
+    let mut peers = stats.get_peer_stats(None, PeerStatsOrder::LastActivity);
+    peers.sort_by_key(|(address, _)| address.to_string());
+    let stats = stats.get_stats();
+
+    let mut metrics = MetricsWriter::default();
+
+    metrics.gauge("shardus_net_outstanding_sends", "Sends waiting for their transport to complete.", outstanding_sends);
+    metrics.gauge("shardus_net_outstanding_receives", "Received messages waiting for the listen callback.", outstanding_receives);
+
+    metrics.summary(
+        "shardus_net_receive_latency_seconds",
+        "Time from receiving a message until the listen callback runs.",
+        &stats.receive_latency,
+    );
+    metrics.summary("shardus_net_send_latency_seconds", "Time from a send call until its transport completes.", &stats.send_latency);
+
+    metrics.gauge("shardus_net_cpu_pool_queue_depth", "Jobs waiting for a CPU pool thread.", stats.cpu_pool_queue_depth);
+    metrics.summary("shardus_net_cpu_pool_queue_wait_seconds", "Time a job waits for a CPU pool thread.", &stats.cpu_pool_queue_wait);
+    metrics.summary("shardus_net_cpu_pool_run_seconds", "Time a job runs on a CPU pool thread.", &stats.cpu_pool_elapsed);
+
+    metrics.gauge("shardus_net_runtime_workers", "Worker threads of the runtime.", stats.runtime.workers);
+    metrics.gauge("shardus_net_runtime_alive_tasks", "Tasks alive on the runtime.", stats.runtime.alive_tasks);
+    metrics.gauge(
+        "shardus_net_runtime_global_queue_depth",
+        "Tasks waiting in the global queue of the runtime.",
+        stats.runtime.global_queue_depth,
+    );
+
+    metrics.gauge("shardus_net_inbound_connections", "Open inbound connections.", stats.inbound_connections.open);
+    metrics.gauge("shardus_net_inbound_connection_ips", "Remote IPs with an open inbound connection.", stats.inbound_connections.ips);
+    metrics.family("shardus_net_inbound_connections_filtered", "counter", "Inbound connections refused by the IP allow and deny lists.");
+    metrics.sample("shardus_net_inbound_connections_filtered_total", &[], stats.inbound_connections.filtered);
+    metrics.family("shardus_net_inbound_connections_refused", "counter", "Inbound connections refused over the connection limits.");
+    metrics.sample("shardus_net_inbound_connections_refused_total", &[], stats.inbound_connections.refused);
+    metrics.family("shardus_net_inbound_connections_evicted", "counter", "Idle inbound connections closed to admit new ones.");
+    metrics.sample("shardus_net_inbound_connections_evicted_total", &[], stats.inbound_connections.evicted);
+    metrics.family("shardus_net_inbound_connection_timeouts", "counter", "Inbound connections closed for stalling, by timeout.");
+    metrics.sample("shardus_net_inbound_connection_timeouts_total", &[("timeout", "idle")], stats.inbound_connections.idle_timeouts);
+    metrics.sample("shardus_net_inbound_connection_timeouts_total", &[("timeout", "frame")], stats.inbound_connections.frame_timeouts);
+
+    metrics.family("shardus_net_rate_limited_frames", "counter", "Inbound frames dropped by a rate limit, by limit.");
+    for (limit, count) in &stats.rate_limited {
+        metrics.sample("shardus_net_rate_limited_frames_total", &[("limit", limit)], count);
+    }
+    metrics.family("shardus_net_rate_limit_disconnects", "counter", "Connections closed for going over a rate limit.");
+    metrics.sample("shardus_net_rate_limit_disconnects_total", &[], stats.rate_limit_disconnects);
+
+    metrics.family("shardus_net_send_errors", "counter", "Failed sends by kind.");
+    for (kind, count) in &send_errors {
+        metrics.sample("shardus_net_send_errors_total", &[("kind", kind)], count);
+    }
+
+    let peer_counters: [(&str, &str, PeerCounter); 6] = [
+        ("shardus_net_peer_sent_bytes", "Bytes sent to a peer.", |counters| counters.bytes_sent),
+        ("shardus_net_peer_received_bytes", "Bytes received from a peer.", |counters| counters.bytes_received),
+        ("shardus_net_peer_sent_messages", "Messages sent to a peer.", |counters| counters.messages_sent),
+        ("shardus_net_peer_received_messages", "Messages received from a peer.", |counters| counters.messages_received),
+        ("shardus_net_peer_connects", "Connections opened to a peer.", |counters| counters.connects),
+        ("shardus_net_peer_reconnects", "Connections to a peer replaced after a failed write.", |counters| counters.reconnects),
+    ];
+    for (name, help, counter) in peer_counters {
+        metrics.family(name, "counter", help);
+        let total = format!("{}_total", name);
+        for (address, counters) in &peers {
+            metrics.sample(&total, &[("peer", &address.to_string())], counter(counters));
+        }
+    }
+
+    metrics.family("shardus_net_peer_send_errors", "counter", "Failed sends to a peer by kind.");
+    for (address, counters) in &peers {
+        let peer = address.to_string();
+        for (kind, count) in &counters.send_errors {
+            metrics.sample("shardus_net_peer_send_errors_total", &[("peer", &peer), ("kind", kind)], count);
+        }
+    }
+
+    metrics.family("shardus_net_peer_send_latency_seconds", "gauge", "Average time to write a frame to a peer.");
+    for (address, counters) in &peers {
+        metrics.sample(
+            "shardus_net_peer_send_latency_seconds",
+            &[("peer", &address.to_string())],
+            counters.average_send_latency().as_secs_f64(),
+        );
+    }
+
+    metrics.family("shardus_net_peer_last_activity_timestamp_seconds", "gauge", "Unix time of the last send or receive with a peer.");
+    for (address, counters) in &peers {
+        metrics.sample(
+            "shardus_net_peer_last_activity_timestamp_seconds",
+            &[("peer", &address.to_string())],
+            counters.last_activity_ms() as f64 / 1000.0,
+        );
+    }
+
+    let route_counters: [(&str, &str, RouteCounter); 6] = [
+        ("shardus_net_route_sent_bytes", "Payload bytes sent on a route.", |counters| counters.bytes_sent),
+        ("shardus_net_route_received_bytes", "Payload bytes received on a route.", |counters| counters.bytes_received),
+        ("shardus_net_route_sent_messages", "Messages sent on a route.", |counters| counters.messages_sent),
+        ("shardus_net_route_received_messages", "Messages received on a route.", |counters| counters.messages_received),
+        ("shardus_net_route_dropped_messages", "Received messages dropped by the checks of a route.", |counters| counters.dropped),
+        ("shardus_net_route_send_errors", "Failed sends on a route.", |counters| counters.send_errors),
+    ];
+    for (name, help, counter) in route_counters {
+        metrics.family(name, "counter", help);
+        let total = format!("{}_total", name);
+        for route in &stats.routes {
+            metrics.sample(&total, &[("route", &route.route)], counter(&route.counters));
+        }
+    }
+    metrics.family("shardus_net_unknown_route_messages", "counter", "Received messages dropped for naming a route that is not registered.");
+    metrics.sample("shardus_net_unknown_route_messages_total", &[], stats.unknown_route_drops);
+
+    metrics.family(
+        "shardus_net_route_callback_latency_seconds",
+        "summary",
+        "Time from receiving a message on a route until its handler runs.",
+    );
+    for route in &stats.routes {
+        metrics.summary_samples("shardus_net_route_callback_latency_seconds", &[("route", &route.route)], &route.callback_latency);
+    }
+
+    metrics.finish()
+}
+
+// bind: the metrics endpoint is only reachable from the local host
+pub(crate) fn bind(port: u16) -> io::Result<StdTcpListener> {
+    let listener = StdTcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+    listener.set_nonblocking(true)?;
+
+    Ok(listener)
+}
+
+// serve: answer GET /metrics on the listener until the Sn owning the stats is dropped
+pub(crate) async fn serve(listener: StdTcpListener, stats: Weak<Mutex<Stats>>) {
+    let listener = match TcpListener::from_std(listener) {
+        Ok(listener) => listener,
+        Err(_) => return,
+    };
+
+    loop {
+        if stats.strong_count() == 0 {
+            return;
+        }
+
+        let socket = match tokio::time::timeout(OWNER_CHECK_INTERVAL, listener.accept()).await {
+            Ok(Ok((socket, _))) => socket,
+            Err(_) => continue,
+            Ok(Err(_)) => {
+                // Out of file descriptors and the like, back off instead of spinning
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+
+        let stats = match stats.upgrade() {
+            Some(stats) => stats,
+            None => return,
+        };
+
+        tokio::spawn(async move { respond(socket, &stats).await.ok() });
+    }
+}
+
+async fn respond(mut socket: TcpStream, stats: &Mutex<Stats>) -> io::Result<()> {
+    let request = tokio::time::timeout(REQUEST_TIMEOUT, read_request(&mut socket))
+        .await
+        .map_err(|_| io::Error::from(io::ErrorKind::TimedOut))??;
+
+    let request_line = request.lines().next().unwrap_or_default();
+    let mut parts = request_line.split_whitespace();
+    let (method, target) = (parts.next(), parts.next().map(|target| target.split('?').next().unwrap_or_default()));
+
+    let (status, content_type, body) = match (method, target) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", CONTENT_TYPE, render(&mut stats.lock().expect("Stats lock poisoned"))),
+        _ => ("404 Not Found", "text/plain; charset=utf-8", "Not Found\n".to_string()),
+    };
+
+    let head = format!("HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", status, content_type, body.len());
+    socket.write_all(head.as_bytes()).await?;
+    socket.write_all(body.as_bytes()).await?;
+    socket.shutdown().await
+}
+
+// read_request: everything up to the blank line ending the headers
+async fn read_request(socket: &mut TcpStream) -> io::Result<String> {
+    let mut request = Vec::new();
+    let mut buffer = [0u8; 1024];
+
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+        if request.len() > MAX_REQUEST_SIZE {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Metrics request is too large"));
+        }
+
+        let read = socket.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        request.extend_from_slice(&buffer[..read]);
+    }
+
+    Ok(String::from_utf8_lossy(&request).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cidr::IpFilter;
+    use crate::connection_limit::{ConnectionLimits, InboundConnections};
+    use crate::cpu_pool::CpuPool;
+    use crate::peer_stats::PeerStats;
+    use crate::rate_limit::{Exceeded, RateLimiter, RateLimits};
+    use crate::routes::RouteTable;
+    use crate::runtime::RUNTIME;
+    use crate::shardus_net_sender::SenderError;
+    use crate::transport::PeerAddress;
+    use std::io::{Read, Write as IoWrite};
+    use std::sync::Arc;
+
+    fn stats() -> Stats {
+        let peer_stats = Arc::new(PeerStats::new());
+        let peer = PeerAddress::Ip(([127, 0, 0, 1], 9001).into());
+        peer_stats.record_send(&peer, 100, Duration::from_millis(2), &Ok(()));
+        let refused = io::Error::from(io::ErrorKind::ConnectionRefused);
+        peer_stats.record_send(&peer, 100, Duration::from_millis(2), &Err(SenderError::ConnectionFailedError(refused, peer.clone())));
+
+        let rate_limiter = Arc::new(RateLimiter::new(RateLimits::default()));
+        rate_limiter.reject(&peer, Exceeded::IpBytes);
+
+        let inbound_connections = Arc::new(InboundConnections::new(ConnectionLimits::default(), IpFilter::default(), Vec::new()));
+        let routes = RouteTable::new(&[Duration::from_secs(10)]);
+        let (mut stats, incrementers) = Stats::new(CpuPool::new(1, &[]).stats(), peer_stats, rate_limiter, inbound_connections, routes.stats(), &[Duration::from_secs(10)]);
+        incrementers.increment_outstanding_sends();
+        incrementers.record_send_latency(Duration::from_micros(1500));
+        incrementers.record_route_send("gossip", 40, true);
+        stats.put_elapsed_receive("gossip", Duration::from_micros(250));
+
+        stats
+    }
+
+    #[test]
+    fn test_render() {
+        let text = render(&mut stats());
+
+        for line in [
+            "# TYPE shardus_net_outstanding_sends gauge",
+            "shardus_net_outstanding_sends 1",
+            "# TYPE shardus_net_send_latency_seconds summary",
+            "shardus_net_send_latency_seconds{window=\"10s\",quantile=\"0.5\"} 0.0015",
+            "shardus_net_send_latency_seconds_count{window=\"lifetime\"} 1",
+            "shardus_net_receive_latency_seconds_sum{window=\"10s\"} 0.00025",
+            "shardus_net_send_errors_total{kind=\"connection_failed\"} 1",
+            "shardus_net_rate_limited_frames_total{limit=\"ip_bytes\"} 1",
+            "shardus_net_rate_limit_disconnects_total 0",
+            "shardus_net_inbound_connections 0",
+            "shardus_net_peer_sent_bytes_total{peer=\"127.0.0.1:9001\"} 100",
+            "shardus_net_peer_send_errors_total{peer=\"127.0.0.1:9001\",kind=\"connection_failed\"} 1",
+            "shardus_net_route_sent_bytes_total{route=\"gossip\"} 40",
+            "shardus_net_unknown_route_messages_total 0",
+            "shardus_net_route_callback_latency_seconds_count{route=\"gossip\",window=\"10s\"} 1",
+        ] {
+            assert!(text.lines().any(|rendered| rendered == line), "missing {}", line);
+        }
+        assert!(text.ends_with("# EOF\n"));
+        assert_eq!(escape("a\\b\"c\nd", true), "a\\\\b\\\"c\\nd");
+    }
+
+    #[test]
+    fn test_serve() {
+        let stats = Arc::new(Mutex::new(stats()));
+        let listener = bind(0).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        RUNTIME.spawn(serve(listener, Arc::downgrade(&stats)));
+
+        let get = |path: &str| {
+            let mut socket = std::net::TcpStream::connect((Ipv4Addr::LOCALHOST, port)).unwrap();
+            write!(socket, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+            let mut response = String::new();
+            socket.read_to_string(&mut response).unwrap();
+            response
+        };
+
+        let response = get("/metrics");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains(CONTENT_TYPE));
+        assert!(response.ends_with("# EOF\n"));
+
+        assert!(get("/").starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+
+    #[test]
+    fn test_serve_stops_once_stats_are_dropped() {
+        let stats = Arc::new(Mutex::new(stats()));
+        let listener = bind(0).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let served = RUNTIME.spawn(serve(listener, Arc::downgrade(&stats)));
+
+        let mut socket = std::net::TcpStream::connect((Ipv4Addr::LOCALHOST, port)).unwrap();
+        write!(socket, "GET /metrics HTTP/1.1\r\n\r\n").unwrap();
+        socket.read_to_string(&mut String::new()).unwrap();
+
+        // Nobody scrapes the endpoint after the stats are gone
+        drop(stats);
+        RUNTIME.block_on(async { tokio::time::timeout(OWNER_CHECK_INTERVAL * 5, served).await }).unwrap().unwrap();
+        assert!(std::net::TcpStream::connect((Ipv4Addr::LOCALHOST, port)).is_err());
+    }
+}
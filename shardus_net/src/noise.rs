@@ -0,0 +1,284 @@
+use crypto::{HexStringOrBuffer, ShardusCrypto};
+use snow::{Builder, HandshakeState, TransportState};
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+// Frames starting with this byte carry Noise handshake messages (0x1 is used for headered messages).
+pub const NOISE_HANDSHAKE_MARKER: u8 = 0x2;
+pub const NOISE_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+const NOISE_PARAMS: &str = "Noise_XX_25519_ChaChaPoly_BLAKE2s";
+const NOISE_MAX_MESSAGE_LEN: usize = 65535;
+const NOISE_TAG_LEN: usize = 16;
+const NOISE_MAX_PLAINTEXT_LEN: usize = NOISE_MAX_MESSAGE_LEN - NOISE_TAG_LEN;
+const NOISE_CHUNK_LEN_PREFIX: usize = 2;
+
+#[derive(Error, Debug)]
+#[allow(clippy::enum_variant_names)]
+pub enum NoiseError {
+    #[error("Noise protocol failure. {0}")]
+    ProtocolError(#[from] snow::Error),
+    #[error("Failed to exchange handshake. {0}")]
+    StreamError(#[from] std::io::Error),
+    #[error("Peer identity does not match its static key")]
+    IdentityMismatchError,
+    #[error("Received a malformed noise frame")]
+    MalformedFrameError,
+    #[error("Handshake did not complete within {0:?}")]
+    TimeoutError(Duration),
+}
+
+/// The local node identity used for Noise handshakes. The static key is the x25519 form of the
+/// ed25519 signing key, and the ed25519 public key is sent as the handshake payload so that the
+/// remote side can bind the session to the identity that would otherwise sign every message.
+pub struct NoiseIdentity {
+    crypto: Arc<ShardusCrypto>,
+    public_key: Vec<u8>,
+    static_secret: Vec<u8>,
+}
+
+impl NoiseIdentity {
+    pub fn new(crypto: Arc<ShardusCrypto>, key_pair: &crypto::KeyPair) -> Self {
+        let (_, static_secret) = crypto.get_curve25519_key_pair(key_pair);
+
+        Self {
+            crypto,
+            public_key: key_pair.public_key.0.to_vec(),
+            static_secret,
+        }
+    }
+
+    pub fn public_key(&self) -> &[u8] {
+        &self.public_key
+    }
+
+    fn builder(&self) -> Builder<'_> {
+        Builder::new(NOISE_PARAMS.parse().expect("Noise params are valid")).local_private_key(&self.static_secret)
+    }
+}
+
+/// An established Noise transport. Every frame written through a session is encrypted and
+/// authenticated, so messages sent inside it do not need to be signed individually.
+pub struct NoiseSession {
+    transport: TransportState,
+    remote_public_key: Vec<u8>,
+}
+
+impl NoiseSession {
+    // initiate: run the initiator side of the XX handshake over a freshly connected stream
+    pub async fn initiate<S: AsyncRead + AsyncWrite + Unpin>(stream: &mut S, identity: &NoiseIdentity) -> Result<Self, NoiseError> {
+        with_handshake_timeout(async {
+            let mut handshake = identity.builder().build_initiator()?;
+
+            // -> e
+            write_handshake_frame(stream, &mut handshake, &[]).await?;
+
+            // <- e, ee, s, es
+            let responder_frame = read_handshake_frame(stream).await?;
+            let remote_public_key = read_handshake_payload(&mut handshake, &responder_frame)?;
+            verify_remote_identity(identity, &handshake, &remote_public_key)?;
+
+            // -> s, se
+            write_handshake_frame(stream, &mut handshake, identity.public_key()).await?;
+
+            Self::from_handshake(handshake, remote_public_key)
+        })
+        .await
+    }
+
+    // respond: run the responder side of the XX handshake, given the first frame already read by the listener
+    pub async fn respond<S: AsyncRead + AsyncWrite + Unpin>(stream: &mut S, first_frame: &[u8], identity: &NoiseIdentity) -> Result<Self, NoiseError> {
+        with_handshake_timeout(async {
+            let mut handshake = identity.builder().build_responder()?;
+
+            // -> e
+            read_handshake_payload(&mut handshake, first_frame)?;
+
+            // <- e, ee, s, es
+            write_handshake_frame(stream, &mut handshake, identity.public_key()).await?;
+
+            // -> s, se
+            let initiator_frame = read_handshake_frame(stream).await?;
+            let remote_public_key = read_handshake_payload(&mut handshake, &initiator_frame)?;
+            verify_remote_identity(identity, &handshake, &remote_public_key)?;
+
+            Self::from_handshake(handshake, remote_public_key)
+        })
+        .await
+    }
+
+    fn from_handshake(handshake: HandshakeState, remote_public_key: Vec<u8>) -> Result<Self, NoiseError> {
+        Ok(Self {
+            transport: handshake.into_transport_mode()?,
+            remote_public_key,
+        })
+    }
+
+    // remote_public_key: the ed25519 public key the remote side proved ownership of during the handshake
+    pub fn remote_public_key(&self) -> &[u8] {
+        &self.remote_public_key
+    }
+
+    // encrypt: split the plaintext into noise sized chunks, each prefixed with its u16 ciphertext length
+    pub fn encrypt(&mut self, plaintext: &[u8]) -> Result<Vec<u8>, NoiseError> {
+        let chunk_count = (plaintext.len() / NOISE_MAX_PLAINTEXT_LEN) + 1;
+        let mut encrypted = Vec::with_capacity(plaintext.len() + chunk_count * (NOISE_TAG_LEN + NOISE_CHUNK_LEN_PREFIX));
+        let mut chunk_buffer = vec![0u8; NOISE_MAX_MESSAGE_LEN];
+
+        for chunk in plaintext.chunks(NOISE_MAX_PLAINTEXT_LEN) {
+            let len = self.transport.write_message(chunk, &mut chunk_buffer)?;
+            encrypted.extend_from_slice(&(len as u16).to_be_bytes());
+            encrypted.extend_from_slice(&chunk_buffer[..len]);
+        }
+
+        Ok(encrypted)
+    }
+
+    pub fn decrypt(&mut self, ciphertext: &[u8]) -> Result<Vec<u8>, NoiseError> {
+        let mut decrypted = Vec::with_capacity(ciphertext.len());
+        let mut chunk_buffer = vec![0u8; NOISE_MAX_MESSAGE_LEN];
+        let mut remaining = ciphertext;
+
+        while !remaining.is_empty() {
+            if remaining.len() < NOISE_CHUNK_LEN_PREFIX {
+                return Err(NoiseError::MalformedFrameError);
+            }
+            let len = u16::from_be_bytes([remaining[0], remaining[1]]) as usize;
+            let chunk = remaining.get(NOISE_CHUNK_LEN_PREFIX..NOISE_CHUNK_LEN_PREFIX + len).ok_or(NoiseError::MalformedFrameError)?;
+
+            let len = self.transport.read_message(chunk, &mut chunk_buffer)?;
+            decrypted.extend_from_slice(&chunk_buffer[..len]);
+
+            remaining = &remaining[NOISE_CHUNK_LEN_PREFIX + chunk.len()..];
+        }
+
+        Ok(decrypted)
+    }
+}
+
+async fn with_handshake_timeout<F: std::future::Future<Output = Result<NoiseSession, NoiseError>>>(handshake: F) -> Result<NoiseSession, NoiseError> {
+    match tokio::time::timeout(NOISE_HANDSHAKE_TIMEOUT, handshake).await {
+        Ok(result) => result,
+        Err(_) => Err(NoiseError::TimeoutError(NOISE_HANDSHAKE_TIMEOUT)),
+    }
+}
+
+async fn write_handshake_frame<S: AsyncWrite + Unpin>(stream: &mut S, handshake: &mut HandshakeState, payload: &[u8]) -> Result<(), NoiseError> {
+    let mut buffer = vec![0u8; NOISE_MAX_MESSAGE_LEN];
+    let len = handshake.write_message(payload, &mut buffer)?;
+
+    stream.write_u32((len + 1) as u32).await?;
+    stream.write_u8(NOISE_HANDSHAKE_MARKER).await?;
+    stream.write_all(&buffer[..len]).await?;
+
+    Ok(())
+}
+
+async fn read_handshake_frame<S: AsyncRead + Unpin>(stream: &mut S) -> Result<Vec<u8>, NoiseError> {
+    let len = stream.read_u32().await? as usize;
+    if len == 0 || len > NOISE_MAX_MESSAGE_LEN + 1 {
+        return Err(NoiseError::MalformedFrameError);
+    }
+
+    let mut buffer = vec![0u8; len];
+    stream.read_exact(&mut buffer).await?;
+
+    Ok(buffer)
+}
+
+// read_handshake_payload: consume a handshake frame (including its marker byte) and return the decrypted payload
+fn read_handshake_payload(handshake: &mut HandshakeState, frame: &[u8]) -> Result<Vec<u8>, NoiseError> {
+    match frame.split_first() {
+        Some((&NOISE_HANDSHAKE_MARKER, message)) => {
+            let mut payload = vec![0u8; NOISE_MAX_MESSAGE_LEN];
+            let len = handshake.read_message(message, &mut payload)?;
+            payload.truncate(len);
+            Ok(payload)
+        }
+        _ => Err(NoiseError::MalformedFrameError),
+    }
+}
+
+// verify_remote_identity: the claimed ed25519 key must convert to the static key used in the handshake
+fn verify_remote_identity(identity: &NoiseIdentity, handshake: &HandshakeState, remote_public_key: &[u8]) -> Result<(), NoiseError> {
+    let expected_static = identity.crypto.get_curve25519_pk(&HexStringOrBuffer::Buffer(remote_public_key.to_vec()));
+
+    match (expected_static, handshake.get_remote_static()) {
+        (Some(expected), Some(actual)) if expected == actual => Ok(()),
+        _ => Err(NoiseError::IdentityMismatchError),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::RUNTIME;
+
+    const TEST_HASH_KEY: &str = "69fa4195670576c0160d660c3be36556ff8d504725be8a59b5a96509e0c994bc";
+    const INITIATOR_SK: &str = "c3774b92cc8850fb4026b073081290b82cab3c0f66cac250b4d710ee9aaf83ed8088b37f6f458104515ae18c2a05bde890199322f62ab5114d20c77bde5e6c9d";
+    const RESPONDER_SK: &str = "d4e7ab21cd6644d639131a100df8cae3cfdb623e009d565fd96029e8438c76fa1163f642da600d31299755f6c567e661e402faa8f4e4b999c0c9ddfdca5d43e8";
+
+    fn test_identity(secret_key: &str) -> NoiseIdentity {
+        let crypto = Arc::new(ShardusCrypto::new(TEST_HASH_KEY));
+        let key_pair = crypto.get_key_pair_using_sk(&HexStringOrBuffer::Hex(secret_key.to_string()));
+        NoiseIdentity::new(crypto, &key_pair)
+    }
+
+    #[test]
+    fn test_handshake_and_transport() {
+        let initiator_identity = test_identity(INITIATOR_SK);
+        let responder_identity = test_identity(RESPONDER_SK);
+        let initiator_public_key = initiator_identity.public_key().to_vec();
+        let responder_public_key = responder_identity.public_key().to_vec();
+
+        RUNTIME.block_on(async move {
+            let (mut initiator_stream, mut responder_stream) = tokio::io::duplex(NOISE_MAX_MESSAGE_LEN * 4);
+
+            let responder = tokio::spawn(async move {
+                let first_frame = read_handshake_frame(&mut responder_stream).await.unwrap();
+                NoiseSession::respond(&mut responder_stream, &first_frame, &responder_identity).await.unwrap()
+            });
+            let mut initiator = NoiseSession::initiate(&mut initiator_stream, &initiator_identity).await.unwrap();
+            let mut responder = responder.await.unwrap();
+
+            assert_eq!(initiator.remote_public_key(), responder_public_key.as_slice());
+            assert_eq!(responder.remote_public_key(), initiator_public_key.as_slice());
+
+            // Larger than a single noise message so that chunking is exercised
+            let plaintext: Vec<u8> = (0..(NOISE_MAX_MESSAGE_LEN * 2 + 7)).map(|i| i as u8).collect();
+            let ciphertext = initiator.encrypt(&plaintext).unwrap();
+            assert_ne!(plaintext, ciphertext);
+            assert_eq!(plaintext, responder.decrypt(&ciphertext).unwrap());
+
+            let empty = responder.encrypt(&[]).unwrap();
+            assert!(initiator.decrypt(&empty).unwrap().is_empty());
+        });
+    }
+
+    #[test]
+    fn test_decrypt_rejects_malformed_frames() {
+        let initiator_identity = test_identity(INITIATOR_SK);
+        let responder_identity = test_identity(RESPONDER_SK);
+
+        RUNTIME.block_on(async move {
+            let (mut initiator_stream, mut responder_stream) = tokio::io::duplex(NOISE_MAX_MESSAGE_LEN);
+
+            let responder = tokio::spawn(async move {
+                let first_frame = read_handshake_frame(&mut responder_stream).await.unwrap();
+                NoiseSession::respond(&mut responder_stream, &first_frame, &responder_identity).await.unwrap()
+            });
+            let mut initiator = NoiseSession::initiate(&mut initiator_stream, &initiator_identity).await.unwrap();
+            let mut responder = responder.await.unwrap();
+
+            let mut ciphertext = initiator.encrypt(b"hello").unwrap();
+            let last = ciphertext.len() - 1;
+            ciphertext[last] ^= 0xff;
+
+            assert!(matches!(responder.decrypt(&ciphertext), Err(NoiseError::ProtocolError(_))));
+            assert!(matches!(responder.decrypt(&[0x0]), Err(NoiseError::MalformedFrameError)));
+            assert!(matches!(responder.decrypt(&[0x0, 0x9, 0x1]), Err(NoiseError::MalformedFrameError)));
+        });
+    }
+}
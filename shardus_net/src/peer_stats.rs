@@ -0,0 +1,225 @@
+use crate::shardus_net_sender::SendResult;
+use crate::transport::PeerAddress;
+
+use lru::LruCache;
+use std::collections::BTreeMap;
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// Inbound peers are keyed by the address they connect from, which changes with every connection, so
+// the table is bounded and forgets the least recently active peer first.
+const PEER_STATS_CAPACITY: usize = 4096;
+
+/// Counters for one remote address. Byte counts are of the frames as handed to and read from the
+/// transport, before noise encryption and after decryption.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct PeerCounters {
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub messages_sent: u64,
+    pub messages_received: u64,
+    pub send_errors: BTreeMap<&'static str, u64>,
+    pub connects: u64,
+    pub reconnects: u64,
+    sends_completed: u64,
+    send_elapsed: Duration,
+    last_activity: Option<SystemTime>,
+}
+
+impl PeerCounters {
+    pub(crate) fn send_errors_total(&self) -> u64 {
+        self.send_errors.values().sum()
+    }
+
+    // average_send_latency: from handing a frame to its connection until the write finished or failed
+    pub(crate) fn average_send_latency(&self) -> Duration {
+        match self.sends_completed {
+            0 => Duration::ZERO,
+            sends_completed => Duration::from_nanos((self.send_elapsed.as_nanos() / sends_completed as u128) as u64),
+        }
+    }
+
+    pub(crate) fn last_activity_ms(&self) -> u64 {
+        self.last_activity
+            .and_then(|last_activity| last_activity.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |since| since.as_millis() as u64)
+    }
+}
+
+/// What `peer_stats` sorts by (descending) before taking the top N.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum PeerStatsOrder {
+    BytesSent,
+    BytesReceived,
+    MessagesSent,
+    MessagesReceived,
+    SendErrors,
+    SendLatency,
+    LastActivity,
+}
+
+impl PeerStatsOrder {
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "bytes_sent" => Some(PeerStatsOrder::BytesSent),
+            "bytes_received" => Some(PeerStatsOrder::BytesReceived),
+            "messages_sent" => Some(PeerStatsOrder::MessagesSent),
+            "messages_received" => Some(PeerStatsOrder::MessagesReceived),
+            "send_errors" => Some(PeerStatsOrder::SendErrors),
+            "send_latency" => Some(PeerStatsOrder::SendLatency),
+            "last_activity" => Some(PeerStatsOrder::LastActivity),
+            _ => None,
+        }
+    }
+
+    fn key(&self, counters: &PeerCounters) -> u128 {
+        match self {
+            PeerStatsOrder::BytesSent => counters.bytes_sent as u128,
+            PeerStatsOrder::BytesReceived => counters.bytes_received as u128,
+            PeerStatsOrder::MessagesSent => counters.messages_sent as u128,
+            PeerStatsOrder::MessagesReceived => counters.messages_received as u128,
+            PeerStatsOrder::SendErrors => counters.send_errors_total() as u128,
+            PeerStatsOrder::SendLatency => counters.average_send_latency().as_nanos(),
+            PeerStatsOrder::LastActivity => counters.last_activity_ms() as u128,
+        }
+    }
+}
+
+/// Per remote address counters, written by the sender and listener tasks and read by `peer_stats()`.
+pub(crate) struct PeerStats {
+    peers: Mutex<LruCache<PeerAddress, PeerCounters>>,
+    // send errors by kind across all peers, kept when a peer is dropped from the table
+    send_errors: Mutex<BTreeMap<&'static str, u64>>,
+}
+
+impl PeerStats {
+    pub(crate) fn new() -> Self {
+        Self::with_capacity(PEER_STATS_CAPACITY)
+    }
+
+    fn with_capacity(capacity: usize) -> Self {
+        Self {
+            peers: Mutex::new(LruCache::new(NonZeroUsize::new(capacity.max(1)).unwrap())),
+            send_errors: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub(crate) fn record_send(&self, address: &PeerAddress, bytes: usize, elapsed: Duration, result: &SendResult) {
+        if let Err(error) = result {
+            *self.send_errors.lock().expect("Peer stats lock poisoned").entry(error.kind()).or_insert(0) += 1;
+        }
+
+        self.update(address, |counters| {
+            counters.sends_completed += 1;
+            counters.send_elapsed += elapsed;
+            match result {
+                Ok(_) => {
+                    counters.bytes_sent += bytes as u64;
+                    counters.messages_sent += 1;
+                }
+                Err(error) => *counters.send_errors.entry(error.kind()).or_insert(0) += 1,
+            }
+        });
+    }
+
+    pub(crate) fn record_receive(&self, address: &PeerAddress, bytes: usize) {
+        self.update(address, |counters| {
+            counters.bytes_received += bytes as u64;
+            counters.messages_received += 1;
+        });
+    }
+
+    pub(crate) fn record_connect(&self, address: &PeerAddress) {
+        self.update(address, |counters| counters.connects += 1);
+    }
+
+    // record_reconnect: a cached connection failed a write and is being replaced
+    pub(crate) fn record_reconnect(&self, address: &PeerAddress) {
+        self.update(address, |counters| counters.reconnects += 1);
+    }
+
+    // snapshot: every tracked peer, or with top the N largest by order
+    pub(crate) fn snapshot(&self, top: Option<usize>, order: PeerStatsOrder) -> Vec<(PeerAddress, PeerCounters)> {
+        let mut peers: Vec<(PeerAddress, PeerCounters)> = self.lock().iter().map(|(address, counters)| (address.clone(), counters.clone())).collect();
+
+        if let Some(top) = top {
+            peers.sort_by_key(|(_, counters)| std::cmp::Reverse(order.key(counters)));
+            peers.truncate(top);
+        }
+
+        peers
+    }
+
+    pub(crate) fn send_errors(&self) -> BTreeMap<&'static str, u64> {
+        self.send_errors.lock().expect("Peer stats lock poisoned").clone()
+    }
+
+    fn update(&self, address: &PeerAddress, update: impl FnOnce(&mut PeerCounters)) {
+        let mut peers = self.lock();
+        if !peers.contains(address) {
+            peers.put(address.clone(), PeerCounters::default());
+        }
+        let counters = peers.get_mut(address).expect("Unexpected! The peer has just been inserted.");
+
+        update(counters);
+        counters.last_activity = Some(SystemTime::now());
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, LruCache<PeerAddress, PeerCounters>> {
+        self.peers.lock().expect("Peer stats lock poisoned")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shardus_net_sender::SenderError;
+
+    fn peer(port: u16) -> PeerAddress {
+        PeerAddress::Ip(([127, 0, 0, 1], port).into())
+    }
+
+    #[test]
+    fn test_counters_and_top_n() {
+        let peer_stats = PeerStats::new();
+
+        peer_stats.record_connect(&peer(1));
+        peer_stats.record_send(&peer(1), 100, Duration::from_millis(2), &Ok(()));
+        peer_stats.record_send(&peer(1), 100, Duration::from_millis(4), &Ok(()));
+        peer_stats.record_receive(&peer(2), 10);
+        peer_stats.record_reconnect(&peer(2));
+        let refused = std::io::Error::from(std::io::ErrorKind::ConnectionRefused);
+        peer_stats.record_send(&peer(2), 50, Duration::from_millis(6), &Err(SenderError::ConnectionFailedError(refused, peer(2))));
+
+        let all = peer_stats.snapshot(None, PeerStatsOrder::SendErrors);
+        assert_eq!(all.len(), 2);
+
+        let top = peer_stats.snapshot(Some(1), PeerStatsOrder::BytesSent);
+        let (address, counters) = &top[0];
+        assert_eq!(address, &peer(1));
+        assert_eq!((counters.bytes_sent, counters.messages_sent, counters.connects), (200, 2, 1));
+        assert_eq!(counters.average_send_latency(), Duration::from_millis(3));
+        assert!(counters.last_activity_ms() > 0);
+
+        let (address, counters) = &peer_stats.snapshot(Some(1), PeerStatsOrder::SendErrors)[0];
+        assert_eq!(address, &peer(2));
+        assert_eq!((counters.bytes_sent, counters.bytes_received, counters.reconnects), (0, 10, 1));
+        assert_eq!(counters.send_errors.get("connection_failed"), Some(&1));
+        assert_eq!(peer_stats.send_errors().get("connection_failed"), Some(&1));
+    }
+
+    #[test]
+    fn test_least_recently_active_peer_is_dropped() {
+        let peer_stats = PeerStats::with_capacity(2);
+
+        peer_stats.record_receive(&peer(1), 1);
+        peer_stats.record_receive(&peer(2), 1);
+        peer_stats.record_receive(&peer(1), 1);
+        peer_stats.record_receive(&peer(3), 1);
+
+        let mut ports: Vec<u16> = peer_stats.snapshot(None, PeerStatsOrder::LastActivity).iter().map(|(address, _)| address.port()).collect();
+        ports.sort();
+        assert_eq!(ports, vec![1, 3]);
+    }
+}
@@ -0,0 +1,320 @@
+use crate::cidr::Cidr;
+use crate::resolver::Resolver;
+use crate::socket_options::SocketOptions;
+use crate::transport::PeerAddress;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use std::io;
+use std::net::IpAddr;
+use thiserror::Error;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+// Longest HTTP CONNECT response head read before the proxy is given up on
+const MAX_RESPONSE_HEAD: usize = 8 * 1024;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+#[allow(clippy::enum_variant_names)]
+pub enum ProxyError {
+    #[error("Unsupported proxy scheme {0}, expected socks5 or http")]
+    UnsupportedSchemeError(String),
+    #[error("Invalid proxy URL {0}, expected scheme://[user:password@]host:port")]
+    InvalidUrlError(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ProxyKind {
+    Socks5,
+    HttpConnect,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Bypass {
+    Cidr(Cidr),
+    // matches the host and its subdomains
+    Host(String),
+}
+
+/// An egress proxy outbound TCP connections are dialed through. Host names are handed to the proxy
+/// to resolve, and peers matching the bypass list are connected to directly.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Proxy {
+    kind: ProxyKind,
+    address: PeerAddress,
+    credentials: Option<(String, String)>,
+    bypass: Vec<Bypass>,
+}
+
+impl Proxy {
+    // new: url is socks5://[user:password@]host:port or http://[user:password@]host:port. bypass holds
+    // CIDR blocks, IP addresses and host names, or * to bypass the proxy for every peer.
+    pub(crate) fn new(url: &str, bypass: &[String]) -> Result<Self, ProxyError> {
+        let invalid = || ProxyError::InvalidUrlError(url.to_string());
+        let (scheme, rest) = url.split_once("://").ok_or_else(invalid)?;
+        let kind = match scheme.to_ascii_lowercase().as_str() {
+            "socks5" | "socks5h" => ProxyKind::Socks5,
+            "http" => ProxyKind::HttpConnect,
+            _ => return Err(ProxyError::UnsupportedSchemeError(scheme.to_string())),
+        };
+
+        let rest = rest.trim_end_matches('/');
+        let (credentials, host_port) = match rest.rsplit_once('@') {
+            Some((user_info, host_port)) => {
+                let (user, password) = user_info.split_once(':').unwrap_or((user_info, ""));
+                (Some((user.to_string(), password.to_string())), host_port)
+            }
+            None => (None, rest),
+        };
+        let (host, port) = host_port.rsplit_once(':').ok_or_else(invalid)?;
+        let port = port.parse::<u16>().map_err(|_| invalid())?;
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if host.is_empty() {
+            return Err(invalid());
+        }
+
+        let bypass = bypass
+            .iter()
+            .map(|entry| match entry.parse::<Cidr>() {
+                Ok(cidr) => Bypass::Cidr(cidr),
+                Err(_) => Bypass::Host(entry.trim_start_matches('.').to_ascii_lowercase()),
+            })
+            .collect();
+
+        Ok(Self {
+            kind,
+            address: PeerAddress::parse(host, port),
+            credentials,
+            bypass,
+        })
+    }
+
+    // applies_to: whether a connection to address goes through the proxy, unix socket peers never do
+    pub(crate) fn applies_to(&self, address: &PeerAddress) -> bool {
+        let bypassed = |bypass: &Bypass| match (bypass, address) {
+            (Bypass::Host(host), _) if host == "*" => true,
+            (Bypass::Cidr(cidr), PeerAddress::Ip(address)) => cidr.contains(&address.ip()),
+            (Bypass::Host(host), PeerAddress::Host(name, _)) => {
+                let name = name.to_ascii_lowercase();
+                name == *host || name.strip_suffix(host.as_str()).is_some_and(|subdomain| subdomain.ends_with('.'))
+            }
+            _ => false,
+        };
+
+        !matches!(address, PeerAddress::Unix(_)) && !self.bypass.iter().any(bypassed)
+    }
+
+    // connect: a stream to address tunneled through the proxy
+    pub(crate) async fn connect(&self, address: &PeerAddress, options: &SocketOptions, resolver: &Resolver) -> io::Result<TcpStream> {
+        let (host, port) = match address {
+            PeerAddress::Ip(address) => (address.ip().to_string(), address.port()),
+            PeerAddress::Host(host, port) => (host.clone(), *port),
+            PeerAddress::Unix(_) => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Unix socket peers cannot be reached through a proxy")),
+        };
+
+        let mut stream = match &self.address {
+            PeerAddress::Ip(proxy) => options.connect(*proxy).await?,
+            PeerAddress::Host(proxy, proxy_port) => options.connect_any(resolver.resolve(proxy, *proxy_port).await?).await?,
+            PeerAddress::Unix(_) => unreachable!("proxy addresses are parsed from a host and port"),
+        };
+
+        match self.kind {
+            ProxyKind::Socks5 => self.socks5_connect(&mut stream, &host, port).await?,
+            ProxyKind::HttpConnect => self.http_connect(&mut stream, &host, port).await?,
+        }
+        Ok(stream)
+    }
+
+    // socks5_connect: RFC 1928, with username and password authentication from RFC 1929 when credentials are set
+    async fn socks5_connect(&self, stream: &mut TcpStream, host: &str, port: u16) -> io::Result<()> {
+        let methods: &[u8] = if self.credentials.is_some() { &[0x00, 0x02] } else { &[0x00] };
+        let mut greeting = vec![0x05, methods.len() as u8];
+        greeting.extend_from_slice(methods);
+        stream.write_all(&greeting).await?;
+
+        let mut choice = [0u8; 2];
+        stream.read_exact(&mut choice).await?;
+        match (choice, &self.credentials) {
+            ([0x05, 0x00], _) => {}
+            ([0x05, 0x02], Some((user, password))) => {
+                let mut auth = vec![0x01, user.len() as u8];
+                auth.extend_from_slice(user.as_bytes());
+                auth.push(password.len() as u8);
+                auth.extend_from_slice(password.as_bytes());
+                stream.write_all(&auth).await?;
+
+                let mut status = [0u8; 2];
+                stream.read_exact(&mut status).await?;
+                if status[1] != 0x00 {
+                    return Err(proxy_error("SOCKS5 proxy rejected the credentials"));
+                }
+            }
+            _ => return Err(proxy_error("SOCKS5 proxy offered no acceptable authentication method")),
+        }
+
+        let mut request = vec![0x05, 0x01, 0x00];
+        match host.parse::<IpAddr>() {
+            Ok(IpAddr::V4(ip)) => {
+                request.push(0x01);
+                request.extend_from_slice(&ip.octets());
+            }
+            Ok(IpAddr::V6(ip)) => {
+                request.push(0x04);
+                request.extend_from_slice(&ip.octets());
+            }
+            Err(_) => {
+                request.extend_from_slice(&[0x03, host.len() as u8]);
+                request.extend_from_slice(host.as_bytes());
+            }
+        }
+        request.extend_from_slice(&port.to_be_bytes());
+        stream.write_all(&request).await?;
+
+        let mut reply = [0u8; 4];
+        stream.read_exact(&mut reply).await?;
+        if reply[1] != 0x00 {
+            return Err(proxy_error(&format!("SOCKS5 proxy failed to connect to {}:{}: {}", host, port, socks5_reply(reply[1]))));
+        }
+
+        // The address the proxy bound for the tunnel is of no use, but has to be read past
+        let bound_address_len = match reply[3] {
+            0x01 => 4,
+            0x04 => 16,
+            0x03 => stream.read_u8().await? as usize,
+            _ => return Err(proxy_error("SOCKS5 proxy replied with an unknown address type")),
+        };
+        let mut bound_address = vec![0u8; bound_address_len + 2];
+        stream.read_exact(&mut bound_address).await?;
+
+        Ok(())
+    }
+
+    async fn http_connect(&self, stream: &mut TcpStream, host: &str, port: u16) -> io::Result<()> {
+        let authority = match host.parse::<IpAddr>() {
+            Ok(IpAddr::V6(_)) => format!("[{}]:{}", host, port),
+            _ => format!("{}:{}", host, port),
+        };
+        let mut request = format!("CONNECT {} HTTP/1.1\r\nHost: {}\r\n", authority, authority);
+        if let Some((user, password)) = &self.credentials {
+            request.push_str(&format!("Proxy-Authorization: Basic {}\r\n", BASE64.encode(format!("{}:{}", user, password))));
+        }
+        request.push_str("\r\n");
+        stream.write_all(request.as_bytes()).await?;
+
+        // Read byte by byte so nothing the peer sends through the tunnel is consumed with the response
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            if head.len() >= MAX_RESPONSE_HEAD {
+                return Err(proxy_error("HTTP proxy response head is too long"));
+            }
+            head.push(stream.read_u8().await?);
+        }
+
+        let head = String::from_utf8_lossy(&head);
+        let status_line = head.lines().next().unwrap_or_default();
+        match status_line.split_whitespace().nth(1) {
+            Some(status) if status.starts_with('2') => Ok(()),
+            _ => Err(proxy_error(&format!("HTTP proxy failed to connect to {}: {}", authority, status_line))),
+        }
+    }
+}
+
+fn proxy_error(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::ConnectionRefused, message)
+}
+
+fn socks5_reply(code: u8) -> &'static str {
+    match code {
+        0x01 => "general failure",
+        0x02 => "connection not allowed by ruleset",
+        0x03 => "network unreachable",
+        0x04 => "host unreachable",
+        0x05 => "connection refused",
+        0x06 => "TTL expired",
+        0x07 => "command not supported",
+        0x08 => "address type not supported",
+        _ => "unknown error",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::RUNTIME;
+    use std::time::Duration;
+    use tokio::net::TcpListener;
+
+    // proxy_stand_in: accepts one connection and walks through the handshake, checking each message it
+    // expects and sending its canned reply, then echoes whatever comes through the tunnel
+    async fn proxy_stand_in(exchanges: Vec<(Vec<u8>, Vec<u8>)>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            for (expected, reply) in exchanges {
+                let mut received = vec![0u8; expected.len()];
+                stream.read_exact(&mut received).await.unwrap();
+                assert_eq!(String::from_utf8_lossy(&received), String::from_utf8_lossy(&expected));
+                stream.write_all(&reply).await.unwrap();
+            }
+
+            let mut tunneled = [0u8; 5];
+            stream.read_exact(&mut tunneled).await.unwrap();
+            stream.write_all(&tunneled).await.unwrap();
+        });
+
+        address.to_string()
+    }
+
+    async fn assert_tunnels(proxy: Proxy, target: PeerAddress) {
+        let mut stream = proxy.connect(&target, &SocketOptions::default(), &Resolver::new(Duration::ZERO)).await.unwrap();
+        stream.write_all(b"hello").await.unwrap();
+        let mut echoed = [0u8; 5];
+        stream.read_exact(&mut echoed).await.unwrap();
+        assert_eq!(&echoed, b"hello");
+    }
+
+    #[test]
+    fn test_socks5_with_credentials() {
+        RUNTIME.block_on(async {
+            let address = proxy_stand_in(vec![
+                (vec![0x05, 0x02, 0x00, 0x02], vec![0x05, 0x02]),
+                (b"\x01\x04node\x06secret".to_vec(), vec![0x01, 0x00]),
+                (b"\x05\x01\x00\x03\x10archiver.example\x23\x29".to_vec(), vec![0x05, 0x00, 0x00, 0x01, 127, 0, 0, 1, 0x1f, 0x90]),
+            ])
+            .await;
+
+            let proxy = Proxy::new(&format!("socks5://node:secret@{}", address), &[]).unwrap();
+            assert_tunnels(proxy, PeerAddress::parse("archiver.example", 9001)).await;
+        });
+    }
+
+    #[test]
+    fn test_http_connect() {
+        RUNTIME.block_on(async {
+            let expected = b"CONNECT 10.0.0.7:9001 HTTP/1.1\r\nHost: 10.0.0.7:9001\r\nProxy-Authorization: Basic dTpw\r\n\r\n".to_vec();
+            let address = proxy_stand_in(vec![(expected, b"HTTP/1.1 200 Connection established\r\n\r\n".to_vec())]).await;
+
+            let proxy = Proxy::new(&format!("http://u:p@{}", address), &[]).unwrap();
+            assert_tunnels(proxy, PeerAddress::parse("10.0.0.7", 9001)).await;
+        });
+    }
+
+    #[test]
+    fn test_parse_and_bypass() {
+        let bypass = ["10.0.0.0/8".to_string(), "internal.example".to_string()];
+        let proxy = Proxy::new("socks5://[::1]:1080", &bypass).unwrap();
+        assert_eq!(proxy.address, PeerAddress::Ip("[::1]:1080".parse().unwrap()));
+
+        assert!(!proxy.applies_to(&PeerAddress::parse("10.1.2.3", 9001)));
+        assert!(!proxy.applies_to(&PeerAddress::parse("archiver.internal.example", 9001)));
+        assert!(!proxy.applies_to(&PeerAddress::parse("unix:/tmp/sn.sock", 0)));
+        assert!(proxy.applies_to(&PeerAddress::parse("192.0.2.1", 9001)));
+        assert!(proxy.applies_to(&PeerAddress::parse("notinternal.example", 9001)));
+        assert!(!Proxy::new("http://proxy:3128", &["*".to_string()]).unwrap().applies_to(&PeerAddress::parse("192.0.2.1", 9001)));
+
+        assert_eq!(Proxy::new("ftp://proxy:21", &[]), Err(ProxyError::UnsupportedSchemeError("ftp".to_string())));
+        assert_eq!(Proxy::new("socks5://proxy", &[]), Err(ProxyError::InvalidUrlError("socks5://proxy".to_string())));
+    }
+}
@@ -0,0 +1,155 @@
+use std::convert::TryFrom;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+// Opens every v2 header
+const V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+// Longest v1 header line including the trailing CRLF, from the spec
+const V1_MAX_LEN: usize = 107;
+
+#[derive(Error, Debug)]
+#[allow(clippy::enum_variant_names)]
+pub enum ProxyProtocolError {
+    #[error("Invalid PROXY protocol header. {0}")]
+    InvalidHeaderError(&'static str),
+    #[error("Failed to read the PROXY protocol header. {0}")]
+    ReadHeaderError(#[from] io::Error),
+}
+
+type ProxyProtocolResult<T> = Result<T, ProxyProtocolError>;
+
+// read_header: the PROXY protocol v1 or v2 header at the start of a connection from a load balancer. Ok
+// with the address of the client the connection was proxied for, None for health checks of the proxy
+// itself (v2 LOCAL, v1 UNKNOWN) and for protocols other than TCP over IPv4 or IPv6.
+pub(crate) async fn read_header<S: AsyncRead + Unpin>(stream: &mut S) -> ProxyProtocolResult<Option<SocketAddr>> {
+    let mut start = [0u8; 12];
+    stream.read_exact(&mut start).await?;
+
+    if start == V2_SIGNATURE {
+        read_v2(stream).await
+    } else if start.starts_with(b"PROXY ") {
+        read_v1(stream, start).await
+    } else {
+        Err(ProxyProtocolError::InvalidHeaderError("expected a v1 or v2 header"))
+    }
+}
+
+// read_v1: the rest of a line such as "PROXY TCP4 203.0.113.7 10.0.0.2 51234 9001\r\n"
+async fn read_v1<S: AsyncRead + Unpin>(stream: &mut S, start: [u8; 12]) -> ProxyProtocolResult<Option<SocketAddr>> {
+    let mut line = start.to_vec();
+    while !line.ends_with(b"\r\n") {
+        if line.len() == V1_MAX_LEN {
+            return Err(ProxyProtocolError::InvalidHeaderError("v1 header line is too long"));
+        }
+        line.push(stream.read_u8().await?);
+    }
+
+    let line = std::str::from_utf8(&line[..line.len() - 2]).map_err(|_| ProxyProtocolError::InvalidHeaderError("v1 header is not ASCII"))?;
+    let fields: Vec<&str> = line.split(' ').collect();
+    match fields.as_slice() {
+        ["PROXY", "UNKNOWN", ..] => Ok(None),
+        ["PROXY", family @ ("TCP4" | "TCP6"), source, _destination, source_port, _destination_port] => {
+            let ip = source.parse::<IpAddr>().map_err(|_| ProxyProtocolError::InvalidHeaderError("v1 source address"))?;
+            let port = source_port.parse::<u16>().map_err(|_| ProxyProtocolError::InvalidHeaderError("v1 source port"))?;
+            if ip.is_ipv4() != (*family == "TCP4") {
+                return Err(ProxyProtocolError::InvalidHeaderError("v1 source address does not match its family"));
+            }
+            Ok(Some(SocketAddr::new(ip, port)))
+        }
+        _ => Err(ProxyProtocolError::InvalidHeaderError("v1 header fields")),
+    }
+}
+
+// read_v2: version and command, address family and protocol, then a big endian length of the addresses
+// and any TLVs that follow them. TLVs are read past and ignored.
+async fn read_v2<S: AsyncRead + Unpin>(stream: &mut S) -> ProxyProtocolResult<Option<SocketAddr>> {
+    let version_command = stream.read_u8().await?;
+    let family = stream.read_u8().await?;
+    let mut addresses = vec![0u8; stream.read_u16().await? as usize];
+    stream.read_exact(&mut addresses).await?;
+
+    if version_command >> 4 != 0x2 {
+        return Err(ProxyProtocolError::InvalidHeaderError("unsupported version"));
+    }
+    match version_command & 0x0f {
+        0x0 => return Ok(None),
+        0x1 => {}
+        _ => return Err(ProxyProtocolError::InvalidHeaderError("unsupported command")),
+    }
+
+    let source = match family {
+        // TCP over IPv4: source and destination addresses, then source and destination ports
+        0x11 if addresses.len() >= 12 => {
+            let ip = Ipv4Addr::from(<[u8; 4]>::try_from(&addresses[0..4]).unwrap());
+            SocketAddr::new(ip.into(), u16::from_be_bytes([addresses[8], addresses[9]]))
+        }
+        0x21 if addresses.len() >= 36 => {
+            let ip = Ipv6Addr::from(<[u8; 16]>::try_from(&addresses[0..16]).unwrap());
+            SocketAddr::new(ip.into(), u16::from_be_bytes([addresses[32], addresses[33]]))
+        }
+        0x11 | 0x21 => return Err(ProxyProtocolError::InvalidHeaderError("v2 addresses are truncated")),
+        _ => return Ok(None),
+    };
+
+    Ok(Some(source))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::RUNTIME;
+
+    async fn parse(header: &[u8]) -> ProxyProtocolResult<Option<SocketAddr>> {
+        let mut stream = header;
+        let source = read_header(&mut stream).await;
+        // Nothing past the header may be consumed
+        assert!(source.is_err() || stream == b"frame");
+        source
+    }
+
+    #[test]
+    fn test_v1() {
+        RUNTIME.block_on(async {
+            let source = parse(b"PROXY TCP4 203.0.113.7 10.0.0.2 51234 9001\r\nframe").await.unwrap();
+            assert_eq!(source, Some("203.0.113.7:51234".parse().unwrap()));
+            let source = parse(b"PROXY TCP6 2001:db8::7 2001:db8::2 51234 9001\r\nframe").await.unwrap();
+            assert_eq!(source, Some("[2001:db8::7]:51234".parse().unwrap()));
+            assert_eq!(parse(b"PROXY UNKNOWN\r\nframe").await.unwrap(), None);
+
+            assert!(parse(b"PROXY TCP4 2001:db8::7 10.0.0.2 51234 9001\r\n").await.is_err());
+            assert!(parse(&[b"PROXY TCP4 ".as_slice(), &[b'1'; 120]].concat()).await.is_err());
+            assert!(parse(b"\0\0\0\x10not a proxy header").await.is_err());
+        });
+    }
+
+    #[test]
+    fn test_v2() {
+        let header = |version_command: u8, family: u8, addresses: &[u8]| {
+            let mut header = V2_SIGNATURE.to_vec();
+            header.extend_from_slice(&[version_command, family]);
+            header.extend_from_slice(&(addresses.len() as u16).to_be_bytes());
+            header.extend_from_slice(addresses);
+            header.extend_from_slice(b"frame");
+            header
+        };
+
+        RUNTIME.block_on(async {
+            let ipv4 = [203, 0, 113, 7, 10, 0, 0, 2, 0xc8, 0x22, 0x23, 0x29];
+            assert_eq!(parse(&header(0x21, 0x11, &ipv4)).await.unwrap(), Some("203.0.113.7:51234".parse().unwrap()));
+            // TLVs after the addresses are skipped
+            let with_tlv = [ipv4.as_slice(), &[0x04, 0x00, 0x01, 0xff]].concat();
+            assert_eq!(parse(&header(0x21, 0x11, &with_tlv)).await.unwrap(), Some("203.0.113.7:51234".parse().unwrap()));
+
+            let mut ipv6 = Ipv6Addr::LOCALHOST.octets().to_vec();
+            ipv6.extend_from_slice(&Ipv6Addr::UNSPECIFIED.octets());
+            ipv6.extend_from_slice(&[0xc8, 0x22, 0x23, 0x29]);
+            assert_eq!(parse(&header(0x21, 0x21, &ipv6)).await.unwrap(), Some("[::1]:51234".parse().unwrap()));
+
+            assert_eq!(parse(&header(0x20, 0x00, &[])).await.unwrap(), None);
+            assert!(parse(&header(0x21, 0x11, &ipv4[..6])).await.is_err());
+            assert!(parse(&header(0x11, 0x11, &ipv4)).await.is_err());
+        });
+    }
+}
@@ -0,0 +1,404 @@
+use crate::connection_limit::ConnectionPermit;
+use crate::events::{ConnectionEvent, EventKind, Events};
+use crate::shardus_net_listener::{FrameSink, ListenerError};
+use crate::transport::{PeerAddress, Transport, MAX_FRAME_SIZE};
+
+use super::runtime::RUNTIME;
+
+use log::{error, info};
+use quinn::crypto::rustls::{QuicClientConfig, QuicServerConfig};
+use quinn::{ClientConfig, Connection, Endpoint, RecvStream, ServerConfig};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::{CertificateDer, PrivatePkcs8KeyDer, ServerName, UnixTime};
+use rustls::{DigitallySignedStruct, SignatureScheme};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use thiserror::Error;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::Mutex;
+use tracing::{info_span, Instrument};
+
+const QUIC_ALPN: &[u8] = b"shardus-net";
+const QUIC_SERVER_NAME: &str = "shardus-net";
+// STOP_SENDING code a stream is stopped with when its frame is over MAX_FRAME_SIZE
+const FRAME_TOO_LARGE: u32 = 1;
+
+#[derive(Error, Debug)]
+#[allow(clippy::enum_variant_names)]
+pub enum QuicError {
+    #[error("Failed to configure QUIC. {0}")]
+    ConfigError(String),
+    #[error("Failed to connect. {0}")]
+    ConnectError(#[from] quinn::ConnectError),
+    #[error("Connection failed. {0}")]
+    ConnectionError(#[from] quinn::ConnectionError),
+    #[error("Failed to write to stream. {0}")]
+    WriteError(#[from] quinn::WriteError),
+    #[error("Stream was already closed. {0}")]
+    ClosedStreamError(#[from] quinn::ClosedStream),
+    #[error("QUIC socket failure. {0}")]
+    IoError(#[from] io::Error),
+}
+
+/// Sends frames over QUIC, opening one unidirectional stream per request so that a slow or lost
+/// frame never blocks the ones behind it. Connections are cached per peer and reused.
+pub struct QuicSender {
+    endpoints: Mutex<ClientEndpoints>,
+    connections: Mutex<HashMap<SocketAddr, Connection>>,
+    events: Arc<Events>,
+}
+
+#[derive(Default)]
+struct ClientEndpoints {
+    ipv4: Option<Endpoint>,
+    ipv6: Option<Endpoint>,
+}
+
+impl QuicSender {
+    pub(crate) fn new(events: Arc<Events>) -> Self {
+        Self {
+            endpoints: Mutex::new(ClientEndpoints::default()),
+            connections: Mutex::new(HashMap::new()),
+            events,
+        }
+    }
+
+    pub async fn send(&self, address: SocketAddr, data: &[u8]) -> Result<(), QuicError> {
+        let connection = self.get_or_connect(address).await?;
+
+        if let Err(err) = Self::write_frame(&connection, data).await {
+            // The cached connection may have been closed by the peer. Reconnect and try once more.
+            self.connections.lock().await.remove(&address);
+            self.events
+                .emit(ConnectionEvent::new(EventKind::Reconnect, &PeerAddress::Ip(address)).outbound().over_quic().because(err));
+            let connection = self.get_or_connect(address).await?;
+            return Self::write_frame(&connection, data).await;
+        }
+
+        Ok(())
+    }
+
+    // evict: close the cached connection to address, false when there was none
+    pub async fn evict(&self, address: &SocketAddr) -> bool {
+        match self.connections.lock().await.remove(address) {
+            Some(connection) => {
+                connection.close(0u32.into(), b"evicted");
+                true
+            }
+            None => false,
+        }
+    }
+
+    async fn get_or_connect(&self, address: SocketAddr) -> Result<Connection, QuicError> {
+        let peer_address = PeerAddress::Ip(address);
+        if let Some(connection) = self.connections.lock().await.get(&address) {
+            match connection.close_reason() {
+                None => return Ok(connection.clone()),
+                Some(reason) => self.events.emit(ConnectionEvent::new(EventKind::Closed, &peer_address).outbound().over_quic().because(reason)),
+            }
+        }
+
+        let connection = match self.connect(address).await {
+            Ok(connection) => connection,
+            Err(err) => {
+                self.events.emit(ConnectionEvent::new(EventKind::ConnectFailed, &peer_address).outbound().over_quic().because(&err));
+                return Err(err);
+            }
+        };
+        self.connections.lock().await.insert(address, connection.clone());
+        self.events.emit(ConnectionEvent::new(EventKind::Opened, &peer_address).outbound().over_quic());
+
+        Ok(connection)
+    }
+
+    async fn connect(&self, address: SocketAddr) -> Result<Connection, QuicError> {
+        let endpoint = self.endpoint_for(&address).await?;
+
+        Ok(endpoint.connect(address, QUIC_SERVER_NAME)?.await?)
+    }
+
+    async fn endpoint_for(&self, address: &SocketAddr) -> Result<Endpoint, QuicError> {
+        let mut endpoints = self.endpoints.lock().await;
+        let (endpoint, bind_address) = match address {
+            SocketAddr::V4(_) => (&mut endpoints.ipv4, SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0))),
+            SocketAddr::V6(_) => (&mut endpoints.ipv6, SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0))),
+        };
+
+        if let Some(endpoint) = endpoint {
+            return Ok(endpoint.clone());
+        }
+
+        let mut new_endpoint = Endpoint::client(bind_address)?;
+        new_endpoint.set_default_client_config(client_config()?);
+        *endpoint = Some(new_endpoint.clone());
+
+        Ok(new_endpoint)
+    }
+
+    async fn write_frame(connection: &Connection, data: &[u8]) -> Result<(), QuicError> {
+        let mut stream = connection.open_uni().await?;
+        stream.write_u32(data.len() as u32).await?;
+        stream.write_all(data).await?;
+        stream.finish()?;
+
+        Ok(())
+    }
+}
+
+/// Accepts QUIC connections on the UDP port matching the TCP listener and feeds every frame read
+/// from their streams through the same verification path as TCP frames.
+pub struct QuicListener;
+
+impl QuicListener {
+    pub fn bind(address: SocketAddr) -> Result<Endpoint, QuicError> {
+        let endpoint = Endpoint::server(server_config()?, address)?;
+        info!("Listening for QUIC connections on {}", endpoint.local_addr()?);
+
+        Ok(endpoint)
+    }
+
+    pub(crate) async fn accept_connections(endpoint: Endpoint, sink: FrameSink) {
+        while let Some(incoming) = endpoint.accept().await {
+            let permit = match sink.admit(&PeerAddress::Ip(incoming.remote_address()), Transport::Quic) {
+                Some(permit) => permit,
+                None => {
+                    incoming.refuse();
+                    continue;
+                }
+            };
+            let sink = sink.clone();
+
+            RUNTIME.spawn(async move {
+                let connection = match incoming.await {
+                    Ok(connection) => connection,
+                    Err(err) => {
+                        error!("Failed to accept QUIC connection due to {}", err);
+                        return;
+                    }
+                };
+                let remote_addr = PeerAddress::Ip(connection.remote_address());
+                sink.events.emit(ConnectionEvent::new(EventKind::Opened, &remote_addr).inbound().over_quic());
+                let evicted_connection = connection.clone();
+                permit.on_evict(move || evicted_connection.close(0u32.into(), b"connection limit"));
+                let _connection = sink.rate_limiter.track(&remote_addr);
+
+                let reason = loop {
+                    let stream = match connection.accept_uni().await {
+                        Ok(stream) => stream,
+                        Err(err) => break err,
+                    };
+                    let sink = sink.clone();
+                    let remote_addr = remote_addr.clone();
+                    let permit = permit.clone();
+                    let span = info_span!("connection", peer = %remote_addr, transport = "quic");
+
+                    RUNTIME.spawn(
+                        async move {
+                            if let Err(err) = Self::receive(stream, &remote_addr, &sink, &permit).await {
+                                error!("QUIC stream from {} failed with Error: {}", remote_addr, err);
+                            }
+                        }
+                        .instrument(span),
+                    );
+                };
+
+                info!("QUIC connection safely completed and shutdown with {}", remote_addr);
+                sink.events.emit(ConnectionEvent::new(EventKind::Closed, &remote_addr).inbound().over_quic().because(reason));
+            });
+        }
+    }
+
+    async fn receive(mut stream: RecvStream, remote_addr: &PeerAddress, sink: &FrameSink, permit: &ConnectionPermit) -> Result<(), Box<dyn std::error::Error>> {
+        loop {
+            let msg_len = match sink.connections.until_idle(stream.read_u32()).await {
+                Ok(Ok(msg_len)) => msg_len,
+                Ok(Err(_)) => break,
+                Err(_) => return Err(ListenerError::IdleTimeoutError.into()),
+            };
+            if msg_len > MAX_FRAME_SIZE {
+                stream.stop(FRAME_TOO_LARGE.into()).ok();
+                return Err(ListenerError::FrameTooLargeError(msg_len).into());
+            }
+            let mut buffer = vec![0u8; msg_len as usize];
+            sink.connections
+                .within_frame_timeout(stream.read_exact(&mut buffer))
+                .await
+                .map_err(|_| ListenerError::FrameTimeoutError)??;
+            permit.touch();
+
+            sink.process_frame(buffer, remote_addr, None).await?;
+        }
+
+        Ok(())
+    }
+}
+
+fn crypto_provider() -> Arc<CryptoProvider> {
+    Arc::new(rustls::crypto::ring::default_provider())
+}
+
+// server_config: a fresh self-signed certificate per listener. Peers are authenticated by the
+// signature carried in every message, not by the certificate, so no CA is involved.
+fn server_config() -> Result<ServerConfig, QuicError> {
+    let certified_key = rcgen::generate_simple_self_signed(vec![QUIC_SERVER_NAME.to_string()]).map_err(|err| QuicError::ConfigError(err.to_string()))?;
+    let certificate = certified_key.cert.der().clone();
+    let private_key = PrivatePkcs8KeyDer::from(certified_key.key_pair.serialize_der());
+
+    let mut server_crypto = rustls::ServerConfig::builder_with_provider(crypto_provider())
+        .with_protocol_versions(&[&rustls::version::TLS13])
+        .map_err(|err| QuicError::ConfigError(err.to_string()))?
+        .with_no_client_auth()
+        .with_single_cert(vec![certificate], private_key.into())
+        .map_err(|err| QuicError::ConfigError(err.to_string()))?;
+    server_crypto.alpn_protocols = vec![QUIC_ALPN.to_vec()];
+
+    let server_crypto = QuicServerConfig::try_from(server_crypto).map_err(|err| QuicError::ConfigError(err.to_string()))?;
+
+    Ok(ServerConfig::with_crypto(Arc::new(server_crypto)))
+}
+
+fn client_config() -> Result<ClientConfig, QuicError> {
+    let provider = crypto_provider();
+    let mut client_crypto = rustls::ClientConfig::builder_with_provider(provider.clone())
+        .with_protocol_versions(&[&rustls::version::TLS13])
+        .map_err(|err| QuicError::ConfigError(err.to_string()))?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(SelfSignedCertVerifier(provider)))
+        .with_no_client_auth();
+    client_crypto.alpn_protocols = vec![QUIC_ALPN.to_vec()];
+
+    let client_crypto = QuicClientConfig::try_from(client_crypto).map_err(|err| QuicError::ConfigError(err.to_string()))?;
+
+    Ok(ClientConfig::new(Arc::new(client_crypto)))
+}
+
+// SelfSignedCertVerifier: accepts any certificate but still checks the TLS handshake signature,
+// which keeps the channel encrypted while leaving peer authentication to message signatures.
+#[derive(Debug)]
+struct SelfSignedCertVerifier(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for SelfSignedCertVerifier {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(&self, message: &[u8], cert: &CertificateDer<'_>, dss: &DigitallySignedStruct) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(&self, message: &[u8], cert: &CertificateDer<'_>, dss: &DigitallySignedStruct) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cidr::IpFilter;
+    use crate::connection_limit::{ConnectionLimits, InboundConnections};
+    use crate::cpu_pool::CpuPool;
+    use crate::peer_stats::PeerStats;
+    use crate::rate_limit::{RateLimiter, RateLimits};
+    use crate::routes::RouteTable;
+    use crate::shardus_net_listener::{FrameTarget, Inbox, ReceivedMessage};
+    use crypto::ShardusCrypto;
+    use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+
+    const TEST_HASH_KEY: &str = "69fa4195670576c0160d660c3be36556ff8d504725be8a59b5a96509e0c994bc";
+
+    // spawn_test_listener: accept QUIC connections on a free localhost port, delivering frames directly
+    fn spawn_test_listener() -> (SocketAddr, UnboundedReceiver<ReceivedMessage>) {
+        let endpoint = Endpoint::server(server_config().unwrap(), "127.0.0.1:0".parse().unwrap()).unwrap();
+        let address = endpoint.local_addr().unwrap();
+        let (tx, rx) = unbounded_channel();
+        RUNTIME.spawn(QuicListener::accept_connections(
+            endpoint,
+            FrameSink {
+                target: FrameTarget::Direct(
+                    Inbox {
+                        received_msg_tx: tx,
+                        routes: Arc::new(RouteTable::new(&[])),
+                    },
+                    Arc::new(CpuPool::new(1, &[])),
+                    Arc::new(ShardusCrypto::new(TEST_HASH_KEY)),
+                ),
+                peer_stats: Arc::new(PeerStats::new()),
+                events: Arc::new(Events::new()),
+                rate_limiter: Arc::new(RateLimiter::new(RateLimits::default())),
+                connections: Arc::new(InboundConnections::new(ConnectionLimits::default(), IpFilter::default(), Vec::new())),
+            },
+        ));
+
+        (address, rx)
+    }
+
+    #[test]
+    fn test_send_over_localhost_udp() {
+        RUNTIME.block_on(async {
+            let (address, mut rx) = spawn_test_listener();
+
+            let sender = QuicSender::new(Arc::new(Events::new()));
+            sender.send(address, b"first").await.unwrap();
+            sender.send(address, b"second").await.unwrap();
+
+            let mut received = [rx.recv().await.unwrap(), rx.recv().await.unwrap()];
+            received.sort_by(|a, b| a.0.cmp(&b.0));
+
+            assert_eq!(received[0].0, "first");
+            assert_eq!(received[1].0, "second");
+            assert!(received
+                .iter()
+                .all(|(_, remote_addr, metadata, _)| metadata.is_none() && matches!(remote_addr, PeerAddress::Ip(addr) if addr.ip() == address.ip())));
+            assert_eq!(sender.connections.lock().await.len(), 1);
+        });
+    }
+
+    #[test]
+    fn test_reconnects_after_eviction() {
+        RUNTIME.block_on(async {
+            let (address, mut rx) = spawn_test_listener();
+
+            let sender = QuicSender::new(Arc::new(Events::new()));
+            sender.send(address, b"before").await.unwrap();
+            assert_eq!(rx.recv().await.unwrap().0, "before");
+
+            sender.evict(&address).await;
+            assert!(sender.connections.lock().await.is_empty());
+
+            sender.send(address, b"after").await.unwrap();
+            assert_eq!(rx.recv().await.unwrap().0, "after");
+        });
+    }
+
+    #[test]
+    fn test_stops_streams_over_max_frame_size() {
+        RUNTIME.block_on(async {
+            let (address, mut rx) = spawn_test_listener();
+
+            let sender = QuicSender::new(Arc::new(Events::new()));
+            let connection = sender.get_or_connect(address).await.unwrap();
+            let mut stream = connection.open_uni().await.unwrap();
+            stream.write_u32(MAX_FRAME_SIZE + 1).await.unwrap();
+            assert_eq!(stream.stopped().await.unwrap(), Some(FRAME_TOO_LARGE.into()));
+
+            // Other streams of the connection are unaffected
+            sender.send(address, b"after").await.unwrap();
+            assert_eq!(rx.recv().await.unwrap().0, "after");
+        });
+    }
+}
@@ -0,0 +1,292 @@
+use crate::token_bucket::TokenBucket;
+use crate::transport::PeerAddress;
+
+use lru::LruCache;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::net::IpAddr;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, RwLock};
+use std::time::Instant;
+
+// Buckets are kept for this many remote IPs and as many signers, the least recently seen are forgotten first
+const TRACKED_BUCKETS: usize = 4096;
+
+/// A sustained rate per remote. Bursts of up to one second's worth pass straight away, so a frame
+/// larger than `bytes_per_second` is always dropped. Unset rates are unlimited.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct Limit {
+    pub messages_per_second: Option<f64>,
+    pub bytes_per_second: Option<f64>,
+}
+
+/// Limits on inbound frames, by the IP they arrive from and by the verified signer (`Sign.owner`).
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct RateLimits {
+    pub per_ip: Limit,
+    pub per_signer: Limit,
+    // close the connection a frame over a limit arrived on, besides dropping the frame
+    pub disconnect: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Exceeded {
+    IpMessages,
+    IpBytes,
+    SignerMessages,
+    SignerBytes,
+}
+
+impl Exceeded {
+    pub(crate) const ALL: [Exceeded; 4] = [Exceeded::IpMessages, Exceeded::IpBytes, Exceeded::SignerMessages, Exceeded::SignerBytes];
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Exceeded::IpMessages => "ip_messages",
+            Exceeded::IpBytes => "ip_bytes",
+            Exceeded::SignerMessages => "signer_messages",
+            Exceeded::SignerBytes => "signer_bytes",
+        }
+    }
+}
+
+// Over: which bucket of a remote ran out
+enum Over {
+    Messages,
+    Bytes,
+}
+
+struct Buckets {
+    messages: Option<TokenBucket>,
+    bytes: Option<TokenBucket>,
+}
+
+impl Buckets {
+    fn new(limit: &Limit, now: Instant) -> Self {
+        let bucket = |rate: f64| TokenBucket::new(rate.max(0.0), rate.max(0.0), now);
+
+        Self {
+            messages: limit.messages_per_second.map(bucket),
+            bytes: limit.bytes_per_second.map(bucket),
+        }
+    }
+
+    // take: one message and bytes, or neither when either limit is reached
+    fn take(&mut self, bytes: usize, now: Instant) -> Result<(), Over> {
+        if self.messages.as_mut().is_some_and(|messages| !messages.has(1.0, now)) {
+            return Err(Over::Messages);
+        }
+        if self.bytes.as_mut().is_some_and(|bucket| !bucket.has(bytes as f64, now)) {
+            return Err(Over::Bytes);
+        }
+
+        if let Some(messages) = &mut self.messages {
+            messages.try_take(1.0, now);
+        }
+        if let Some(bucket) = &mut self.bytes {
+            bucket.try_take(bytes as f64, now);
+        }
+        Ok(())
+    }
+}
+
+/// Token buckets for every remote IP and signer seen recently, shared by all connections of a listener.
+pub(crate) struct RateLimiter {
+    limits: RwLock<RateLimits>,
+    ips: Mutex<LruCache<IpAddr, Buckets>>,
+    signers: Mutex<LruCache<Vec<u8>, Buckets>>,
+    // open connections of every address and whether to close them on their next frame, limits checked off
+    // the connection task mark them here
+    disconnects: Mutex<HashMap<PeerAddress, (usize, bool)>>,
+    dropped: [AtomicU64; 4],
+    disconnected: AtomicU64,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(limits: RateLimits) -> Self {
+        let capacity = NonZeroUsize::new(TRACKED_BUCKETS).unwrap();
+
+        Self {
+            limits: RwLock::new(limits),
+            ips: Mutex::new(LruCache::new(capacity)),
+            signers: Mutex::new(LruCache::new(capacity)),
+            disconnects: Mutex::new(HashMap::new()),
+            dropped: Default::default(),
+            disconnected: AtomicU64::new(0),
+        }
+    }
+
+    // update: apply new limits, every remote starts over with a full bucket
+    pub(crate) fn update(&self, limits: RateLimits) {
+        *self.limits.write().expect("Rate limiter lock poisoned") = limits;
+        self.ips.lock().expect("Rate limiter lock poisoned").clear();
+        self.signers.lock().expect("Rate limiter lock poisoned").clear();
+    }
+
+    // check_ip: Err with the limit the frame is over. Unix socket peers have no IP and are not limited.
+    pub(crate) fn check_ip(&self, address: &PeerAddress, bytes: usize) -> Result<(), Exceeded> {
+        let ip = match address {
+            PeerAddress::Ip(address) => address.ip(),
+            PeerAddress::Unix(_) | PeerAddress::Host(..) => return Ok(()),
+        };
+        let limit = self.limits.read().expect("Rate limiter lock poisoned").per_ip;
+
+        check(&self.ips, ip, &limit, bytes).map_err(|over| match over {
+            Over::Messages => Exceeded::IpMessages,
+            Over::Bytes => Exceeded::IpBytes,
+        })
+    }
+
+    // check_signer: like check_ip for the owner of a verified message
+    pub(crate) fn check_signer(&self, signer: &[u8], bytes: usize) -> Result<(), Exceeded> {
+        let limit = self.limits.read().expect("Rate limiter lock poisoned").per_signer;
+
+        check(&self.signers, signer.to_vec(), &limit, bytes).map_err(|over| match over {
+            Over::Messages => Exceeded::SignerMessages,
+            Over::Bytes => Exceeded::SignerBytes,
+        })
+    }
+
+    // reject: count a dropped frame and, when configured, mark its connection to be closed while still open
+    pub(crate) fn reject(&self, address: &PeerAddress, exceeded: Exceeded) {
+        self.dropped[exceeded as usize].fetch_add(1, Ordering::Relaxed);
+
+        if self.limits.read().expect("Rate limiter lock poisoned").disconnect {
+            if let Some((_, disconnect)) = self.disconnects.lock().expect("Rate limiter lock poisoned").get_mut(address) {
+                *disconnect = true;
+            }
+        }
+    }
+
+    // take_disconnect: whether the connection from address has to be closed, counting it once
+    pub(crate) fn take_disconnect(&self, address: &PeerAddress) -> bool {
+        let mut disconnects = self.disconnects.lock().expect("Rate limiter lock poisoned");
+        match disconnects.get_mut(address) {
+            Some((_, disconnect)) if *disconnect => *disconnect = false,
+            _ => return false,
+        }
+
+        self.disconnected.fetch_add(1, Ordering::Relaxed);
+        true
+    }
+
+    // track: register an open connection from address, frames rejected once the returned guard drops no
+    // longer mark it, so a later connection reusing address is not closed for them
+    pub(crate) fn track(&self, address: &PeerAddress) -> ConnectionGuard<'_> {
+        self.disconnects.lock().expect("Rate limiter lock poisoned").entry(address.clone()).or_insert((0, false)).0 += 1;
+
+        ConnectionGuard {
+            rate_limiter: self,
+            address: address.clone(),
+        }
+    }
+
+    // dropped: frames dropped for each limit, in the order of Exceeded::ALL
+    pub(crate) fn dropped(&self) -> Vec<(&'static str, u64)> {
+        Exceeded::ALL
+            .iter()
+            .map(|exceeded| (exceeded.name(), self.dropped[*exceeded as usize].load(Ordering::Relaxed)))
+            .collect()
+    }
+
+    pub(crate) fn disconnected(&self) -> u64 {
+        self.disconnected.load(Ordering::Relaxed)
+    }
+}
+
+pub(crate) struct ConnectionGuard<'a> {
+    rate_limiter: &'a RateLimiter,
+    address: PeerAddress,
+}
+
+impl Drop for ConnectionGuard<'_> {
+    fn drop(&mut self) {
+        let mut disconnects = self.rate_limiter.disconnects.lock().expect("Rate limiter lock poisoned");
+        if let Some((connections, _)) = disconnects.get_mut(&self.address) {
+            *connections -= 1;
+            if *connections == 0 {
+                disconnects.remove(&self.address);
+            }
+        }
+    }
+}
+
+fn check<K: Hash + Eq>(buckets: &Mutex<LruCache<K, Buckets>>, key: K, limit: &Limit, bytes: usize) -> Result<(), Over> {
+    if limit.messages_per_second.is_none() && limit.bytes_per_second.is_none() {
+        return Ok(());
+    }
+
+    let now = Instant::now();
+    let mut buckets = buckets.lock().expect("Rate limiter lock poisoned");
+    buckets.get_or_insert_mut(key, || Buckets::new(limit, now)).take(bytes, now)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peer(ip: [u8; 4]) -> PeerAddress {
+        PeerAddress::Ip((ip, 9001).into())
+    }
+
+    #[test]
+    fn test_limits_per_ip_and_signer() {
+        let limiter = RateLimiter::new(RateLimits {
+            per_ip: Limit {
+                messages_per_second: Some(2.0),
+                bytes_per_second: None,
+            },
+            per_signer: Limit {
+                messages_per_second: None,
+                bytes_per_second: Some(100.0),
+            },
+            disconnect: false,
+        });
+
+        assert!(limiter.check_ip(&peer([10, 0, 0, 1]), 1000).is_ok());
+        assert!(limiter.check_ip(&peer([10, 0, 0, 1]), 1000).is_ok());
+        assert_eq!(limiter.check_ip(&peer([10, 0, 0, 1]), 1), Err(Exceeded::IpMessages));
+        assert!(limiter.check_ip(&peer([10, 0, 0, 2]), 1).is_ok());
+        assert!(limiter.check_ip(&PeerAddress::Unix("/tmp/sn.sock".into()), 1).is_ok());
+
+        assert!(limiter.check_signer(b"owner", 60).is_ok());
+        assert_eq!(limiter.check_signer(b"owner", 60), Err(Exceeded::SignerBytes));
+        assert!(limiter.check_signer(b"other", 60).is_ok());
+
+        limiter.reject(&peer([10, 0, 0, 1]), Exceeded::IpMessages);
+        assert!(!limiter.take_disconnect(&peer([10, 0, 0, 1])));
+        assert_eq!(limiter.dropped()[0], ("ip_messages", 1));
+
+        // New limits start every remote over
+        limiter.update(RateLimits {
+            disconnect: true,
+            ..Default::default()
+        });
+        assert!((0..10).all(|_| limiter.check_ip(&peer([10, 0, 0, 1]), 1000).is_ok()));
+
+        let _connection = limiter.track(&peer([10, 0, 0, 1]));
+        limiter.reject(&peer([10, 0, 0, 1]), Exceeded::SignerBytes);
+        assert!(limiter.take_disconnect(&peer([10, 0, 0, 1])));
+        assert!(!limiter.take_disconnect(&peer([10, 0, 0, 1])));
+        assert_eq!(limiter.disconnected(), 1);
+    }
+
+    #[test]
+    fn test_forgets_disconnects_of_closed_connections() {
+        let limiter = RateLimiter::new(RateLimits {
+            disconnect: true,
+            ..Default::default()
+        });
+
+        let closed = limiter.track(&peer([10, 0, 0, 1]));
+        limiter.reject(&peer([10, 0, 0, 1]), Exceeded::SignerMessages);
+        drop(closed);
+        // Rejected by the batch verifier after the connection closed
+        limiter.reject(&peer([10, 0, 0, 1]), Exceeded::SignerMessages);
+
+        let _reused = limiter.track(&peer([10, 0, 0, 1]));
+        assert!(!limiter.take_disconnect(&peer([10, 0, 0, 1])));
+        assert_eq!(limiter.disconnected(), 0);
+    }
+}
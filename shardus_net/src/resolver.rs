@@ -0,0 +1,75 @@
+use lru::LruCache;
+use std::io;
+use std::net::SocketAddr;
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// Addresses are cached for this many host and port pairs, the least recently used are forgotten first
+const CACHED_HOSTS: usize = 1024;
+
+// ResolvedHosts: the addresses of each host and port, and when they were looked up
+type ResolvedHosts = LruCache<(String, u16), (Instant, Vec<SocketAddr>)>;
+
+pub(crate) const DEFAULT_DNS_CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// Resolves host names on the blocking pool of the runtime and caches their addresses for `ttl`.
+/// The system resolver does not report record TTLs, so every entry is kept for the same time.
+/// Failed lookups are not cached.
+pub(crate) struct Resolver {
+    ttl: Duration,
+    cache: Mutex<ResolvedHosts>,
+}
+
+impl Resolver {
+    pub(crate) fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            cache: Mutex::new(LruCache::new(NonZeroUsize::new(CACHED_HOSTS).unwrap())),
+        }
+    }
+
+    // resolve: every address of host, in the order the system resolver prefers them
+    pub(crate) async fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+        let key = (host.to_string(), port);
+        if let Some((resolved_at, addresses)) = self.lock().get(&key) {
+            if resolved_at.elapsed() < self.ttl {
+                return Ok(addresses.clone());
+            }
+        }
+
+        let addresses: Vec<SocketAddr> = tokio::net::lookup_host((host, port)).await?.collect();
+        if addresses.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, format!("No addresses found for {}", host)));
+        }
+
+        if !self.ttl.is_zero() {
+            self.lock().put(key, (Instant::now(), addresses.clone()));
+        }
+        Ok(addresses)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, ResolvedHosts> {
+        self.cache.lock().expect("Resolver cache lock poisoned")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::RUNTIME;
+
+    #[test]
+    fn test_resolve_and_cache() {
+        let resolver = Resolver::new(DEFAULT_DNS_CACHE_TTL);
+
+        RUNTIME.block_on(async {
+            let addresses = resolver.resolve("localhost", 9001).await.unwrap();
+            assert!(addresses.iter().all(|address| address.ip().is_loopback() && address.port() == 9001));
+            assert!(resolver.lock().contains(&("localhost".to_string(), 9001)));
+
+            assert!(resolver.resolve("shardus-net.invalid", 9001).await.is_err());
+            assert!(!resolver.lock().contains(&("shardus-net.invalid".to_string(), 9001)));
+        });
+    }
+}
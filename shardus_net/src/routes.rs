@@ -0,0 +1,257 @@
+use crate::histogram::{HistogramSummary, LatencyHistogram};
+
+use lru::LruCache;
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+// Outbound route names are whatever JS sends with, so the stats are bounded and forget the least
+// recently active route first. Messages for unknown routes are only counted in total.
+const ROUTE_STATS_CAPACITY: usize = 1024;
+
+/// Checks applied in Rust to the messages of a route before they are handed to its JS handler.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct RouteOptions {
+    // payload bytes, larger messages are dropped
+    pub max_size: Option<usize>,
+    // drop messages without a verified signer, either a signature or the owner of their noise session
+    pub require_signature: bool,
+}
+
+#[derive(Debug)]
+pub(crate) struct Route {
+    pub options: RouteOptions,
+}
+
+/// The routes registered from JS, by name. Messages with a V2 header name a route and are only
+/// delivered when it is registered and its checks pass, messages without one go to the listen callback.
+pub(crate) struct RouteTable {
+    routes: RwLock<HashMap<String, Arc<Route>>>,
+    stats: Arc<RouteStats>,
+}
+
+impl RouteTable {
+    pub(crate) fn new(latency_windows: &[Duration]) -> Self {
+        Self {
+            routes: RwLock::new(HashMap::new()),
+            stats: Arc::new(RouteStats::new(latency_windows)),
+        }
+    }
+
+    pub(crate) fn stats(&self) -> Arc<RouteStats> {
+        self.stats.clone()
+    }
+
+    // register: replaces the options of a route registered before
+    pub(crate) fn register(&self, name: &str, options: RouteOptions) {
+        let route = Arc::new(Route { options });
+        self.write().insert(name.to_string(), route);
+    }
+
+    // unregister: false when no route is registered under name
+    pub(crate) fn unregister(&self, name: &str) -> bool {
+        self.write().remove(name).is_some()
+    }
+
+    // admit: the route a message of bytes payload bytes is delivered to, Err with why it is dropped
+    pub(crate) fn admit(&self, name: &str, bytes: usize, signed: bool) -> Result<Arc<Route>, &'static str> {
+        let route = self.routes.read().expect("Route table lock poisoned").get(name).cloned();
+        let route = match route {
+            Some(route) => route,
+            None => {
+                self.stats.unknown_route_drops.fetch_add(1, Ordering::Relaxed);
+                return Err("unknown route");
+            }
+        };
+
+        let refused = if route.options.max_size.is_some_and(|max_size| bytes > max_size) {
+            Some("over the max size of the route")
+        } else if route.options.require_signature && !signed {
+            Some("unsigned message on a route that requires signatures")
+        } else {
+            None
+        };
+        if let Some(reason) = refused {
+            self.stats.update(name, |route| route.counters.dropped += 1);
+            return Err(reason);
+        }
+
+        Ok(route)
+    }
+
+    fn write(&self) -> std::sync::RwLockWriteGuard<'_, HashMap<String, Arc<Route>>> {
+        self.routes.write().expect("Route table lock poisoned")
+    }
+}
+
+/// Counters for one route. The empty route counts the messages sent and received without one,
+/// which go to the listen callback. Byte counts are of the payload, after decompression when received.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct RouteCounters {
+    pub messages_received: u64,
+    pub bytes_received: u64,
+    pub messages_sent: u64,
+    pub bytes_sent: u64,
+    // received messages dropped by the checks of the route
+    pub dropped: u64,
+    pub send_errors: u64,
+}
+
+struct TrackedRoute {
+    counters: RouteCounters,
+    // from a message being queued for JS until its handler is called
+    callback_latency: LatencyHistogram,
+}
+
+pub(crate) struct RouteStatsSnapshot {
+    pub route: String,
+    pub counters: RouteCounters,
+    pub callback_latency: Vec<(String, HistogramSummary)>,
+}
+
+/// Per route counters and latencies, written by the listener, the send completions and the JS
+/// dispatch, and read by `route_stats()`.
+pub(crate) struct RouteStats {
+    routes: Mutex<LruCache<String, TrackedRoute>>,
+    latency_windows: Vec<Duration>,
+    unknown_route_drops: AtomicU64,
+}
+
+impl RouteStats {
+    fn new(latency_windows: &[Duration]) -> Self {
+        Self {
+            routes: Mutex::new(LruCache::new(NonZeroUsize::new(ROUTE_STATS_CAPACITY).unwrap())),
+            latency_windows: latency_windows.to_vec(),
+            unknown_route_drops: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) fn record_receive(&self, route: &str, bytes: usize) {
+        self.update(route, |route| {
+            route.counters.messages_received += 1;
+            route.counters.bytes_received += bytes as u64;
+        });
+    }
+
+    pub(crate) fn record_send(&self, route: &str, bytes: usize, succeeded: bool) {
+        self.update(route, |route| {
+            if succeeded {
+                route.counters.messages_sent += 1;
+                route.counters.bytes_sent += bytes as u64;
+            } else {
+                route.counters.send_errors += 1;
+            }
+        });
+    }
+
+    pub(crate) fn record_callback_latency(&self, route: &str, elapsed: Duration) {
+        self.update(route, |route| route.callback_latency.record(elapsed));
+    }
+
+    // snapshot: every tracked route, by name
+    pub(crate) fn snapshot(&self) -> Vec<RouteStatsSnapshot> {
+        let mut routes: Vec<RouteStatsSnapshot> = self
+            .lock()
+            .iter_mut()
+            .map(|(name, route)| RouteStatsSnapshot {
+                route: name.clone(),
+                counters: route.counters,
+                callback_latency: route.callback_latency.snapshot(),
+            })
+            .collect();
+        routes.sort_by(|a, b| a.route.cmp(&b.route));
+
+        routes
+    }
+
+    // unknown_route_drops: received messages naming a route that is not registered
+    pub(crate) fn unknown_route_drops(&self) -> u64 {
+        self.unknown_route_drops.load(Ordering::Relaxed)
+    }
+
+    fn update(&self, name: &str, update: impl FnOnce(&mut TrackedRoute)) {
+        let mut routes = self.lock();
+        if !routes.contains(name) {
+            let route = TrackedRoute {
+                counters: RouteCounters::default(),
+                callback_latency: LatencyHistogram::new(&self.latency_windows),
+            };
+            routes.put(name.to_string(), route);
+        }
+
+        update(routes.get_mut(name).expect("Unexpected! The route has just been inserted."));
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, LruCache<String, TrackedRoute>> {
+        self.routes.lock().expect("Route stats lock poisoned")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_admit() {
+        let routes = RouteTable::new(&[]);
+        routes.register("gossip", RouteOptions::default());
+        routes.register(
+            "sync",
+            RouteOptions {
+                max_size: Some(1024),
+                require_signature: true,
+            },
+        );
+
+        assert_eq!(routes.admit("gossip", 1 << 20, false).unwrap().options, RouteOptions::default());
+        assert!(routes.admit("sync", 1024, true).unwrap().options.require_signature);
+        assert_eq!(routes.admit("sync", 1025, true).unwrap_err(), "over the max size of the route");
+        assert_eq!(routes.admit("sync", 10, false).unwrap_err(), "unsigned message on a route that requires signatures");
+        assert_eq!(routes.admit("repair", 10, true).unwrap_err(), "unknown route");
+
+        assert!(routes.unregister("gossip"));
+        assert!(!routes.unregister("gossip"));
+        assert_eq!(routes.admit("gossip", 10, true).unwrap_err(), "unknown route");
+    }
+
+    #[test]
+    fn test_stats() {
+        let routes = RouteTable::new(&[Duration::from_secs(10)]);
+        routes.register(
+            "sync",
+            RouteOptions {
+                max_size: Some(4),
+                require_signature: false,
+            },
+        );
+        let stats = routes.stats();
+
+        assert!(routes.admit("sync", 5, true).is_err());
+        assert!(routes.admit("repair", 5, true).is_err());
+        stats.record_receive("sync", 3);
+        stats.record_callback_latency("sync", Duration::from_micros(250));
+        stats.record_send("sync", 100, true);
+        stats.record_send("sync", 100, false);
+        stats.record_receive("", 7);
+
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.iter().map(|route| route.route.as_str()).collect::<Vec<_>>(), ["", "sync"]);
+        assert_eq!(snapshot[0].counters.bytes_received, 7);
+        assert_eq!(
+            snapshot[1].counters,
+            RouteCounters {
+                messages_received: 1,
+                bytes_received: 3,
+                messages_sent: 1,
+                bytes_sent: 100,
+                dropped: 1,
+                send_errors: 1,
+            }
+        );
+        let latency: Vec<(&str, u64)> = snapshot[1].callback_latency.iter().map(|(window, summary)| (window.as_str(), summary.count)).collect();
+        assert_eq!(latency, [("10s", 1), ("lifetime", 1)]);
+        assert_eq!(stats.unknown_route_drops(), 1);
+    }
+}
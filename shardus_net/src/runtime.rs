@@ -1,4 +1,114 @@
-use once_cell::sync::Lazy;
-use tokio::runtime::Runtime;
+use once_cell::sync::{Lazy, OnceCell};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use thiserror::Error;
+use tokio::runtime::{Builder, Runtime};
 
-pub(crate) static RUNTIME: Lazy<Runtime> = Lazy::new(|| Runtime::new().expect("Failed to initialize tokio runtime"));
+/// Settings for the shared tokio runtime. Anything left unset keeps tokio's default.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct RuntimeConfig {
+    pub worker_threads: Option<usize>,
+    pub max_blocking_threads: Option<usize>,
+    pub thread_name_prefix: Option<String>,
+    // run every task on one thread instead of a worker per core, for hosts where Node needs the cores
+    pub current_thread: bool,
+}
+
+#[derive(Error, Debug)]
+pub enum RuntimeError {
+    #[error("The runtime is already running, configureRuntime must be called before the first Sn is created")]
+    AlreadyStartedError,
+    #[error("The runtime has already been configured")]
+    AlreadyConfiguredError,
+}
+
+static RUNTIME_CONFIG: OnceCell<RuntimeConfig> = OnceCell::new();
+
+pub(crate) static RUNTIME: Lazy<Arc<Runtime>> = Lazy::new(|| build_runtime(RUNTIME_CONFIG.get().cloned().unwrap_or_default()).expect("Failed to initialize tokio runtime"));
+
+// configure_runtime: the runtime is built on first use, so the configuration has to arrive before that
+pub(crate) fn configure_runtime(config: RuntimeConfig) -> Result<(), RuntimeError> {
+    if Lazy::get(&RUNTIME).is_some() {
+        return Err(RuntimeError::AlreadyStartedError);
+    }
+
+    RUNTIME_CONFIG.set(config).map_err(|_| RuntimeError::AlreadyConfiguredError)
+}
+
+fn build_runtime(config: RuntimeConfig) -> std::io::Result<Arc<Runtime>> {
+    let mut builder = if config.current_thread { Builder::new_current_thread() } else { Builder::new_multi_thread() };
+    builder.enable_all();
+
+    if let Some(worker_threads) = config.worker_threads {
+        builder.worker_threads(worker_threads.max(1));
+    }
+
+    if let Some(max_blocking_threads) = config.max_blocking_threads {
+        builder.max_blocking_threads(max_blocking_threads.max(1));
+    }
+
+    if let Some(prefix) = config.thread_name_prefix.clone() {
+        let next_id = AtomicUsize::new(0);
+        builder.thread_name_fn(move || format!("{}-{}", prefix, next_id.fetch_add(1, Ordering::Relaxed)));
+    }
+
+    let runtime = Arc::new(builder.build()?);
+
+    // A current thread runtime only makes progress while some thread is blocked on it
+    if config.current_thread {
+        let driver = runtime.clone();
+        let name = format!("{}-driver", config.thread_name_prefix.as_deref().unwrap_or("shardus-net-runtime"));
+        thread::Builder::new().name(name).spawn(move || driver.block_on(std::future::pending::<()>()))?;
+    }
+
+    Ok(runtime)
+}
+
+/// A snapshot of the runtime metrics reported by `stats()`.
+pub(crate) struct RuntimeStats {
+    pub workers: usize,
+    pub alive_tasks: usize,
+    pub global_queue_depth: usize,
+}
+
+pub(crate) fn runtime_stats() -> RuntimeStats {
+    let metrics = RUNTIME.metrics();
+
+    RuntimeStats {
+        workers: metrics.num_workers(),
+        alive_tasks: metrics.num_alive_tasks(),
+        global_queue_depth: metrics.global_queue_depth(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_current_thread_runtime_makes_progress() {
+        let runtime = build_runtime(RuntimeConfig {
+            current_thread: true,
+            thread_name_prefix: Some("shardus-net-test".to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        runtime.spawn(async move {
+            tx.send(thread::current().name().map(|name| name.to_string())).unwrap();
+        });
+
+        let thread_name = rx.recv_timeout(std::time::Duration::from_secs(5)).unwrap();
+        assert_eq!(thread_name.as_deref(), Some("shardus-net-test-driver"));
+        assert_eq!(runtime.metrics().num_workers(), 1);
+    }
+
+    #[test]
+    fn test_configure_after_start_fails() {
+        Lazy::force(&RUNTIME);
+
+        assert!(matches!(configure_runtime(RuntimeConfig::default()), Err(RuntimeError::AlreadyStartedError)));
+    }
+}
@@ -1,23 +1,54 @@
+use crate::batch_verify::BatchVerifier;
+use crate::config::SnConfig;
+use crate::connection_limit::{ConnectionPermit, InboundConnections};
+use crate::cpu_pool::CpuPool;
+use crate::events::{ConnectionEvent, EventKind, Events};
 use crate::header::header_types::RequestMetadata;
 use crate::header_factory::header_deserialize_factory;
+use crate::key_ring::{KeyRing, LocalIdentity};
 use crate::message::Message;
-use crate::{shardus_crypto, HEADER_SIZE_LIMIT_IN_BYTES};
+use crate::noise::{NoiseError, NoiseSession, NOISE_HANDSHAKE_MARKER};
+use crate::peer_stats::PeerStats;
+use crate::proxy_protocol::{self, ProxyProtocolError};
+use crate::quic::{QuicError, QuicListener};
+use crate::rate_limit::RateLimiter;
+use crate::routes::RouteTable;
+use crate::socket_options::SocketOptions;
+use crate::transport::{PeerAddress, Transport, TransportListener, TransportStream, MAX_FRAME_SIZE};
+use crate::HEADER_SIZE_LIMIT_IN_BYTES;
 
 use super::runtime::RUNTIME;
 
+use crypto::ShardusCrypto;
 use log::{error, info};
+use quinn::Endpoint;
 use std::io::Cursor;
-use std::net::{SocketAddr, ToSocketAddrs};
+use std::net::SocketAddr;
 use std::string::FromUtf8Error;
+use std::sync::Arc;
+use std::time::Duration;
 use thiserror::Error;
 use tokio::io::AsyncReadExt;
-use tokio::net::TcpListener;
-use tokio::net::TcpStream;
 use tokio::sync::mpsc::error::SendError;
 use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tracing::field::Empty;
+use tracing::{info_span, Instrument, Span};
+
+// ReceivedMessage: the message, who sent it, its header and signature when present, and the span it was received under
+pub(crate) type ReceivedMessage = (String, PeerAddress, Option<RequestMetadata>, Span);
 
 pub struct ShardusNetListener {
-    address: SocketAddr,
+    address: PeerAddress,
+    key_ring: Arc<KeyRing>,
+    enable_quic: bool,
+    socket_options: SocketOptions,
+    verify_batch: Option<(usize, Duration)>,
+    cpu_pool: Arc<CpuPool>,
+    peer_stats: Arc<PeerStats>,
+    events: Arc<Events>,
+    rate_limiter: Arc<RateLimiter>,
+    connections: Arc<InboundConnections>,
+    routes: Arc<RouteTable>,
 }
 
 #[derive(Error, Debug)]
@@ -27,131 +58,593 @@ pub enum ListenerError {
     ParseDataAsUtf8Error(#[from] FromUtf8Error),
     #[error("Failed to notify callback with received message. {0}")]
     SendCompleteError(#[from] SendError<()>),
-    #[error("Failed to read from TCPStream. {0}")]
+    #[error("Failed to read from stream. {0}")]
     ReadStreamError(#[from] std::io::Error),
+    #[error("Noise session failed. {0}")]
+    NoiseSessionError(#[from] NoiseError),
+    #[error("Received a noise handshake but noise sessions are not enabled")]
+    NoiseSessionsDisabledError,
+    #[error("Closed over the inbound rate limit")]
+    RateLimitedError,
+    #[error("Closed after the idle timeout passed without a frame")]
+    IdleTimeoutError,
+    #[error("Closed after a frame took longer than the frame timeout to arrive")]
+    FrameTimeoutError,
+    #[error("Failed to deserialize a version {0} header")]
+    DeserializeHeaderError(u8),
+    #[error("Failed to decompress message")]
+    DecompressError,
+    #[error("Refused a frame of {0} bytes, over the maximum frame size")]
+    FrameTooLargeError(u32),
+    #[error("Closed a proxied connection. {0}")]
+    ProxyHeaderError(#[from] ProxyProtocolError),
+    #[error("Refused the client of a proxied connection: {0}")]
+    ProxiedClientRefusedError(&'static str),
+    #[error("Failed to bind {0}. {1}")]
+    BindError(PeerAddress, std::io::Error),
+    #[error("Failed to bind {0} for QUIC. {1}")]
+    QuicBindError(PeerAddress, QuicError),
 }
 
 type ListenerResult<T> = Result<T, ListenerError>;
 
 impl ShardusNetListener {
-    pub fn new<A: ToSocketAddrs>(address: A) -> Result<Self, ()> {
-        let mut addresses = address.to_socket_addrs().map_err(|_| ())?;
-        let address = addresses.next().ok_or(())?;
+    pub fn new(host: &str, port: u16, config: &SnConfig, key_ring: Arc<KeyRing>, cpu_pool: Arc<CpuPool>, peer_stats: Arc<PeerStats>, events: Arc<Events>) -> Result<Self, ()> {
+        let address = PeerAddress::resolve(host, port).map_err(|_| ())?;
+
+        Ok(Self {
+            address,
+            key_ring,
+            enable_quic: config.enable_quic,
+            socket_options: config.socket_options,
+            verify_batch: config.verify_batch(),
+            cpu_pool,
+            peer_stats,
+            events,
+            rate_limiter: Arc::new(RateLimiter::new(config.rate_limits)),
+            connections: Arc::new(InboundConnections::new(config.connection_limits, config.ip_filter.clone(), config.trusted_proxies.clone())),
+            routes: Arc::new(RouteTable::new(&config.latency_windows)),
+        })
+    }
+
+    pub(crate) fn rate_limiter(&self) -> Arc<RateLimiter> {
+        self.rate_limiter.clone()
+    }
 
-        Ok(Self { address })
+    pub(crate) fn inbound_connections(&self) -> Arc<InboundConnections> {
+        self.connections.clone()
     }
 
-    pub fn listen(&self) -> UnboundedReceiver<(String, SocketAddr, Option<RequestMetadata>)> {
-        Self::spawn_listener(self.address)
+    pub(crate) fn routes(&self) -> Arc<RouteTable> {
+        self.routes.clone()
     }
 
-    fn spawn_listener(address: SocketAddr) -> UnboundedReceiver<(String, SocketAddr, Option<RequestMetadata>)> {
+    // listen: bind the address of the listener and any additional ones, then start accepting on all of
+    // them. Ok with the addresses actually bound, so port 0 is reported with the port it was given.
+    // Err with the first address that failed to bind, nothing is left listening then.
+    pub(crate) async fn listen(&self, additional_addresses: Vec<PeerAddress>) -> ListenerResult<(Vec<PeerAddress>, UnboundedReceiver<ReceivedMessage>)> {
+        let mut listeners = Vec::new();
+        let mut endpoints = Vec::new();
+        for address in std::iter::once(self.address.clone()).chain(additional_addresses) {
+            let (listener, bound) = match self.bind(&address, &mut endpoints).await {
+                Ok(bound) => bound,
+                Err(err) => {
+                    error!("Failed to listen to {} due to {}", address, err);
+                    let bind_failed = ConnectionEvent::new(EventKind::BindFailed, &address);
+                    let bind_failed = if matches!(err, ListenerError::QuicBindError(..)) { bind_failed.over_quic() } else { bind_failed };
+                    self.events.emit(bind_failed.because(&err));
+                    return Err(err);
+                }
+            };
+            listeners.push((listener, bound));
+        }
+
+        // verify_batch holds (max frames, max wait) for batched signature verification, None verifies each frame as it arrives
         let (tx, rx) = unbounded_channel();
-        RUNTIME.spawn(Self::bind_to_socket(address, tx));
-        rx
+        let inbox = Inbox {
+            received_msg_tx: tx,
+            routes: self.routes.clone(),
+        };
+        let crypto = self.key_ring.crypto().clone();
+        let target = match self.verify_batch {
+            Some(verify_batch) => FrameTarget::Batched(BatchVerifier::spawn(verify_batch, inbox, self.cpu_pool.clone(), crypto, self.events.clone(), self.rate_limiter.clone())),
+            None => FrameTarget::Direct(inbox, self.cpu_pool.clone(), crypto),
+        };
+        let sink = FrameSink {
+            target,
+            peer_stats: self.peer_stats.clone(),
+            events: self.events.clone(),
+            rate_limiter: self.rate_limiter.clone(),
+            connections: self.connections.clone(),
+        };
+
+        for (endpoint, address) in endpoints {
+            self.events.emit(ConnectionEvent::new(EventKind::Bound, &PeerAddress::Ip(address)).over_quic());
+            RUNTIME.spawn(Self::serve_quic(endpoint, address, sink.clone()));
+        }
+        let addresses = listeners.iter().map(|(_, address)| address.clone()).collect();
+        for (listener, address) in listeners {
+            self.events.emit(ConnectionEvent::new(EventKind::Bound, &address));
+            RUNTIME.spawn(Self::serve_socket(listener, address, self.socket_options, self.key_ring.clone(), sink.clone()));
+        }
+
+        Ok((addresses, rx))
+    }
+
+    // bind: the listener and the address it is bound to, QUIC is bound on the UDP port matching the TCP one
+    async fn bind(&self, address: &PeerAddress, endpoints: &mut Vec<(Endpoint, SocketAddr)>) -> ListenerResult<(TransportListener, PeerAddress)> {
+        let bind_error = |err| ListenerError::BindError(address.clone(), err);
+        let listener = TransportListener::bind(address, &self.socket_options).await.map_err(bind_error)?;
+        let bound = listener.local_addr().map_err(bind_error)?;
+
+        if let (true, PeerAddress::Ip(socket_address)) = (self.enable_quic, &bound) {
+            let endpoint = QuicListener::bind(*socket_address).map_err(|err| ListenerError::QuicBindError(bound.clone(), err))?;
+            endpoints.push((endpoint, *socket_address));
+        }
+
+        Ok((listener, bound))
     }
 
-    async fn bind_to_socket(address: SocketAddr, tx: UnboundedSender<(String, SocketAddr, Option<RequestMetadata>)>) {
+    // serve_quic: accept QUIC on a bound endpoint, binding its port again every 10 seconds once it closes
+    async fn serve_quic(endpoint: Endpoint, address: SocketAddr, sink: FrameSink) {
+        let peer_address = PeerAddress::Ip(address);
+        let mut endpoint = endpoint;
+
         loop {
-            let listener = TcpListener::bind(address).await;
-
-            match listener {
-                Ok(listener) => {
-                    let tx = tx.clone();
-                    match Self::accept_connections(listener, tx).await {
-                        Ok(_) => unreachable!(),
-                        Err(err) => {
-                            error!("Failed to accept connection to {} due to {}", address, err)
-                        }
+            QuicListener::accept_connections(endpoint, sink.clone()).await;
+
+            endpoint = loop {
+                tokio::time::sleep(tokio::time::Duration::from_secs(10)).await;
+                match QuicListener::bind(address) {
+                    Ok(endpoint) => {
+                        sink.events.emit(ConnectionEvent::new(EventKind::Rebound, &peer_address).over_quic());
+                        break endpoint;
+                    }
+                    Err(err) => {
+                        error!("Failed to listen for QUIC on {} due to {}", address, err);
+                        sink.events.emit(ConnectionEvent::new(EventKind::BindFailed, &peer_address).over_quic().because(err));
                     }
                 }
-                Err(err) => error!("Failed to listen to {} due to {}", address, err),
             };
+        }
+    }
 
-            tokio::time::sleep(tokio::time::Duration::from_secs(10)).await;
+    // serve_socket: accept on a bound listener, binding the same address again every 10 seconds after it fails
+    async fn serve_socket(listener: TransportListener, address: PeerAddress, socket_options: SocketOptions, key_ring: Arc<KeyRing>, sink: FrameSink) {
+        let mut listener = listener;
+
+        loop {
+            if let Err(err) = Self::accept_connections(listener, key_ring.clone(), sink.clone()).await {
+                error!("Failed to accept connection to {} due to {}", address, err);
+                sink.events.emit(ConnectionEvent::new(EventKind::ListenerFailed, &address).because(err));
+            }
+
+            listener = loop {
+                tokio::time::sleep(tokio::time::Duration::from_secs(10)).await;
+                match TransportListener::bind(&address, &socket_options).await {
+                    Ok(listener) => {
+                        sink.events.emit(ConnectionEvent::new(EventKind::Rebound, &address));
+                        break listener;
+                    }
+                    Err(err) => {
+                        error!("Failed to listen to {} due to {}", address, err);
+                        sink.events.emit(ConnectionEvent::new(EventKind::BindFailed, &address).because(err));
+                    }
+                }
+            };
         }
     }
 
-    async fn accept_connections(listener: TcpListener, received_msg_tx: UnboundedSender<(String, SocketAddr, Option<RequestMetadata>)>) -> std::io::Result<()> {
+    async fn accept_connections(listener: TransportListener, key_ring: Arc<KeyRing>, sink: FrameSink) -> std::io::Result<()> {
         loop {
             let (socket, remote_addr) = listener.accept().await?;
-            let received_msg_tx = received_msg_tx.clone();
+            let permit = match sink.admit(&remote_addr, Transport::Tcp) {
+                Some(permit) => permit,
+                None => continue,
+            };
+            let sink = sink.clone();
+            let key_ring = key_ring.clone();
+
+            sink.events.emit(ConnectionEvent::new(EventKind::Opened, &remote_addr).inbound());
 
-            RUNTIME.spawn(async move {
-                let result = Self::receive(socket, remote_addr, received_msg_tx).await;
+            let span = info_span!("connection", peer = %remote_addr);
+            let connection_permit = permit.clone();
+            let task = RUNTIME.spawn(async move {
+                let events = sink.events.clone();
+                let result = Self::receive(socket, remote_addr.clone(), key_ring, sink, &connection_permit).instrument(span).await;
+                let closed = ConnectionEvent::new(EventKind::Closed, &remote_addr).inbound();
                 match result {
-                    Ok(_) => info!("Connection safely completed and shutdown with {}", remote_addr),
+                    Ok(reason) => {
+                        info!("Connection safely completed and shutdown with {}", remote_addr);
+                        events.emit(closed.because(reason));
+                    }
                     Err(err) => {
-                        error!("Connection to {} failed with Error: {}", remote_addr, err)
+                        error!("Connection to {} failed with Error: {}", remote_addr, err);
+                        events.emit(closed.because(err));
                     }
                 };
             });
+            permit.on_evict(move || task.abort());
         }
     }
 
-    async fn receive(socket_stream: TcpStream, remote_addr: SocketAddr, received_msg_tx: UnboundedSender<(String, SocketAddr, Option<RequestMetadata>)>) -> ListenerResult<()> {
-        let mut socket_stream: TcpStream = socket_stream;
-        while let Ok(msg_len) = socket_stream.read_u32().await {
+    // receive: read frames until the peer closes the stream, resolving to why the connection ended. Frames
+    // of a connection from a trusted proxy are attributed to the client named in its PROXY protocol header.
+    async fn receive(socket_stream: TransportStream, remote_addr: PeerAddress, key_ring: Arc<KeyRing>, sink: FrameSink, permit: &ConnectionPermit) -> ListenerResult<&'static str> {
+        let mut socket_stream: TransportStream = socket_stream;
+        let remote_addr = Self::client_address(&mut socket_stream, remote_addr, &sink, permit).await?;
+        let _connection = sink.rate_limiter.track(&remote_addr);
+        let mut noise_session: Option<(NoiseSession, Arc<LocalIdentity>)> = None;
+        loop {
+            let msg_len = match sink.connections.until_idle(socket_stream.read_u32()).await {
+                Ok(Ok(msg_len)) => msg_len,
+                Ok(Err(_)) => break,
+                Err(_) => return Err(ListenerError::IdleTimeoutError),
+            };
+            if msg_len > MAX_FRAME_SIZE {
+                return Err(ListenerError::FrameTooLargeError(msg_len));
+            }
             let mut buffer: Vec<u8> = vec![0; msg_len as usize];
+            sink.connections
+                .within_frame_timeout(socket_stream.read_exact(&mut buffer))
+                .await
+                .map_err(|_| ListenerError::FrameTimeoutError)??;
+            permit.touch();
 
-            // @TODO: Do a security check in the case that a sender sends an incorrect length.
+            if noise_session.is_none() && !buffer.is_empty() && buffer[0] == NOISE_HANDSHAKE_MARKER {
+                let identity = key_ring.current();
+                let noise_identity = identity.noise_identity.as_ref().ok_or(ListenerError::NoiseSessionsDisabledError)?;
+                noise_session = Some((NoiseSession::respond(&mut socket_stream, &buffer, noise_identity).await?, identity));
+                info!("Noise session established with {}", remote_addr);
+                continue;
+            }
 
-            // SAFETY: We can set the length of the vec here since we know that:
-            // 1. The capacity has been set above and the length is <= capacity.
-            // 2. We are calling read_exact which will fill the full length of the array.
-            unsafe {
-                buffer.set_len(msg_len as usize);
+            // Sessions keyed with a replaced signing key are closed once the rotation grace period is over,
+            // the peer reconnects and handshakes with the current key
+            if noise_session.as_ref().is_some_and(|(_, identity)| identity.is_expired()) {
+                info!("Closing noise session with {} keyed with a rotated signing key", remote_addr);
+                return Ok("signing key rotated");
             }
 
-            socket_stream.read_exact(&mut buffer).await?;
+            let buffer = match &mut noise_session {
+                Some((noise_session, _)) => noise_session.decrypt(&buffer)?,
+                None => buffer,
+            };
 
-            if !buffer.is_empty() && buffer[0] == 0x1 {
-                // Header is present
-                let msg_bytes = &buffer[1..];
+            let session_owner = noise_session.as_ref().map(|(noise_session, _)| noise_session.remote_public_key());
+            sink.process_frame(buffer, &remote_addr, session_owner).await?;
+        }
 
-                let mut cursor = Cursor::new(msg_bytes.to_vec());
-                let message = Message::deserialize(&mut cursor).expect("Failed to deserialize message");
+        Ok("closed by peer")
+    }
 
-                if message.header.len() > HEADER_SIZE_LIMIT_IN_BYTES {
-                    error!("Header exceeds the limit of {} bytes", HEADER_SIZE_LIMIT_IN_BYTES);
-                    continue;
-                }
+    // client_address: the address a connection from a trusted proxy was made for, which has to open with a
+    // PROXY protocol header. The client is checked against the IP filter and counted against the per IP cap.
+    // Other connections, and health checks of the proxy itself, keep their own address.
+    async fn client_address(socket_stream: &mut TransportStream, remote_addr: PeerAddress, sink: &FrameSink, permit: &ConnectionPermit) -> ListenerResult<PeerAddress> {
+        if !sink.connections.is_trusted_proxy(&remote_addr) {
+            return Ok(remote_addr);
+        }
 
-                if !message.verify(shardus_crypto::get_shardus_crypto_instance()) {
-                    error!("Failed to verify message signature");
-                    continue;
+        let client = sink
+            .connections
+            .within_frame_timeout(proxy_protocol::read_header(socket_stream))
+            .await
+            .map_err(|_| ListenerError::FrameTimeoutError)??;
+        match client {
+            Some(client) => {
+                sink.connections.check_filter(&client.ip()).map_err(ListenerError::ProxiedClientRefusedError)?;
+                let evicted = permit.attribute(client).map_err(ListenerError::ProxiedClientRefusedError)?;
+                let client = PeerAddress::Ip(client);
+                if let Some(evicted) = evicted {
+                    info!("Evicted the connection with {} to admit {}", evicted, client);
+                    sink.events.emit(ConnectionEvent::new(EventKind::Evicted, &evicted).inbound().because("connection limit"));
                 }
-                info!("Message verified!");
+                info!("Connection from {} proxied for {}", remote_addr, client);
+                Ok(client)
+            }
+            None => Ok(remote_addr),
+        }
+    }
 
-                let header_cursor = &mut Cursor::new(message.header);
-                let header = header_deserialize_factory(message.header_version, header_cursor).expect("Failed to deserialize header");
+    // decode_frame: parse one length-delimited frame. Frames that do not deserialize or have an oversized
+    // header are logged and dropped.
+    // Unsigned messages inside a noise session are attributed to the session owner and need no verification.
+    pub(crate) fn decode_frame(buffer: Vec<u8>, session_owner: Option<&[u8]>) -> Option<DecodedFrame> {
+        if buffer.is_empty() || buffer[0] != 0x1 {
+            // No header present
+            return Some(DecodedFrame::Raw(buffer));
+        }
 
-                let data = message.data;
+        // Header is present
+        let msg_bytes = &buffer[1..];
 
-                if !header.validate(data.clone()) {
-                    error!("Failed to validate data with header");
-                    continue;
-                }
+        let mut cursor = Cursor::new(msg_bytes.to_vec());
+        let mut message = match Message::deserialize(&mut cursor) {
+            Some(message) => message,
+            None => {
+                error!("Failed to deserialize message");
+                return None;
+            }
+        };
 
-                let request_metadata = RequestMetadata {
-                    version: message.header_version,
-                    header_json_string: header.to_json_string(),
-                    sign_json_string: message.sign.to_json_string(),
-                };
+        if message.header.len() > HEADER_SIZE_LIMIT_IN_BYTES {
+            error!("Header exceeds the limit of {} bytes", HEADER_SIZE_LIMIT_IN_BYTES);
+            return None;
+        }
+
+        let authenticated = match session_owner {
+            // The session already authenticated the peer, so unsigned messages are attributed to its identity
+            Some(session_owner) if message.sign.sig.is_empty() => {
+                message.sign.owner = session_owner.to_vec();
+                true
+            }
+            _ => false,
+        };
 
-                let decompressed_data_bytes = header.decompress(data.as_slice()).expect("Failed to decompress message");
+        Some(DecodedFrame::Headered { message, authenticated })
+    }
 
-                // deserialize remaining bytes as your message
-                let msg = String::from_utf8(decompressed_data_bytes.to_vec())?;
-                info!("Received message: {}", msg);
-                received_msg_tx.send((msg, remote_addr, Some(request_metadata))).map_err(|_| SendError(()))?;
-            } else {
-                // No header present
+    // deliver_frame: validate and decompress a verified frame and hand it to the JS callback channel.
+    // Frames that fail validation or the checks of their route are logged and dropped without closing
+    // the stream. Err for a header or payload that cannot be decoded, which signed frames are no guard
+    // against, so callers must drop the frame rather than panic. Runs inside the receive_frame span of
+    // the frame, which gets the uuid and tracker_id of the header.
+    pub(crate) fn deliver_frame(frame: DecodedFrame, remote_addr: &PeerAddress, inbox: &Inbox) -> ListenerResult<()> {
+        let signed = frame.signer().is_some();
+        let message = match frame {
+            DecodedFrame::Headered { message, .. } => message,
+            DecodedFrame::Raw(buffer) => {
                 let msg = String::from_utf8(buffer)?;
-                received_msg_tx.send((msg, remote_addr, None)).map_err(|_| SendError(()))?;
+                inbox.routes.stats().record_receive("", msg.len());
+                inbox.received_msg_tx.send((msg, remote_addr.clone(), None, Span::current())).map_err(|_| SendError(()))?;
+                return Ok(());
             }
+        };
+
+        let header_cursor = &mut Cursor::new(message.header);
+        let header = header_deserialize_factory(message.header_version, header_cursor).ok_or(ListenerError::DeserializeHeaderError(message.header_version))?;
+        let span = Span::current();
+        span.record("uuid", tracing::field::display(header.uuid()));
+        span.record("tracker_id", header.tracker_id());
+
+        let data = message.data;
+
+        if !header.validate(data.clone()) {
+            error!("Failed to validate data with header");
+            return Ok(());
         }
 
+        let decompressed_data_bytes = header.decompress(data.as_slice()).ok_or(ListenerError::DecompressError)?;
+
+        let route = header.route();
+        if !route.is_empty() {
+            if let Err(reason) = inbox.routes.admit(route, decompressed_data_bytes.len(), signed) {
+                info!("Dropped a message for route {} from {}: {}", route, remote_addr, reason);
+                return Ok(());
+            }
+        }
+        inbox.routes.stats().record_receive(route, decompressed_data_bytes.len());
+
+        let request_metadata = RequestMetadata {
+            version: message.header_version,
+            header_json_string: header.to_json_string(),
+            sign_json_string: message.sign.to_json_string(),
+            route: route.to_string(),
+        };
+
+        // deserialize remaining bytes as your message
+        let msg = String::from_utf8(decompressed_data_bytes.to_vec())?;
+        info!("Received message: {}", msg);
+        inbox.received_msg_tx.send((msg, remote_addr.clone(), Some(request_metadata), span)).map_err(|_| SendError(()))?;
+
         Ok(())
     }
 }
+
+pub(crate) enum DecodedFrame {
+    Headered { message: Message, authenticated: bool },
+    Raw(Vec<u8>),
+}
+
+impl DecodedFrame {
+    pub(crate) fn signed_message(&self) -> Option<&Message> {
+        match self {
+            DecodedFrame::Headered { message, authenticated: false } => Some(message),
+            _ => None,
+        }
+    }
+
+    // signer: the owner a verified frame is attributed to with the bytes counted against its rate limit,
+    // the header and payload. Raw frames have no signer.
+    pub(crate) fn signer(&self) -> Option<(&[u8], usize)> {
+        match self {
+            DecodedFrame::Headered { message, .. } if !message.sign.owner.is_empty() => Some((&message.sign.owner, message.header.len() + message.data.len())),
+            _ => None,
+        }
+    }
+}
+
+/// Where connection tasks of every transport hand their frames. Each frame is counted in the
+/// peer stats of the address it came from and checked against the rate limit of its IP before it
+/// goes on to its `FrameTarget`. Accept loops admit their connections through it as well.
+#[derive(Clone)]
+pub(crate) struct FrameSink {
+    pub target: FrameTarget,
+    pub peer_stats: Arc<PeerStats>,
+    pub events: Arc<Events>,
+    pub rate_limiter: Arc<RateLimiter>,
+    pub connections: Arc<InboundConnections>,
+}
+
+/// Where verified frames are delivered: the JS callback channel, after frames naming a route are
+/// checked against the routes registered from JS.
+#[derive(Clone)]
+pub(crate) struct Inbox {
+    pub received_msg_tx: UnboundedSender<ReceivedMessage>,
+    pub routes: Arc<RouteTable>,
+}
+
+/// Straight to the JS callback channel with per-frame signature checks, or through a shared
+/// `BatchVerifier` that checks signatures in batches. Either way the CPU heavy work runs on the
+/// `CpuPool`, not on the connection task.
+#[derive(Clone)]
+pub(crate) enum FrameTarget {
+    Direct(Inbox, Arc<CpuPool>, Arc<ShardusCrypto>),
+    Batched(BatchVerifier),
+}
+
+impl FrameSink {
+    // admit: a permit for a new inbound connection, None when it is refused by the IP filter or over the connection limits
+    pub(crate) fn admit(&self, remote_addr: &PeerAddress, transport: Transport) -> Option<Arc<ConnectionPermit>> {
+        let event = |kind: EventKind, address: &PeerAddress| ConnectionEvent {
+            transport,
+            ..ConnectionEvent::new(kind, address).inbound()
+        };
+
+        match self.connections.admit(remote_addr) {
+            Ok((permit, evicted)) => {
+                if let Some(evicted) = evicted {
+                    info!("Evicted the connection with {} to admit {}", evicted, remote_addr);
+                    self.events.emit(event(EventKind::Evicted, &evicted).because("connection limit"));
+                }
+                Some(Arc::new(permit))
+            }
+            Err(reason) => {
+                info!("Refused the connection from {}: {}", remote_addr, reason);
+                self.events.emit(event(EventKind::Refused, remote_addr).because(reason));
+                None
+            }
+        }
+    }
+
+    // process_frame: resolves once the frame is handed on, so frames of one connection stay in order.
+    // Fails once a frame of the connection was over a rate limit and limits are set to disconnect.
+    pub(crate) async fn process_frame(&self, buffer: Vec<u8>, remote_addr: &PeerAddress, session_owner: Option<&[u8]>) -> ListenerResult<()> {
+        self.peer_stats.record_receive(remote_addr, buffer.len());
+
+        let result = match self.rate_limiter.check_ip(remote_addr, buffer.len()) {
+            Ok(_) => self.forward_frame(buffer, remote_addr, session_owner).await,
+            Err(exceeded) => {
+                self.rate_limiter.reject(remote_addr, exceeded);
+                Ok(())
+            }
+        };
+
+        if self.rate_limiter.take_disconnect(remote_addr) {
+            info!("Closing the connection with {} over the rate limit", remote_addr);
+            return Err(ListenerError::RateLimitedError);
+        }
+        result
+    }
+
+    async fn forward_frame(&self, buffer: Vec<u8>, remote_addr: &PeerAddress, session_owner: Option<&[u8]>) -> ListenerResult<()> {
+        let span = info_span!("receive_frame", bytes = buffer.len(), uuid = Empty, tracker_id = Empty);
+
+        match &self.target {
+            FrameTarget::Direct(inbox, cpu_pool, crypto) => {
+                let inbox = inbox.clone();
+                let crypto = crypto.clone();
+                let events = self.events.clone();
+                let rate_limiter = self.rate_limiter.clone();
+                let remote_addr = remote_addr.clone();
+                let session_owner = session_owner.map(|session_owner| session_owner.to_vec());
+
+                cpu_pool
+                    .run(move || {
+                        let _entered = span.enter();
+                        let frame = match ShardusNetListener::decode_frame(buffer, session_owner.as_deref()) {
+                            Some(frame) => frame,
+                            None => return Ok(()),
+                        };
+
+                        if let Some(message) = frame.signed_message() {
+                            if !info_span!("verify").in_scope(|| message.verify(&crypto)) {
+                                error!("Failed to verify message signature");
+                                events.emit(ConnectionEvent::new(EventKind::SignatureRejected, &remote_addr).inbound().because("invalid signature"));
+                                return Ok(());
+                            }
+                            info!("Message verified!");
+                        }
+
+                        if let Some((signer, bytes)) = frame.signer() {
+                            if let Err(exceeded) = rate_limiter.check_signer(signer, bytes) {
+                                rate_limiter.reject(&remote_addr, exceeded);
+                                return Ok(());
+                            }
+                        }
+
+                        ShardusNetListener::deliver_frame(frame, &remote_addr, &inbox)
+                    })
+                    .await
+            }
+            FrameTarget::Batched(batch_verifier) => match span.in_scope(|| ShardusNetListener::decode_frame(buffer, session_owner)) {
+                Some(frame) => batch_verifier.submit(frame, remote_addr.clone(), span).await,
+                None => Ok(()),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crypto::HexStringOrBuffer;
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpStream;
+
+    const HASH_KEY: &str = "69fa4195670576c0160d660c3be36556ff8d504725be8a59b5a96509e0c994bc";
+    const SK: &str = "c3774b92cc8850fb4026b073081290b82cab3c0f66cac250b4d710ee9aaf83ed8088b37f6f458104515ae18c2a05bde890199322f62ab5114d20c77bde5e6c9d";
+
+    // listen: a listener on a free localhost port verifying each frame as it arrives
+    async fn listen() -> (SocketAddr, UnboundedReceiver<ReceivedMessage>) {
+        let crypto = Arc::new(ShardusCrypto::new(HASH_KEY));
+        let key_pair = crypto.get_key_pair_using_sk(&HexStringOrBuffer::Hex(SK.to_string()));
+        let key_ring = Arc::new(KeyRing::new(crypto, key_pair, false));
+        let listener = ShardusNetListener::new(
+            "127.0.0.1",
+            0,
+            &SnConfig::default(),
+            key_ring,
+            Arc::new(CpuPool::new(1, &[])),
+            Arc::new(PeerStats::new()),
+            Arc::new(Events::new()),
+        )
+        .unwrap();
+
+        let (addresses, rx) = listener.listen(Vec::new()).await.unwrap();
+        match addresses[0] {
+            PeerAddress::Ip(address) => (address, rx),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_drops_truncated_messages() {
+        RUNTIME.block_on(async {
+            let (address, mut rx) = listen().await;
+            let mut stream = TcpStream::connect(address).await.unwrap();
+
+            // A headered frame whose message ends inside its header
+            let truncated = [0x1, 1, 6, 0, 0, 0, b'h'];
+            stream.write_u32(truncated.len() as u32).await.unwrap();
+            stream.write_all(&truncated).await.unwrap();
+            stream.write_u32(5).await.unwrap();
+            stream.write_all(b"after").await.unwrap();
+
+            let received = tokio::time::timeout(Duration::from_secs(5), rx.recv()).await.unwrap().unwrap();
+            assert_eq!(received.0, "after");
+        });
+    }
+    #[test]
+    fn test_closes_connections_over_max_frame_size() {
+        RUNTIME.block_on(async {
+            let (address, _rx) = listen().await;
+            let mut stream = TcpStream::connect(address).await.unwrap();
+
+            stream.write_u32(MAX_FRAME_SIZE + 1).await.unwrap();
+            let closed = tokio::time::timeout(Duration::from_secs(5), stream.read_u8()).await.unwrap();
+            assert!(closed.is_err());
+        });
+    }
+}
@@ -1,107 +1,248 @@
 use super::runtime::RUNTIME;
+use crate::config::SnConfig;
+use crate::cpu_pool::CpuPool;
+use crate::events::{ConnectionEvent, EventKind, Events};
 use crate::header::header_types::Header;
 use crate::header_factory::{header_serialize_factory, wrap_serialized_message};
+use crate::key_ring::{KeyRing, KeyRingError, LocalIdentity};
 use crate::message::Message;
+use crate::noise::{NoiseError, NoiseSession};
 use crate::oneshot::Sender;
-use crate::shardus_crypto;
+use crate::peer_stats::PeerStats;
+use crate::proxy::Proxy;
+use crate::quic::{QuicError, QuicSender};
+use crate::resolver::Resolver;
+use crate::socket_options::SocketOptions;
+use crate::transport::{PeerAddress, Transport, TransportStream};
 use log::error;
 #[cfg(debug)]
 use log::info;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use std::io;
 use std::net::SocketAddr;
 
 use lru::LruCache;
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
 use thiserror::Error;
 use tokio::io::AsyncWriteExt;
-use tokio::net::TcpStream;
 use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
 use tokio::sync::Mutex;
+use tracing::{info_span, Instrument, Span};
 
 #[derive(Error, Debug)]
 #[allow(clippy::enum_variant_names)]
 pub enum SenderError {
     #[error("Failed to connect to {1} with error {0}")]
-    ConnectionFailedError(std::io::Error, SocketAddr),
+    ConnectionFailedError(std::io::Error, PeerAddress),
     #[error("Failed to send to {1} with error {0}")]
-    SendFailedError(std::io::Error, SocketAddr),
+    SendFailedError(std::io::Error, PeerAddress),
+    #[error("Failed to establish noise session with {1} with error {0}")]
+    HandshakeFailedError(NoiseError, PeerAddress),
+    #[error("Failed to send over QUIC to {1} with error {0}")]
+    QuicSendFailedError(QuicError, PeerAddress),
+}
+
+impl SenderError {
+    // kind: the name send errors are counted under in peer stats
+    pub fn kind(&self) -> &'static str {
+        match self {
+            SenderError::ConnectionFailedError(..) => "connection_failed",
+            SenderError::SendFailedError(..) => "send_failed",
+            SenderError::HandshakeFailedError(..) => "handshake_failed",
+            SenderError::QuicSendFailedError(..) => "quic_send_failed",
+        }
+    }
 }
 
 pub type SendResult = Result<(), SenderError>;
 
+// OutgoingFrame: a serialized frame, where it goes, who to tell once it is written and the span it is traced under
+type OutgoingFrame = (PeerAddress, Vec<u8>, Sender<SendResult>, Span);
+
 pub struct ShardusNetSender {
-    key_pair: crypto::KeyPair,
-    send_channel: UnboundedSender<(SocketAddr, Vec<u8>, Sender<SendResult>)>,
-    evict_socket_channel: UnboundedSender<SocketAddr>,
+    key_ring: Arc<KeyRing>,
+    cpu_pool: Arc<CpuPool>,
+    quic_peers: Arc<RwLock<HashSet<SocketAddr>>>,
+    resolver: Arc<Resolver>,
+    send_channel: UnboundedSender<OutgoingFrame>,
+    evict_socket_channel: UnboundedSender<PeerAddress>,
 }
 
 impl ShardusNetSender {
-    pub fn new(key_pair: crypto::KeyPair, connections: Arc<Mutex<dyn ConnectionCache + Send>>) -> Self {
+    pub(crate) fn new(key_ring: Arc<KeyRing>, connections: Arc<Mutex<dyn ConnectionCache + Send>>, cpu_pool: Arc<CpuPool>, peer_stats: Arc<PeerStats>, events: Arc<Events>, config: &SnConfig) -> Self {
         let (send_channel, send_channel_rx) = unbounded_channel();
         let (evict_socket_channel, evict_socket_channel_rx) = unbounded_channel();
 
-        Self::spawn_sender(send_channel_rx, Arc::clone(&connections));
-        Self::spawn_evictor(evict_socket_channel_rx, Arc::clone(&connections));
+        let quic_peers = Arc::new(RwLock::new(HashSet::new()));
+        let quic_sender = Arc::new(QuicSender::new(events.clone()));
+        let resolver = Arc::new(Resolver::new(config.dns_cache_ttl));
+
+        let context = ConnectionContext {
+            key_ring: Arc::clone(&key_ring),
+            peer_stats,
+            events: Arc::clone(&events),
+            socket_options: config.socket_options,
+            resolver: Arc::clone(&resolver),
+            proxy: config.proxy.clone().map(Arc::new),
+        };
+        Self::spawn_sender(send_channel_rx, Arc::clone(&connections), context, Arc::clone(&quic_peers), Arc::clone(&quic_sender));
+        Self::spawn_evictor(evict_socket_channel_rx, Arc::clone(&connections), quic_sender, events);
 
         Self {
-            key_pair,
+            key_ring,
+            cpu_pool,
+            quic_peers,
+            resolver,
             send_channel,
             evict_socket_channel,
         }
     }
 
     // send: send data to a socket address without a header
-    pub fn send(&self, address: SocketAddr, data: String, complete_tx: Sender<SendResult>) {
+    pub fn send(&self, address: PeerAddress, data: String, complete_tx: Sender<SendResult>) {
         let data = data.into_bytes();
+        let span = info_span!("send", peer = %address);
         self.send_channel
-            .send((address, data, complete_tx))
+            .send((address, data, complete_tx, span))
             .expect("Unexpected! Failed to send data to channel. Sender task must have been dropped.");
     }
 
     // send_with_header: send data to a socket address with a header and signature
-    pub fn send_with_header(&self, address: SocketAddr, header_version: u8, mut header: Header, data: Vec<u8>, complete_tx: Sender<SendResult>) {
-        let compressed_data = header.compress(data);
-        header.set_message_length(compressed_data.len() as u32);
-        let serialized_header = header_serialize_factory(header_version, header).expect("Failed to serialize header");
-        let mut message = Message::new_unsigned(header_version, serialized_header, compressed_data);
-        message.sign(shardus_crypto::get_shardus_crypto_instance(), &self.key_pair);
-        let serialized_message = wrap_serialized_message(message.serialize());
-        self.send_channel
-            .send((address, serialized_message, complete_tx))
-            .expect("Unexpected! Failed to send data with header to channel. Sender task must have been dropped.");
+    pub fn send_with_header(&self, address: PeerAddress, header_version: u8, header: Header, data: Vec<u8>, complete_tx: Sender<SendResult>) {
+        let sign = self.needs_signature(std::slice::from_ref(&address));
+        let span = info_span!("send_with_header", peer = %address, uuid = %header.uuid(), tracker_id = header.tracker_id());
+        self.prepare_on_cpu_pool(span.clone(), move |crypto, key_pair| {
+            let mut message = build_message(header_version, header, data);
+            if sign {
+                message.sign(crypto, key_pair);
+            }
+            vec![(address, wrap_serialized_message(message.serialize()), complete_tx, span)]
+        });
     }
 
     // multi_send_with_header: send data to multiple socket addresses with a single header and signature
-    pub fn multi_send_with_header(&self, addresses: Vec<SocketAddr>, header_version: u8, mut header: Header, data: Vec<u8>, senders: Vec<Sender<SendResult>>) {
-        let compressed_data = header.compress(data);
-        header.set_message_length(compressed_data.len() as u32);
-        let serialized_header = header_serialize_factory(header_version, header).expect("Failed to serialize header");
-        let mut message = Message::new_unsigned(header_version, serialized_header.clone(), compressed_data.clone());
-        message.sign(shardus_crypto::get_shardus_crypto_instance(), &self.key_pair);
-        let serialized_message = wrap_serialized_message(message.serialize());
-    
-        for (address, sender) in addresses.into_iter().zip(senders.into_iter()) {
-            self.send_channel
-                .send((address, serialized_message.clone(), sender))
-                .expect("Failed to send data with header to channel");
+    pub fn multi_send_with_header(&self, addresses: Vec<PeerAddress>, header_version: u8, header: Header, data: Vec<u8>, senders: Vec<Sender<SendResult>>) {
+        let sign = self.needs_signature(&addresses);
+        let span = info_span!("multi_send_with_header", peers = addresses.len(), uuid = %header.uuid(), tracker_id = header.tracker_id());
+        self.prepare_on_cpu_pool(span.clone(), move |crypto, key_pair| {
+            let mut message = build_message(header_version, header, data);
+            if sign {
+                message.sign(crypto, key_pair);
+            }
+            let serialized_message = wrap_serialized_message(message.serialize());
+
+            addresses
+                .into_iter()
+                .zip(senders)
+                .map(|(address, sender)| {
+                    let span = info_span!(parent: &span, "send", peer = %address);
+                    (address, serialized_message.clone(), sender, span)
+                })
+                .collect()
+        });
+    }
+
+    // multi_send_with_distinct_payloads: send a different header and payload to each socket address, all signed in one pass
+    pub fn multi_send_with_distinct_payloads(&self, addresses: Vec<PeerAddress>, header_version: u8, payloads: Vec<(Header, Vec<u8>)>, senders: Vec<Sender<SendResult>>) {
+        let sign = self.needs_signature(&addresses);
+        let span = info_span!("multi_send_with_distinct_payloads", peers = addresses.len());
+        let spans: Vec<Span> = addresses
+            .iter()
+            .zip(&payloads)
+            .map(|(address, (header, _))| info_span!(parent: &span, "send", peer = %address, uuid = %header.uuid(), tracker_id = header.tracker_id()))
+            .collect();
+        self.prepare_on_cpu_pool(span, move |crypto, key_pair| {
+            let mut messages: Vec<Message> = payloads.into_iter().map(|(header, data)| build_message(header_version, header, data)).collect();
+            if sign {
+                Message::sign_batch(&mut messages, crypto, key_pair);
+            }
+
+            addresses
+                .into_iter()
+                .zip(messages)
+                .zip(senders)
+                .zip(spans)
+                .map(|(((address, message), sender), span)| (address, wrap_serialized_message(message.serialize()), sender, span))
+                .collect()
+        });
+    }
+
+    // prepare_on_cpu_pool: compress, serialize and sign off the JS thread, then queue the results for sending.
+    // Messages no longer need signing when they travel inside a noise session, see needs_signature.
+    fn prepare_on_cpu_pool<F>(&self, span: Span, prepare: F)
+    where
+        F: FnOnce(&crypto::ShardusCrypto, &crypto::KeyPair) -> Vec<OutgoingFrame> + Send + 'static,
+    {
+        let cpu_pool = self.cpu_pool.clone();
+        let key_ring = self.key_ring.clone();
+        let send_channel = self.send_channel.clone();
+        let prepare_span = info_span!(parent: &span, "prepare");
+
+        RUNTIME.spawn(async move {
+            for outgoing in cpu_pool.run(move || prepare_span.in_scope(|| prepare(key_ring.crypto(), &key_ring.current().key_pair))).await {
+                send_channel
+                    .send(outgoing)
+                    .expect("Unexpected! Failed to send data with header to channel. Sender task must have been dropped.");
+            }
+        });
+    }
+
+    // needs_signature: messages sent inside a noise session are authenticated by the session, so signing is skipped.
+    // QUIC streams carry no noise session, so a message headed to any QUIC peer is always signed.
+    fn needs_signature(&self, addresses: &[PeerAddress]) -> bool {
+        let quic_peers = self.quic_peers.read().expect("QUIC peer set lock poisoned");
+        !self.key_ring.use_noise_sessions() || addresses.iter().any(|address| quic_address(address, &quic_peers).is_some())
+    }
+
+    // update_signing_key: see KeyRing::update
+    pub fn update_signing_key(&self, hex_signing_sk: String, grace_period: Duration) -> Result<(), KeyRingError> {
+        self.key_ring.update(hex_signing_sk, grace_period)
+    }
+
+    // set_peer_transport: choose whether an IP peer is reached over TCP (the default) or QUIC. A host name
+    // applies to every address it resolves to, looked up on the runtime so a slow resolver never blocks
+    // the caller. Unix socket peers are always reached over their socket.
+    pub fn set_peer_transport(&self, address: PeerAddress, transport: Transport) {
+        match address {
+            PeerAddress::Ip(address) => set_quic_peer(&self.quic_peers, address, transport),
+            PeerAddress::Host(host, port) => {
+                let quic_peers = Arc::clone(&self.quic_peers);
+                let resolver = Arc::clone(&self.resolver);
+
+                RUNTIME.spawn(async move {
+                    match resolver.resolve(&host, port).await {
+                        Ok(addresses) => addresses.into_iter().for_each(|address| set_quic_peer(&quic_peers, address, transport)),
+                        Err(err) => error!("Failed to resolve {}:{} to set its transport due to {}", host, port, err),
+                    }
+                });
+            }
+            PeerAddress::Unix(_) => {}
         }
     }
 
-    pub fn evict_socket(&self, address: SocketAddr) {
+    pub fn evict_socket(&self, address: PeerAddress) {
         self.evict_socket_channel
             .send(address)
             .expect("Unexpected! Failed to send data to channel. Socket evictor task must have been dropped.");
     }
 
-    fn spawn_evictor(evict_socket_channel_rx: UnboundedReceiver<SocketAddr>, connections: Arc<Mutex<dyn ConnectionCache + Send>>) {
+    fn spawn_evictor(evict_socket_channel_rx: UnboundedReceiver<PeerAddress>, connections: Arc<Mutex<dyn ConnectionCache + Send>>, quic_sender: Arc<QuicSender>, events: Arc<Events>) {
         RUNTIME.spawn(async move {
             let mut evict_socket_channel_rx = evict_socket_channel_rx;
 
             while let Some(address) = evict_socket_channel_rx.recv().await {
+                let evicted = ConnectionEvent::new(EventKind::Evicted, &address).outbound().because("evict_socket");
+                if let PeerAddress::Ip(socket_address) = &address {
+                    if quic_sender.evict(socket_address).await {
+                        events.emit(evicted.clone().over_quic());
+                    }
+                }
                 let mut connections = connections.lock().await;
-                connections.remove(&address);
+                if connections.remove(&address).is_some() {
+                    events.emit(evicted);
+                }
                 #[cfg(debug)]
                 info!("Evicted socket {} from cache", address);
             }
@@ -111,20 +252,48 @@ impl ShardusNetSender {
         });
     }
 
-    fn spawn_sender(send_channel_rx: UnboundedReceiver<(SocketAddr, Vec<u8>, Sender<SendResult>)>, connections: Arc<Mutex<dyn ConnectionCache + Send>>) {
+    fn spawn_sender(
+        send_channel_rx: UnboundedReceiver<OutgoingFrame>,
+        connections: Arc<Mutex<dyn ConnectionCache + Send>>,
+        context: ConnectionContext,
+        quic_peers: Arc<RwLock<HashSet<SocketAddr>>>,
+        quic_sender: Arc<QuicSender>,
+    ) {
         RUNTIME.spawn(async move {
             let mut send_channel_rx = send_channel_rx;
 
-            while let Some((address, data, complete_tx)) = send_channel_rx.recv().await {
+            while let Some((address, data, complete_tx, span)) = send_channel_rx.recv().await {
+                let peer_stats = context.peer_stats.clone();
+                let quic_socket_address = quic_address(&address, &quic_peers.read().expect("QUIC peer set lock poisoned"));
+                if let Some(socket_address) = quic_socket_address {
+                    let quic_sender = quic_sender.clone();
+                    RUNTIME.spawn(
+                        async move {
+                            let started_at = Instant::now();
+                            let result = quic_sender.send(socket_address, &data).await.map_err(|error| SenderError::QuicSendFailedError(error, address.clone()));
+                            peer_stats.record_send(&address, data.len(), started_at.elapsed(), &result);
+                            complete_tx.send(result).ok();
+                        }
+                        .instrument(span),
+                    );
+                    continue;
+                }
+
                 let connection = {
                     let mut connections = connections.lock().await;
-                    connections.get_or_insert(address)
+                    connections.get_or_insert(&address, &context)
                 };
 
-                RUNTIME.spawn(async move {
-                    let result = connection.send(data).await;
-                    complete_tx.send(result).ok();
-                });
+                RUNTIME.spawn(
+                    async move {
+                        let bytes = data.len();
+                        let started_at = Instant::now();
+                        let result = connection.send(data).await;
+                        peer_stats.record_send(&address, bytes, started_at.elapsed(), &result);
+                        complete_tx.send(result).ok();
+                    }
+                    .instrument(span),
+                );
             }
 
             #[cfg(debug)]
@@ -133,56 +302,147 @@ impl ShardusNetSender {
     }
 }
 
+fn build_message(header_version: u8, mut header: Header, data: Vec<u8>) -> Message {
+    let compressed_data = header.compress(data);
+    header.set_message_length(compressed_data.len() as u32);
+    let serialized_header = header_serialize_factory(header_version, header).expect("Failed to serialize header");
+    Message::new_unsigned(header_version, serialized_header, compressed_data)
+}
+
+fn set_quic_peer(quic_peers: &RwLock<HashSet<SocketAddr>>, address: SocketAddr, transport: Transport) {
+    let mut quic_peers = quic_peers.write().expect("QUIC peer set lock poisoned");
+    match transport {
+        Transport::Quic => quic_peers.insert(address),
+        Transport::Tcp => quic_peers.remove(&address),
+    };
+}
+
+fn quic_address(address: &PeerAddress, quic_peers: &HashSet<SocketAddr>) -> Option<SocketAddr> {
+    match address {
+        PeerAddress::Ip(socket_address) if quic_peers.contains(socket_address) => Some(*socket_address),
+        _ => None,
+    }
+}
+
+/// What every outbound connection shares, handed to the connection cache to create new ones.
+#[derive(Clone)]
+pub struct ConnectionContext {
+    key_ring: Arc<KeyRing>,
+    peer_stats: Arc<PeerStats>,
+    events: Arc<Events>,
+    socket_options: SocketOptions,
+    resolver: Arc<Resolver>,
+    proxy: Option<Arc<Proxy>>,
+}
+
 pub struct Connection {
-    address: SocketAddr,
-    socket: Mutex<Option<TcpStream>>,
+    address: PeerAddress,
+    key_ring: Arc<KeyRing>,
+    peer_stats: Arc<PeerStats>,
+    events: Arc<Events>,
+    socket_options: SocketOptions,
+    resolver: Arc<Resolver>,
+    proxy: Option<Arc<Proxy>>,
+    socket: Mutex<Option<ConnectedSocket>>,
+}
+
+struct ConnectedSocket {
+    stream: TransportStream,
+    noise_session: Option<NoiseSession>,
+    // the local identity the noise session was keyed with
+    identity: Arc<LocalIdentity>,
 }
 
 impl Connection {
-    fn new(address: SocketAddr) -> Self {
+    fn new(address: PeerAddress, context: &ConnectionContext) -> Self {
         let socket = Mutex::new(None);
 
-        Self { address, socket }
+        Self {
+            address,
+            key_ring: context.key_ring.clone(),
+            peer_stats: context.peer_stats.clone(),
+            events: context.events.clone(),
+            socket_options: context.socket_options,
+            resolver: context.resolver.clone(),
+            proxy: context.proxy.clone(),
+            socket,
+        }
     }
 
+    #[tracing::instrument(name = "connection_send", skip_all, fields(bytes = data.len()))]
     async fn send(&self, data: Vec<u8>) -> SendResult {
         let mut socket = self.socket.lock().await;
         let socket_op = &mut (*socket);
 
-        let socket = Self::connect_and_set_socket_if_none(socket_op, self.address).await?;
+        let socket = self.connect_and_set_socket_if_none(socket_op).await?;
 
         let result = Self::write_data_to_stream(socket, data.clone()).await;
 
-        if result.is_err() {
+        if let Err(error) = result {
             #[cfg(debug)]
             info!("Failed to send data to {}. Attempting to reconnect and try again.", self.address);
 
             // There was an error sending data. The connection might have been previously closed.
             *socket_op = None;
+            self.peer_stats.record_reconnect(&self.address);
+            self.events.emit(ConnectionEvent::new(EventKind::Closed, &self.address).outbound().because(&error));
+            self.events.emit(ConnectionEvent::new(EventKind::Reconnect, &self.address).outbound().because(&error));
 
             // Since there was an error previously, try reconnecting to the socket and resending the data.
-            let socket = Self::connect_and_set_socket_if_none(socket_op, self.address).await?;
+            let socket = self.connect_and_set_socket_if_none(socket_op).await?;
             let result = Self::write_data_to_stream(socket, data).await;
 
             // If there is still an error even after the retry, return as failure to send.
             if let Err(error) = result {
-                return Err(SenderError::SendFailedError(error, self.address));
+                return Err(SenderError::SendFailedError(error, self.address.clone()));
             }
         }
 
         Ok(())
     }
 
-    async fn connect_and_set_socket_if_none(socket_op: &mut Option<TcpStream>, address: SocketAddr) -> Result<&mut TcpStream, SenderError> {
+    async fn connect_and_set_socket_if_none<'a>(&self, socket_op: &'a mut Option<ConnectedSocket>) -> Result<&'a mut ConnectedSocket, SenderError> {
+        let address = &self.address;
+
+        // A session keyed with a replaced signing key is re-established once the rotation grace period is over
+        if socket_op.as_ref().is_some_and(|socket| socket.noise_session.is_some() && socket.identity.is_expired()) {
+            #[cfg(debug)]
+            info!("Re-establishing noise session with {} after a signing key rotation", address);
+            *socket_op = None;
+            self.events.emit(ConnectionEvent::new(EventKind::Closed, address).outbound().because("signing key rotated"));
+        }
+
         let was_socket_none = socket_op.is_none();
 
         if was_socket_none {
-            let connection_stream = TcpStream::connect(address).await;
-
-            match connection_stream {
-                Ok(connection_stream) => *socket_op = Some(connection_stream),
-                Err(error) => return Err(SenderError::ConnectionFailedError(error, address)),
-            }
+            let connected = match &self.proxy {
+                Some(proxy) if proxy.applies_to(address) => proxy.connect(address, &self.socket_options, &self.resolver).await.map(TransportStream::Tcp),
+                _ => TransportStream::connect(address, &self.socket_options, &self.resolver).await,
+            };
+            let mut stream = match connected {
+                Ok(stream) => stream,
+                Err(error) => {
+                    self.events.emit(ConnectionEvent::new(EventKind::ConnectFailed, address).outbound().because(&error));
+                    return Err(SenderError::ConnectionFailedError(error, address.clone()));
+                }
+            };
+
+            let identity = self.key_ring.current();
+            let noise_session = match &identity.noise_identity {
+                Some(noise_identity) => match NoiseSession::initiate(&mut stream, noise_identity).await {
+                    Ok(noise_session) => Some(noise_session),
+                    Err(error) => {
+                        error!("Noise handshake with {} failed: {}", address, error);
+                        self.events.emit(ConnectionEvent::new(EventKind::ConnectFailed, address).outbound().because(&error));
+                        return Err(SenderError::HandshakeFailedError(error, address.clone()));
+                    }
+                },
+                None => None,
+            };
+
+            *socket_op = Some(ConnectedSocket { stream, noise_session, identity });
+            self.peer_stats.record_connect(address);
+            self.events.emit(ConnectionEvent::new(EventKind::Opened, address).outbound());
         }
 
         let socket = socket_op.as_mut().expect("Unexpected! This socket has already been checked to exist.");
@@ -190,16 +450,23 @@ impl Connection {
         Ok(socket)
     }
 
-    async fn write_data_to_stream(socket: &mut TcpStream, data: Vec<u8>) -> io::Result<()> {
+    async fn write_data_to_stream(socket: &mut ConnectedSocket, data: Vec<u8>) -> io::Result<()> {
+        let data = match &mut socket.noise_session {
+            Some(noise_session) => noise_session.encrypt(&data).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?,
+            None => data,
+        };
+
         let len = data.len() as u32;
-        socket.write_u32(len).await?;
-        socket.write_all(&data).await
+        socket.stream.write_u32(len).await?;
+        socket.stream.write_all(&data).await
     }
 }
 
 impl Drop for Connection {
     fn drop(&mut self) {
-        if let Some(mut stream) = self.socket.get_mut().take() {
+        if let Some(ConnectedSocket { mut stream, .. }) = self.socket.get_mut().take() {
+            self.events
+                .emit(ConnectionEvent::new(EventKind::Closed, &self.address).outbound().because("removed from the connection cache"));
             RUNTIME.spawn(async move {
                 stream.shutdown().await.ok();
             });
@@ -208,36 +475,41 @@ impl Drop for Connection {
 }
 
 pub trait ConnectionCache {
-    fn get_or_insert(&mut self, address: SocketAddr) -> Arc<Connection>;
-    fn remove(&mut self, address: &SocketAddr) -> Option<Arc<Connection>>;
+    fn get_or_insert(&mut self, address: &PeerAddress, context: &ConnectionContext) -> Arc<Connection>;
+    fn remove(&mut self, address: &PeerAddress) -> Option<Arc<Connection>>;
 }
 
-impl ConnectionCache for HashMap<SocketAddr, Arc<Connection>> {
-    fn get_or_insert(&mut self, address: SocketAddr) -> Arc<Connection> {
-        self.entry(address).or_insert_with(|| Arc::new(Connection::new(address))).clone()
+impl ConnectionCache for HashMap<PeerAddress, Arc<Connection>> {
+    fn get_or_insert(&mut self, address: &PeerAddress, context: &ConnectionContext) -> Arc<Connection> {
+        self.entry(address.clone()).or_insert_with(|| Arc::new(Connection::new(address.clone(), context))).clone()
     }
 
-    fn remove(&mut self, address: &SocketAddr) -> Option<Arc<Connection>> {
+    fn remove(&mut self, address: &PeerAddress) -> Option<Arc<Connection>> {
         self.remove(address)
     }
 }
 
-impl ConnectionCache for LruCache<SocketAddr, Arc<Connection>> {
-    fn get_or_insert(&mut self, address: SocketAddr) -> Arc<Connection> {
+impl ConnectionCache for LruCache<PeerAddress, Arc<Connection>> {
+    fn get_or_insert(&mut self, address: &PeerAddress, context: &ConnectionContext) -> Arc<Connection> {
         #[cfg(debug)]
         info!("LruCache stats, current_size: {}, capacity: {}", self.len(), self.cap());
-        match self.get(&address) {
+        match self.get(address) {
             Some(connection) => connection.clone(),
             None => {
-                let connection = Arc::new(Connection::new(address));
+                if self.len() == self.cap().get() {
+                    if let Some((evicted, _)) = self.peek_lru() {
+                        context.events.emit(ConnectionEvent::new(EventKind::Evicted, evicted).outbound().because("lru"));
+                    }
+                }
+                let connection = Arc::new(Connection::new(address.clone(), context));
                 // `put` used instead of push to avoid memory leak.
-                self.put(address, connection.clone());
+                self.put(address.clone(), connection.clone());
                 connection
             }
         }
     }
 
-    fn remove(&mut self, address: &SocketAddr) -> Option<Arc<Connection>> {
+    fn remove(&mut self, address: &PeerAddress) -> Option<Arc<Connection>> {
         self.pop(address)
     }
 }
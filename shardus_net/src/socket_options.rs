@@ -0,0 +1,195 @@
+use socket2::{Domain, Protocol, SockRef, Socket, TcpKeepalive, Type};
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpSocket, TcpStream};
+use tokio::task::JoinSet;
+
+// How long a connection attempt gets before the next address is tried as well, as recommended by RFC 8305
+const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// Tuning for the TCP listener and for the streams it accepts or the sender connects. Unset
+/// options keep the OS defaults.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SocketOptions {
+    // connections the kernel queues until they are accepted
+    pub backlog: u32,
+    pub reuse_address: bool,
+    // lets several processes listen on the same port, the kernel spreads connections between them
+    pub reuse_port: bool,
+    // Some(false) lets a listener on an IPv6 address accept IPv4 peers as well
+    pub only_v6: Option<bool>,
+    pub nodelay: bool,
+    // idle time before keepalive probes are sent, no keepalive when unset
+    pub keepalive: Option<Duration>,
+    pub send_buffer_size: Option<usize>,
+    pub recv_buffer_size: Option<usize>,
+}
+
+impl Default for SocketOptions {
+    // The defaults tokio binds and connects with
+    fn default() -> Self {
+        Self {
+            backlog: 1024,
+            reuse_address: true,
+            reuse_port: false,
+            only_v6: None,
+            nodelay: false,
+            keepalive: None,
+            send_buffer_size: None,
+            recv_buffer_size: None,
+        }
+    }
+}
+
+impl SocketOptions {
+    // bind: must be called within the runtime. Accepted streams inherit the buffer sizes of the listener.
+    pub(crate) fn bind(&self, address: SocketAddr) -> io::Result<TcpListener> {
+        let socket = Socket::new(Domain::for_address(address), Type::STREAM, Some(Protocol::TCP))?;
+        socket.set_reuse_address(self.reuse_address)?;
+        socket.set_reuse_port(self.reuse_port)?;
+        if let (true, Some(only_v6)) = (address.is_ipv6(), self.only_v6) {
+            socket.set_only_v6(only_v6)?;
+        }
+        self.set_buffer_sizes(SockRef::from(&socket))?;
+        socket.set_nonblocking(true)?;
+
+        socket.bind(&address.into())?;
+        socket.listen(self.backlog.min(i32::MAX as u32) as i32)?;
+        TcpListener::from_std(socket.into())
+    }
+
+    pub(crate) async fn connect(&self, address: SocketAddr) -> io::Result<TcpStream> {
+        let socket = if address.is_ipv4() { TcpSocket::new_v4()? } else { TcpSocket::new_v6()? };
+        // Buffer sizes are set before connecting so the window scale is negotiated for them
+        self.set_buffer_sizes(SockRef::from(&socket))?;
+
+        let stream = socket.connect(address).await?;
+        self.configure(&stream)?;
+        Ok(stream)
+    }
+
+    // connect_any: the first of the addresses to accept a connection. Attempts alternate between IPv6 and
+    // IPv4 (happy eyeballs), the next one starting once the last failed or CONNECTION_ATTEMPT_DELAY passed.
+    pub(crate) async fn connect_any(&self, addresses: Vec<SocketAddr>) -> io::Result<TcpStream> {
+        let mut remaining = interleave_families(addresses).into_iter();
+        let mut attempts = JoinSet::new();
+        let mut last_error = None;
+
+        loop {
+            if let Some(address) = remaining.next() {
+                let options = *self;
+                attempts.spawn(async move { options.connect(address).await });
+            } else if attempts.is_empty() {
+                return Err(last_error.unwrap_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Expected at least one address")));
+            }
+
+            let finished = if remaining.len() > 0 {
+                match tokio::time::timeout(CONNECTION_ATTEMPT_DELAY, attempts.join_next()).await {
+                    Ok(finished) => finished,
+                    Err(_) => continue,
+                }
+            } else {
+                attempts.join_next().await
+            };
+
+            // Dropping the set aborts the attempts still running
+            match finished {
+                Some(Ok(Ok(stream))) => return Ok(stream),
+                Some(Ok(Err(err))) => last_error = Some(err),
+                Some(Err(err)) => last_error = Some(io::Error::other(err)),
+                None => {}
+            }
+        }
+    }
+
+    // configure: the options set on every accepted or connected stream
+    pub(crate) fn configure(&self, stream: &TcpStream) -> io::Result<()> {
+        stream.set_nodelay(self.nodelay)?;
+
+        if let Some(keepalive) = self.keepalive {
+            SockRef::from(stream).set_tcp_keepalive(&TcpKeepalive::new().with_time(keepalive))?;
+        }
+
+        Ok(())
+    }
+
+    fn set_buffer_sizes(&self, socket: SockRef) -> io::Result<()> {
+        if let Some(send_buffer_size) = self.send_buffer_size {
+            socket.set_send_buffer_size(send_buffer_size)?;
+        }
+        if let Some(recv_buffer_size) = self.recv_buffer_size {
+            socket.set_recv_buffer_size(recv_buffer_size)?;
+        }
+
+        Ok(())
+    }
+}
+
+// interleave_families: alternate between address families, starting with the family of the first address
+fn interleave_families(addresses: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let first_is_ipv6 = addresses.first().is_some_and(|address| address.is_ipv6());
+    let (preferred, other): (Vec<_>, Vec<_>) = addresses.into_iter().partition(|address| address.is_ipv6() == first_is_ipv6);
+
+    let mut interleaved = Vec::with_capacity(preferred.len() + other.len());
+    let (mut preferred, mut other) = (preferred.into_iter(), other.into_iter());
+    loop {
+        match (preferred.next(), other.next()) {
+            (None, None) => return interleaved,
+            (preferred, other) => interleaved.extend(preferred.into_iter().chain(other)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::RUNTIME;
+
+    #[test]
+    fn test_reuse_port_and_stream_options() {
+        let options = SocketOptions {
+            reuse_port: true,
+            nodelay: true,
+            keepalive: Some(Duration::from_secs(30)),
+            recv_buffer_size: Some(256 * 1024),
+            ..Default::default()
+        };
+
+        RUNTIME.block_on(async {
+            let listener = options.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+            let address = listener.local_addr().unwrap();
+            // A second listener on the same port only binds with SO_REUSEPORT
+            let _second = options.bind(address).unwrap();
+            assert!(SocketOptions::default().bind(address).is_err());
+
+            let stream = options.connect(address).await.unwrap();
+            assert!(stream.nodelay().unwrap());
+            assert!(SockRef::from(&stream).keepalive().unwrap());
+            assert!(SockRef::from(&stream).recv_buffer_size().unwrap() >= 256 * 1024);
+        });
+    }
+
+    #[test]
+    fn test_connect_any_skips_refused_addresses() {
+        let addresses = |addresses: &[&str]| addresses.iter().map(|address| address.parse().unwrap()).collect::<Vec<SocketAddr>>();
+        assert_eq!(
+            interleave_families(addresses(&["[::1]:1", "[::1]:2", "127.0.0.1:1", "127.0.0.1:2"])),
+            addresses(&["[::1]:1", "127.0.0.1:1", "[::1]:2", "127.0.0.1:2"])
+        );
+
+        RUNTIME.block_on(async {
+            let options = SocketOptions::default();
+            let listener = options.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+            let open = listener.local_addr().unwrap();
+            // Bound but never listening, so connecting to it is refused
+            let closed = TcpSocket::new_v4().unwrap();
+            closed.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+            let closed = closed.local_addr().unwrap();
+
+            let stream = options.connect_any(vec![closed, open]).await.unwrap();
+            assert_eq!(stream.peer_addr().unwrap(), open);
+            assert!(options.connect_any(vec![closed]).await.is_err());
+        });
+    }
+}
@@ -1,26 +1,52 @@
+use super::connection_limit::{InboundConnectionStats, InboundConnections};
+use super::cpu_pool::CpuPoolStats;
+use super::histogram::{HistogramSummary, LatencyHistogram};
+use super::peer_stats::{PeerCounters, PeerStats, PeerStatsOrder};
+use super::rate_limit::RateLimiter;
 use super::ring_buffer::{RingBuffer, Stats as RingBufferStats};
+use super::routes::{RouteStats, RouteStatsSnapshot};
+use super::runtime::{runtime_stats, RuntimeStats};
+use super::transport::PeerAddress;
 use std::{
+    collections::BTreeMap,
     sync::{
         atomic::{AtomicUsize, Ordering},
-        Arc,
+        Arc, Mutex,
     },
     time::Duration,
 };
 
 const RING_BUFFER_SIZE: usize = 100;
 
+/// `Stats` shared between the JS thread and the metrics endpoint.
+pub(crate) type SharedStats = Arc<Mutex<Stats>>;
+
 pub(crate) struct Stats {
     pub outstanding_sends_buffer: RingBuffer<usize>,
     pub outstanding_receives_buffer: RingBuffer<usize>,
-    pub receive_elapsed_buffer: RingBuffer<Duration>,
+    receive_latency: LatencyHistogram,
+    send_latency: Arc<Mutex<LatencyHistogram>>,
     outstanding_sends: Arc<AtomicUsize>,
     outstanding_receives: Arc<AtomicUsize>,
+    cpu_pool: Arc<CpuPoolStats>,
+    peer_stats: Arc<PeerStats>,
+    rate_limiter: Arc<RateLimiter>,
+    inbound_connections: Arc<InboundConnections>,
+    route_stats: Arc<RouteStats>,
 }
 
 impl Stats {
-    pub(crate) fn new() -> (Self, Incrementers) {
+    pub(crate) fn new(
+        cpu_pool: Arc<CpuPoolStats>,
+        peer_stats: Arc<PeerStats>,
+        rate_limiter: Arc<RateLimiter>,
+        inbound_connections: Arc<InboundConnections>,
+        route_stats: Arc<RouteStats>,
+        latency_windows: &[Duration],
+    ) -> (Self, Incrementers) {
         let outstanding_sends = Arc::new(AtomicUsize::new(0));
         let outstanding_receives = Arc::new(AtomicUsize::new(0));
+        let send_latency = Arc::new(Mutex::new(LatencyHistogram::new(latency_windows)));
 
         (
             Self {
@@ -28,11 +54,19 @@ impl Stats {
                 outstanding_receives: outstanding_receives.clone(),
                 outstanding_sends_buffer: RingBuffer::new(RING_BUFFER_SIZE),
                 outstanding_receives_buffer: RingBuffer::new(RING_BUFFER_SIZE),
-                receive_elapsed_buffer: RingBuffer::new(RING_BUFFER_SIZE),
+                receive_latency: LatencyHistogram::new(latency_windows),
+                send_latency: send_latency.clone(),
+                cpu_pool,
+                peer_stats,
+                rate_limiter,
+                inbound_connections,
+                route_stats: route_stats.clone(),
             },
             Incrementers {
                 outstanding_sends,
                 outstanding_receives,
+                send_latency,
+                route_stats,
             },
         )
     }
@@ -47,26 +81,70 @@ impl Stats {
         self.outstanding_receives_buffer.put(outstanding);
     }
 
-    pub(crate) fn put_elapsed_receive(&mut self, elapsed: Duration) {
-        self.receive_elapsed_buffer.put(elapsed);
+    // put_elapsed_receive: from a message being queued for JS until the callback of its route is called
+    pub(crate) fn put_elapsed_receive(&mut self, route: &str, elapsed: Duration) {
+        self.receive_latency.record(elapsed);
+        self.route_stats.record_callback_latency(route, elapsed);
     }
 
     pub(crate) fn get_stats(&mut self) -> StatsResult {
+        let mut cpu_pool = self.cpu_pool.buffers();
+
         StatsResult {
             outstanding_sends: self.outstanding_sends_buffer.get_stats(),
             outstanding_receives: self.outstanding_receives_buffer.get_stats(),
-            receive_elapsed: self.receive_elapsed_buffer.get_stats(),
+            receive_latency: self.receive_latency.snapshot(),
+            send_latency: self.send_latency.lock().expect("Send latency lock poisoned").snapshot(),
+            cpu_pool_queue_depth: self.cpu_pool.queue_depth(),
+            cpu_pool_queue_depths: cpu_pool.queue_depth_buffer.get_stats(),
+            cpu_pool_queue_wait: cpu_pool.queue_wait.snapshot(),
+            cpu_pool_elapsed: cpu_pool.run_elapsed.snapshot(),
+            runtime: runtime_stats(),
+            rate_limited: self.rate_limiter.dropped(),
+            rate_limit_disconnects: self.rate_limiter.disconnected(),
+            inbound_connections: self.inbound_connections.stats(),
+            routes: self.route_stats.snapshot(),
+            unknown_route_drops: self.route_stats.unknown_route_drops(),
         }
     }
+
+    // outstanding: the sends and receives in flight right now
+    pub(crate) fn outstanding(&self) -> (usize, usize) {
+        (self.outstanding_sends.load(Ordering::Relaxed), self.outstanding_receives.load(Ordering::Relaxed))
+    }
+
+    pub(crate) fn send_errors(&self) -> BTreeMap<&'static str, u64> {
+        self.peer_stats.send_errors()
+    }
+
+    pub(crate) fn get_peer_stats(&self, top: Option<usize>, order: PeerStatsOrder) -> Vec<(PeerAddress, PeerCounters)> {
+        self.peer_stats.snapshot(top, order)
+    }
+
+    pub(crate) fn get_route_stats(&self) -> Vec<RouteStatsSnapshot> {
+        self.route_stats.snapshot()
+    }
 }
 
 #[derive(Clone)]
 pub(crate) struct Incrementers {
     outstanding_sends: Arc<AtomicUsize>,
     outstanding_receives: Arc<AtomicUsize>,
+    send_latency: Arc<Mutex<LatencyHistogram>>,
+    route_stats: Arc<RouteStats>,
 }
 
 impl Incrementers {
+    // record_send_latency: from the send call until its transport reported completion
+    pub(crate) fn record_send_latency(&self, elapsed: Duration) {
+        self.send_latency.lock().expect("Send latency lock poisoned").record(elapsed);
+    }
+
+    // record_route_send: a completed send of bytes payload bytes on route, "" for sends without one
+    pub(crate) fn record_route_send(&self, route: &str, bytes: usize, succeeded: bool) {
+        self.route_stats.record_send(route, bytes, succeeded);
+    }
+
     pub(crate) fn increment_outstanding_sends(&self) {
         self.outstanding_sends.fetch_add(1, Ordering::Relaxed);
     }
@@ -79,5 +157,18 @@ impl Incrementers {
 pub(crate) struct StatsResult {
     pub outstanding_sends: RingBufferStats<usize>,
     pub outstanding_receives: RingBufferStats<usize>,
-    pub receive_elapsed: RingBufferStats<Duration>,
+    pub receive_latency: Vec<(String, HistogramSummary)>,
+    pub send_latency: Vec<(String, HistogramSummary)>,
+    pub cpu_pool_queue_depth: usize,
+    pub cpu_pool_queue_depths: RingBufferStats<usize>,
+    pub cpu_pool_queue_wait: Vec<(String, HistogramSummary)>,
+    pub cpu_pool_elapsed: Vec<(String, HistogramSummary)>,
+    pub runtime: RuntimeStats,
+    // inbound frames dropped by each rate limit, and the connections closed for it
+    pub rate_limited: Vec<(&'static str, u64)>,
+    pub rate_limit_disconnects: u64,
+    pub inbound_connections: InboundConnectionStats,
+    pub routes: Vec<RouteStatsSnapshot>,
+    // received messages naming a route that is not registered
+    pub unknown_route_drops: u64,
 }
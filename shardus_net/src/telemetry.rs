@@ -0,0 +1,127 @@
+use crate::runtime::RUNTIME;
+
+use opentelemetry::trace::{TraceError, TracerProvider as _};
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::trace::TracerProvider;
+use opentelemetry_sdk::Resource;
+use std::fs::OpenOptions;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use thiserror::Error;
+use tracing::Level;
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::layer::SubscriberExt;
+
+const DEFAULT_SERVICE_NAME: &str = "shardus-net";
+
+/// Where message spans are exported. Both exporters can be enabled at once.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct TracingConfig {
+    // append one JSON line per closed span, carrying its fields and those of its parents
+    pub json_file: Option<PathBuf>,
+    // export spans over OTLP/gRPC to a collector such as http://127.0.0.1:4317
+    pub otlp_endpoint: Option<String>,
+    pub service_name: Option<String>,
+}
+
+#[derive(Error, Debug)]
+#[allow(clippy::enum_variant_names)]
+pub enum TracingError {
+    #[error("Tracing has already been configured")]
+    AlreadyConfiguredError,
+    #[error("Tracing needs a JSON file, an OTLP endpoint or both")]
+    NoExporterError,
+    #[error("Failed to open the trace file. {0}")]
+    OpenFileError(#[from] std::io::Error),
+    #[error("Failed to create the OTLP exporter. {0}")]
+    ExporterError(#[from] TraceError),
+}
+
+// configure_tracing: install the process wide subscriber. Until this is called every span is disabled
+// and costs next to nothing. Only spans of this crate are exported, not those of the libraries below it.
+pub(crate) fn configure_tracing(config: TracingConfig) -> Result<(), TracingError> {
+    if tracing::dispatcher::has_been_set() {
+        return Err(TracingError::AlreadyConfiguredError);
+    }
+    if config.json_file.is_none() && config.otlp_endpoint.is_none() {
+        return Err(TracingError::NoExporterError);
+    }
+
+    let json_layer = match &config.json_file {
+        Some(path) => {
+            let file = OpenOptions::new().create(true).append(true).open(path)?;
+            let layer = tracing_subscriber::fmt::layer()
+                .json()
+                .with_span_events(FmtSpan::CLOSE)
+                .with_current_span(true)
+                .with_span_list(true)
+                .with_writer(Mutex::new(file));
+            Some(layer)
+        }
+        None => None,
+    };
+
+    let otlp_layer = match &config.otlp_endpoint {
+        Some(endpoint) => {
+            // The gRPC channel and the batch export task run on the shared runtime
+            let _runtime = RUNTIME.enter();
+            let exporter = opentelemetry_otlp::SpanExporter::builder().with_tonic().with_endpoint(endpoint.clone()).build()?;
+            let service_name = config.service_name.clone().unwrap_or_else(|| DEFAULT_SERVICE_NAME.to_string());
+            let provider = TracerProvider::builder()
+                .with_batch_exporter(exporter, opentelemetry_sdk::runtime::Tokio)
+                .with_resource(Resource::new(vec![KeyValue::new("service.name", service_name)]))
+                .build();
+            let tracer = provider.tracer(DEFAULT_SERVICE_NAME);
+            opentelemetry::global::set_tracer_provider(provider);
+
+            Some(tracing_opentelemetry::layer().with_tracer(tracer))
+        }
+        None => None,
+    };
+
+    let subscriber = tracing_subscriber::registry()
+        .with(Targets::new().with_target("shardus_net", Level::INFO))
+        .with(json_layer)
+        .with(otlp_layer);
+
+    tracing::subscriber::set_global_default(subscriber).map_err(|_| TracingError::AlreadyConfiguredError)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_file_export() {
+        assert!(matches!(configure_tracing(TracingConfig::default()), Err(TracingError::NoExporterError)));
+
+        let path = std::env::temp_dir().join(format!("shardus-net-trace-{}.json", std::process::id()));
+        configure_tracing(TracingConfig {
+            json_file: Some(path.clone()),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let span = tracing::info_span!("send_with_header", uuid = "3f1c", tracker_id = "tracker-7");
+        span.in_scope(|| tracing::info_span!("connection_send").in_scope(|| {}));
+        drop(span);
+
+        let exported = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+        // Other tests may be tracing into the same file by now
+        let lines: Vec<&str> = exported.lines().filter(|line| line.contains("tracker-7")).collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains("\"name\":\"connection_send\"") && lines[0].contains("\"tracker_id\":\"tracker-7\""));
+        assert!(lines[1].contains("\"name\":\"send_with_header\"") && lines[1].contains("\"uuid\":\"3f1c\""));
+
+        assert!(matches!(
+            configure_tracing(TracingConfig {
+                json_file: Some(path),
+                ..Default::default()
+            }),
+            Err(TracingError::AlreadyConfiguredError)
+        ));
+    }
+}
@@ -0,0 +1,65 @@
+use std::time::Instant;
+
+/// Holds up to `capacity` tokens and refills at `rate` tokens per second. Taking more tokens than
+/// are left fails without taking any.
+#[derive(Clone, Debug)]
+pub(crate) struct TokenBucket {
+    capacity: f64,
+    rate: f64,
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    // new: starts full, so a burst of up to capacity passes straight away
+    pub(crate) fn new(capacity: f64, rate: f64, now: Instant) -> Self {
+        Self {
+            capacity,
+            rate,
+            tokens: capacity,
+            refilled_at: now,
+        }
+    }
+
+    pub(crate) fn try_take(&mut self, amount: f64, now: Instant) -> bool {
+        if !self.has(amount, now) {
+            return false;
+        }
+
+        self.tokens -= amount;
+        true
+    }
+
+    // has: whether try_take would succeed, without taking anything
+    pub(crate) fn has(&mut self, amount: f64, now: Instant) -> bool {
+        self.refill(now);
+        self.tokens >= amount
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.refilled_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.refilled_at = now;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_burst_then_refill() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(3.0, 10.0, start);
+
+        assert!((0..3).all(|_| bucket.try_take(1.0, start)));
+        assert!(!bucket.try_take(1.0, start));
+
+        // A tenth of a second buys one token back, never more than the capacity
+        assert!(bucket.try_take(1.0, start + Duration::from_millis(100)));
+        assert!(!bucket.try_take(1.0, start + Duration::from_millis(100)));
+        assert!(!bucket.try_take(4.0, start + Duration::from_secs(60)));
+        assert!(bucket.try_take(3.0, start + Duration::from_secs(60)));
+    }
+}
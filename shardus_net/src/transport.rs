@@ -0,0 +1,322 @@
+use crate::resolver::Resolver;
+use crate::socket_options::SocketOptions;
+
+use log::warn;
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::os::unix::fs::FileTypeExt;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::{TcpListener, TcpStream, UnixListener, UnixStream};
+
+// Hosts of the form `unix:/path/to/socket` address a unix domain socket instead of a TCP endpoint.
+pub const UNIX_SOCKET_PREFIX: &str = "unix:";
+
+// Frames announcing more bytes are refused before anything is allocated for them, on every transport
+pub(crate) const MAX_FRAME_SIZE: u32 = 64 * 1024 * 1024;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PeerAddress {
+    Ip(SocketAddr),
+    Unix(PathBuf),
+    // a host name and port, resolved on the runtime whenever a connection to it is made
+    Host(String, u16),
+}
+
+impl PeerAddress {
+    // resolve: turn a JS host/port pair into an address, the port is ignored for unix socket paths
+    pub fn resolve(host: &str, port: u16) -> io::Result<Self> {
+        if let Some(path) = host.strip_prefix(UNIX_SOCKET_PREFIX) {
+            return Ok(PeerAddress::Unix(PathBuf::from(path)));
+        }
+
+        let mut addresses = (host, port).to_socket_addrs()?;
+        let address = addresses.next().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Expected at least one address"))?;
+
+        Ok(PeerAddress::Ip(address))
+    }
+
+    // parse: like resolve without looking host names up, so it never blocks on DNS
+    pub fn parse(host: &str, port: u16) -> Self {
+        if let Some(path) = host.strip_prefix(UNIX_SOCKET_PREFIX) {
+            return PeerAddress::Unix(PathBuf::from(path));
+        }
+
+        match host.parse::<IpAddr>() {
+            Ok(ip) => PeerAddress::Ip(SocketAddr::new(ip, port)),
+            Err(_) => PeerAddress::Host(host.to_string(), port),
+        }
+    }
+
+    // host: the host string reported to JS, unix peers are reported with their socket path (if any) and port 0
+    pub fn host(&self) -> String {
+        match self {
+            PeerAddress::Ip(address) => address.ip().to_string(),
+            PeerAddress::Unix(path) => format!("{}{}", UNIX_SOCKET_PREFIX, path.display()),
+            PeerAddress::Host(host, _) => host.clone(),
+        }
+    }
+
+    pub fn port(&self) -> u16 {
+        match self {
+            PeerAddress::Ip(address) => address.port(),
+            PeerAddress::Unix(_) => 0,
+            PeerAddress::Host(_, port) => *port,
+        }
+    }
+}
+
+impl From<SocketAddr> for PeerAddress {
+    fn from(address: SocketAddr) -> Self {
+        PeerAddress::Ip(address)
+    }
+}
+
+impl fmt::Display for PeerAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PeerAddress::Ip(address) => write!(f, "{}", address),
+            PeerAddress::Unix(path) => write!(f, "{}{}", UNIX_SOCKET_PREFIX, path.display()),
+            PeerAddress::Host(host, port) => write!(f, "{}:{}", host, port),
+        }
+    }
+}
+
+/// The transport used to reach an IP peer. Unix socket peers always use their stream socket.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Transport {
+    Tcp,
+    Quic,
+}
+
+impl Transport {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "tcp" => Some(Transport::Tcp),
+            "quic" => Some(Transport::Quic),
+            _ => None,
+        }
+    }
+}
+
+/// A connected stream over either transport. Both carry the same length-prefixed frames.
+pub enum TransportStream {
+    Tcp(TcpStream),
+    Unix(UnixStream),
+}
+
+impl TransportStream {
+    pub(crate) async fn connect(address: &PeerAddress, options: &SocketOptions, resolver: &Resolver) -> io::Result<Self> {
+        match address {
+            PeerAddress::Ip(address) => Ok(TransportStream::Tcp(options.connect(*address).await?)),
+            PeerAddress::Unix(path) => Ok(TransportStream::Unix(UnixStream::connect(path).await?)),
+            PeerAddress::Host(host, port) => Ok(TransportStream::Tcp(options.connect_any(resolver.resolve(host, *port).await?).await?)),
+        }
+    }
+}
+
+impl AsyncRead for TransportStream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            TransportStream::Tcp(stream) => Pin::new(stream).poll_read(cx, buf),
+            TransportStream::Unix(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for TransportStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            TransportStream::Tcp(stream) => Pin::new(stream).poll_write(cx, buf),
+            TransportStream::Unix(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            TransportStream::Tcp(stream) => Pin::new(stream).poll_flush(cx),
+            TransportStream::Unix(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            TransportStream::Tcp(stream) => Pin::new(stream).poll_shutdown(cx),
+            TransportStream::Unix(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
+
+pub enum TransportListener {
+    // the options are applied to every accepted stream
+    Tcp(TcpListener, SocketOptions),
+    Unix(UnixListener),
+}
+
+impl TransportListener {
+    pub async fn bind(address: &PeerAddress, options: &SocketOptions) -> io::Result<Self> {
+        match address {
+            PeerAddress::Ip(address) => Ok(TransportListener::Tcp(options.bind(*address)?, *options)),
+            PeerAddress::Host(host, port) => {
+                let address = tokio::net::lookup_host((host.as_str(), *port))
+                    .await?
+                    .next()
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("No addresses found for {}", host)))?;
+                Ok(TransportListener::Tcp(options.bind(address)?, *options))
+            }
+            PeerAddress::Unix(path) => {
+                remove_stale_socket(path).await?;
+                Ok(TransportListener::Unix(UnixListener::bind(path)?))
+            }
+        }
+    }
+
+    // local_addr: the address actually bound, with the port the OS picked for port 0
+    pub fn local_addr(&self) -> io::Result<PeerAddress> {
+        match self {
+            TransportListener::Tcp(listener, _) => Ok(PeerAddress::Ip(listener.local_addr()?)),
+            TransportListener::Unix(listener) => {
+                let address = listener.local_addr()?;
+                let path = address.as_pathname().ok_or_else(|| io::Error::new(io::ErrorKind::AddrNotAvailable, "Expected a named unix socket"))?;
+                Ok(PeerAddress::Unix(path.to_path_buf()))
+            }
+        }
+    }
+
+    pub async fn accept(&self) -> io::Result<(TransportStream, PeerAddress)> {
+        match self {
+            TransportListener::Tcp(listener, options) => {
+                let (stream, remote_addr) = listener.accept().await?;
+                // A stream that cannot be tuned still works, so it is kept
+                if let Err(err) = options.configure(&stream) {
+                    warn!("Failed to set socket options on the connection from {}: {}", remote_addr, err);
+                }
+                Ok((TransportStream::Tcp(stream), PeerAddress::Ip(remote_addr)))
+            }
+            TransportListener::Unix(listener) => {
+                let (stream, remote_addr) = listener.accept().await?;
+                let path = remote_addr.as_pathname().map(|path| path.to_path_buf()).unwrap_or_default();
+                Ok((TransportStream::Unix(stream), PeerAddress::Unix(path)))
+            }
+        }
+    }
+}
+
+// remove_stale_socket: a socket file left behind by a previous process would make the bind fail. Only a
+// socket nothing accepts on is removed, a live socket or any other file at path is reported as in use.
+async fn remove_stale_socket(path: &Path) -> io::Result<()> {
+    let metadata = match std::fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err),
+    };
+
+    let in_use = || io::Error::new(io::ErrorKind::AddrInUse, format!("{} is in use", path.display()));
+    if !metadata.file_type().is_socket() {
+        return Err(in_use());
+    }
+    match UnixStream::connect(path).await {
+        Err(err) if err.kind() == io::ErrorKind::ConnectionRefused => std::fs::remove_file(path),
+        _ => Err(in_use()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::RUNTIME;
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[test]
+    fn test_resolve() {
+        assert_eq!(PeerAddress::resolve("unix:/tmp/shardus.sock", 1234).unwrap(), PeerAddress::Unix(PathBuf::from("/tmp/shardus.sock")));
+        assert_eq!(PeerAddress::resolve("127.0.0.1", 1234).unwrap(), PeerAddress::Ip("127.0.0.1:1234".parse().unwrap()));
+
+        assert_eq!(PeerAddress::parse("::1", 1234), PeerAddress::Ip("[::1]:1234".parse().unwrap()));
+        assert_eq!(PeerAddress::parse("archiver.example", 1234), PeerAddress::Host("archiver.example".to_string(), 1234));
+        assert_eq!(PeerAddress::parse("archiver.example", 1234).to_string(), "archiver.example:1234");
+    }
+
+    #[test]
+    fn test_transport_from_name() {
+        assert_eq!(Transport::from_name("tcp"), Some(Transport::Tcp));
+        assert_eq!(Transport::from_name("quic"), Some(Transport::Quic));
+        assert_eq!(Transport::from_name("udp"), None);
+    }
+
+    #[test]
+    fn test_host_and_port() {
+        let tcp = PeerAddress::Ip("10.0.0.1:9001".parse().unwrap());
+        assert_eq!(tcp.host(), "10.0.0.1");
+        assert_eq!(tcp.port(), 9001);
+
+        let unix = PeerAddress::Unix(PathBuf::from("/tmp/shardus.sock"));
+        assert_eq!(unix.host(), "unix:/tmp/shardus.sock");
+        assert_eq!(unix.port(), 0);
+        assert_eq!(unix.to_string(), "unix:/tmp/shardus.sock");
+    }
+
+    #[test]
+    fn test_local_addr_of_port_zero() {
+        RUNTIME.block_on(async {
+            let listener = TransportListener::bind(&PeerAddress::Ip("127.0.0.1:0".parse().unwrap()), &SocketOptions::default()).await.unwrap();
+            let bound = listener.local_addr().unwrap();
+            assert_ne!(bound.port(), 0);
+
+            assert!(TransportListener::bind(&bound, &SocketOptions::default()).await.is_err());
+        });
+    }
+
+    #[test]
+    fn test_unix_round_trip() {
+        let path = std::env::temp_dir().join(format!("shardus-net-{}.sock", uuid::Uuid::new_v4()));
+        let address = PeerAddress::Unix(path.clone());
+
+        RUNTIME.block_on(async move {
+            let listener = TransportListener::bind(&address, &SocketOptions::default()).await.unwrap();
+            let accepted = tokio::spawn(async move {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let len = stream.read_u32().await.unwrap();
+                let mut buffer = vec![0u8; len as usize];
+                stream.read_exact(&mut buffer).await.unwrap();
+                buffer
+            });
+
+            let mut stream = TransportStream::connect(&address, &SocketOptions::default(), &Resolver::new(Duration::ZERO)).await.unwrap();
+            stream.write_u32(5).await.unwrap();
+            stream.write_all(b"hello").await.unwrap();
+
+            assert_eq!(accepted.await.unwrap(), b"hello".to_vec());
+        });
+
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn test_unix_bind_only_replaces_stale_sockets() {
+        let path = std::env::temp_dir().join(format!("shardus-net-{}.sock", uuid::Uuid::new_v4()));
+        let address = PeerAddress::Unix(path.clone());
+
+        RUNTIME.block_on(async {
+            std::fs::write(&path, b"not a socket").unwrap();
+            let err = TransportListener::bind(&address, &SocketOptions::default()).await.err().unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+            assert_eq!(std::fs::read(&path).unwrap(), b"not a socket");
+            std::fs::remove_file(&path).unwrap();
+
+            // Closing a listener leaves its socket file behind
+            drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+            let live = TransportListener::bind(&address, &SocketOptions::default()).await.unwrap();
+
+            let err = TransportListener::bind(&address, &SocketOptions::default()).await.err().unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+            drop(live);
+        });
+
+        std::fs::remove_file(path).ok();
+    }
+}
@@ -3,14 +3,31 @@ import {
   AppHeader,
   AugmentedData,
   CombinedHeader,
+  ConnectionEvent,
   GetSenderAddressResult,
   ListenerResponder,
+  LogLevel,
   NewAugData,
+  PeerStats,
+  PeerStatsOpts,
+  RouteStats,
+  RateLimit,
+  RateLimitOpts,
+  IpFilterOpts,
+  ListenAddress,
+  ListenOpts,
+  ListeningServer,
   RemoteSender,
   ResponseCallback,
+  RouteHandler,
+  RouteOpts,
+  ROUTE_HEADER_VERSION,
+  RuntimeOpts,
+  RustLogRecord,
   Sign,
   SnOpts,
   TimeoutCallback,
+  TracingOpts,
   validateSnOpts,
 } from './types'
 import { base64BufferReviver, stringifyData } from './util/Encoding'
@@ -20,6 +37,7 @@ import { TTLMap } from './util/TTLMap'
 const net = require('../../shardus-net.node')
 
 const DEFAULT_ADDRESS = '0.0.0.0'
+const UNIX_SOCKET_PREFIX = 'unix:'
 
 //todo make this a dynamic config or connect to shardus core log levels
 const verbose_logs = false
@@ -40,23 +58,113 @@ export const getSenderAddress = (raw_tx: string): GetSenderAddressResult => {
   return result
 }
 
+/**
+ * Forwards log records of the native side at or above `level` to `callback`. Records beyond
+ * `maxPerSecond` (default 200) are dropped, and the next record through is preceded by a warning with
+ * the number dropped. Calling it again replaces the callback and level.
+ */
+export const setLogger = (callback: (record: RustLogRecord) => void, level: LogLevel, maxPerSecond?: number) => {
+  net.setLogger(callback, level, maxPerSecond)
+  logFlags.net_rust = level !== 'off'
+}
+
+/**
+ * Configures the runtime that drives all sockets. It is shared by every Sn instance and built when
+ * the first one is created, so this must be called before that. Throws if it is called too late.
+ */
+export const configureRuntime = (opts: RuntimeOpts) => {
+  net.configureRuntime({
+    worker_threads: opts.workerThreads,
+    max_blocking_threads: opts.maxBlockingThreads,
+    thread_name_prefix: opts.threadNamePrefix,
+    current_thread: opts.currentThread,
+  })
+}
+
+/**
+ * Exports spans of every send and receive, tagged with the uuid and tracker_id of the message header,
+ * to a JSON file, an OTLP collector or both. Can only be called once per process.
+ */
+export const configureTracing = (opts: TracingOpts) => {
+  net.configureTracing({
+    json_file: opts.jsonFile,
+    otlp_endpoint: opts.otlpEndpoint,
+    service_name: opts.serviceName,
+  })
+}
+
+const toNativeRateLimit = (limit?: RateLimit) =>
+  limit && {
+    messages_per_second: limit.messagesPerSecond,
+    bytes_per_second: limit.bytesPerSecond,
+  }
+
+const toNativeRateLimits = (opts: RateLimitOpts) => ({
+  per_ip: toNativeRateLimit(opts.perIp),
+  per_signer: toNativeRateLimit(opts.perSigner),
+  disconnect: opts.disconnect,
+})
+
 export const Sn = (opts: SnOpts) => {
   validateSnOpts(opts)
 
-  const PORT = opts.port
+  // reassigned to the port actually bound once listening on port 0
+  let PORT = opts.port
   const ADDRESS = opts.address || DEFAULT_ADDRESS
   const USE_LRU_CACHE = (opts.senderOpts && opts.senderOpts.useLruCache) || false
   const LRU_SIZE = (opts.senderOpts && opts.senderOpts.lruSize) || 1028
   const HASH_KEY = opts.crypto.hashKey
   const SIGNING_SECRET_KEY_HEX = opts.crypto.signingSecretKeyHex
+  const USE_NOISE_SESSIONS = opts.crypto.useNoiseSessions || false
+  const ENABLE_QUIC = (opts.quicOpts && opts.quicOpts.enabled) || false
+  const VERIFY_BATCH_SIZE = (opts.verifyOpts && opts.verifyOpts.batchSize) || 0
+  const VERIFY_BATCH_WINDOW_US = (opts.verifyOpts && opts.verifyOpts.batchWindowUs) || 500
+  const CPU_POOL_THREADS = opts.cpuPoolOpts && opts.cpuPoolOpts.threads
+  const LATENCY_WINDOWS_MS = opts.statsOpts && opts.statsOpts.latencyWindowsMs
+  const METRICS_PORT = opts.statsOpts && opts.statsOpts.metricsPort
 
   const HEADER_OPTS = opts.headerOpts || {
     sendHeaderVersion: 0,
   }
 
-  const _net = net.Sn(PORT, ADDRESS, USE_LRU_CACHE, LRU_SIZE, HASH_KEY, SIGNING_SECRET_KEY_HEX)
+  // routes travel in the V2 header, so headers naming one are sent as V2 whatever sendHeaderVersion is
+  const headerVersionFor = (headers: AppHeader[]) =>
+    headers.some((header) => header.route !== undefined) ? ROUTE_HEADER_VERSION : HEADER_OPTS.sendHeaderVersion
+  const routeOf = (header: AppHeader) => (header.route === undefined ? undefined : String(header.route))
+
+  const _net = net.Sn(PORT, ADDRESS, USE_LRU_CACHE, LRU_SIZE, HASH_KEY, SIGNING_SECRET_KEY_HEX, {
+    use_noise_sessions: USE_NOISE_SESSIONS,
+    enable_quic: ENABLE_QUIC,
+    verify_batch_size: VERIFY_BATCH_SIZE,
+    verify_batch_window_us: VERIFY_BATCH_WINDOW_US,
+    cpu_pool_threads: CPU_POOL_THREADS,
+    latency_windows_ms: LATENCY_WINDOWS_MS,
+    metrics_port: METRICS_PORT,
+    rate_limits: opts.rateLimitOpts && toNativeRateLimits(opts.rateLimitOpts),
+    connection_limits: opts.connectionLimitOpts && {
+      max_connections: opts.connectionLimitOpts.maxConnections,
+      max_per_ip: opts.connectionLimitOpts.maxPerIp,
+      on_limit: opts.connectionLimitOpts.onLimit,
+      idle_timeout_ms: opts.connectionLimitOpts.idleTimeoutMs,
+      frame_timeout_ms: opts.connectionLimitOpts.frameTimeoutMs,
+    },
+    ip_filter: opts.ipFilterOpts,
+    trusted_proxies: opts.trustedProxies,
+    dns_cache_ttl_ms: opts.senderOpts && opts.senderOpts.dnsCacheTtlMs,
+    proxy: opts.senderOpts && opts.senderOpts.proxy,
+    socket_options: opts.socketOpts && {
+      backlog: opts.socketOpts.backlog,
+      reuse_address: opts.socketOpts.reuseAddress,
+      reuse_port: opts.socketOpts.reusePort,
+      only_v6: opts.socketOpts.onlyV6,
+      nodelay: opts.socketOpts.nodelay,
+      keepalive_ms: opts.socketOpts.keepaliveMs,
+      send_buffer_size: opts.socketOpts.sendBufferSize,
+      recv_buffer_size: opts.socketOpts.recvBufferSize,
+    },
+  })
 
-  net.setLoggingEnabled(false)
+  net.setLoggingEnabled(logFlags.net_rust)
 
   // we're going to keep track of response IDs here
   const responseUUIDMapping: {
@@ -116,6 +224,55 @@ export const Sn = (opts: SnOpts) => {
     }
   }
 
+  /**
+   * Binds onResponse to the request UUID so the reply can be matched, and calls onTimeout
+   * if no reply arrives in time. A timeout of 0 registers nothing.
+   */
+  const _registerResponseHandler = (
+    augData: AugmentedData,
+    timeout: number,
+    onResponse: ResponseCallback,
+    onTimeout: TimeoutCallback
+  ) => {
+    if (timeout !== 0) {
+      const timer = setTimeout(() => {
+        const mapping = responseUUIDMapping[augData.UUID]
+        timedOutUUIDMapping.set(
+          augData.UUID,
+          {
+            timedOutAt: Date.now(),
+            requestCreatedAt: mapping !== undefined ? mapping.timestamp : 0,
+          },
+          retainTimedOutEntriesForMillis,
+          (key, value) => {
+            /* prettier-ignore */ if(logFlags.net_verbose) console.log(`_sendAug: request id ${key}: expired from timedOutUUIDMapping at ${value.timedOutAt}, request created at ${value.requestCreatedAt}}`)
+            /* prettier-ignore */ if(logFlags.net_verbose) histogram.logData((Date.now() - value.requestCreatedAt) / 1000)
+          }
+        )
+        /* prettier-ignore */ if(logFlags.net_verbose) console.log(`_sendAug: request id ${augData.UUID}: timed out after ${Date.now() - mapping.timestamp}ms`)
+        /* prettier-ignore */ if(logFlags.net_verbose) console.log(`_sendAug: request id ${augData.UUID}: detailed aug data: ${JSON.stringify(augData)}`)
+
+        //should we clear the request socket here?
+        //should be be logging better at this level
+        //maybe a counter service could be passed to this library?
+
+        delete responseUUIDMapping[augData.UUID]
+        onTimeout()
+      }, timeout)
+
+      // this is where we bind the response callback to the UUID
+      // later extractUUIDHandleData will call this callback if it
+      // finds a UUID match.
+      responseUUIDMapping[augData.UUID] = {
+        callback: (data: unknown, appHeader?: AppHeader, sign?: Sign) => {
+          clearTimeout(timer)
+          onResponse(data, appHeader, sign)
+        },
+        timestamp: Date.now(),
+      }
+    }
+  }
+
   /**
    * This sends our data the that is wrapped in an augData structure
    * a new function will be added similar to this one to send data with header
@@ -191,44 +348,7 @@ export const Sn = (opts: SnOpts) => {
         throw error
       }
 
-      // a timeout of 0 means no return message is expected.
-      if (timeout !== 0) {
-        const timer = setTimeout(() => {
-          const mapping = responseUUIDMapping[augData.UUID]
-          timedOutUUIDMapping.set(
-            augData.UUID,
-            {
-              timedOutAt: Date.now(),
-              requestCreatedAt: mapping !== undefined ? mapping.timestamp : 0,
-            },
-            retainTimedOutEntriesForMillis,
-            (key, value) => {
-              /* prettier-ignore */ if(logFlags.net_verbose) console.log(`_sendAug: request id ${key}: expired from timedOutUUIDMapping at ${value.timedOutAt}, request created at ${value.requestCreatedAt}}`)
-              /* prettier-ignore */ if(logFlags.net_verbose) histogram.logData((Date.now() - value.requestCreatedAt) / 1000)
-            }
-          )
-          /* prettier-ignore */ if(logFlags.net_verbose) console.log(`_sendAug: request id ${augData.UUID}: timed out after ${Date.now() - mapping.timestamp}ms`)
-          /* prettier-ignore */ if(logFlags.net_verbose) console.log(`_sendAug: request id ${augData.UUID}: detailed aug data: ${JSON.stringify(augData)}`)
-
-          //should we clear the request socket here?
-          //should be be logging better at this level
-          //maybe a counter service could be passed to this library?
-
-          delete responseUUIDMapping[augData.UUID]
-          onTimeout()
-        }, timeout)
-
-        // this is where we bind the response callback to the UUID
-        // later extractUUIDHandleData will call this callback if it
-        // finds a UUID match.
-        responseUUIDMapping[augData.UUID] = {
-          callback: (data: unknown, appHeader?: AppHeader, sign?: Sign) => {
-            clearTimeout(timer)
-            onResponse(data, appHeader, sign)
-          },
-          timestamp: Date.now(),
-        }
-      }
+      _registerResponseHandler(augData, timeout, onResponse, onTimeout)
     })
   }
 
@@ -272,6 +392,7 @@ export const Sn = (opts: SnOpts) => {
         tracker_id: header.tracker_id,
         verification_data: header.verification_data,
         compression: header.compression,
+        route: routeOf(header),
       }
 
       return _wrappedSendAug(
@@ -282,7 +403,7 @@ export const Sn = (opts: SnOpts) => {
         onResponse,
         onTimeout,
         {
-          version: HEADER_OPTS.sendHeaderVersion,
+          version: headerVersionFor([header]),
           headerData: combinedHeader,
         },
         awaitProcessing
@@ -293,6 +414,78 @@ export const Sn = (opts: SnOpts) => {
     }
   }
 
+  /**
+   * Sends a different payload and header to each destination. All messages are signed in a single
+   * pass on the Rust side, which is cheaper than one sendWithHeader call per destination.
+   * Every destination gets its own request id, so responses are matched per destination.
+   *
+   * @returns once every send has completed, whether all of them succeeded and the errors of those that failed
+   * @throws if ports, addresses, payloads and headers do not all have the same length
+   */
+  const multiSendWithDistinctPayloads = async (
+    ports: number[],
+    addresses: string[],
+    payloads: unknown[],
+    headers: AppHeader[],
+    timeout = 0,
+    onResponse: ResponseCallback = noop,
+    onTimeout: TimeoutCallback = noop,
+    awaitProcessing: boolean = true
+  ) => {
+    if (addresses.length !== ports.length || payloads.length !== ports.length || headers.length !== ports.length) {
+      throw new Error('multiSendWithDistinctPayloads: ports, addresses, payloads and headers must all have the same length')
+    }
+    // no send would ever complete to resolve the promise below
+    if (ports.length === 0) return { success: true, errors: [] }
+
+    const msgDir: 'ask' | 'tell' = onResponse === noop ? 'tell' : 'ask'
+
+    const stringifiedHeaders: string[] = []
+    const stringifiedPayloads: string[] = []
+    const augDatas: AugmentedData[] = []
+    for (let i = 0; i < payloads.length; i++) {
+      const UUID = uuid()
+      const augData: AugmentedData = NewAugData(payloads[i], UUID, PORT, ADDRESS, timeout, msgDir)
+      const combinedHeader: CombinedHeader = {
+        uuid: UUID,
+        sender_id: headers[i].sender_id,
+        tracker_id: headers[i].tracker_id,
+        verification_data: headers[i].verification_data,
+        compression: headers[i].compression,
+        route: routeOf(headers[i]),
+      }
+      augDatas.push(augData)
+      stringifiedPayloads.push(stringifyData(augData, opts.customStringifier))
+      stringifiedHeaders.push(stringifyData(combinedHeader, opts.customStringifier))
+    }
+
+    const result = new Promise<{ success: boolean; errors: string[] }>((resolve) => {
+      const errors: string[] = []
+      let remaining = ports.length
+      _net.multi_send_with_distinct_payloads(
+        ports,
+        addresses,
+        headerVersionFor(headers),
+        stringifiedHeaders,
+        stringifiedPayloads,
+        (error) => {
+          if (error) errors.push(error)
+          remaining -= 1
+          if (remaining === 0) resolve({ success: errors.length === 0, errors })
+        },
+        awaitProcessing
+      )
+      // without awaitProcessing the completion callback is never called
+      if (!awaitProcessing) resolve({ success: true, errors })
+    })
+
+    for (const augData of augDatas) {
+      _registerResponseHandler(augData, timeout, onResponse, onTimeout)
+    }
+
+    return result
+  }
+
   const sendWithHeader = async (
     port: number,
     address: string,
@@ -317,10 +510,11 @@ export const Sn = (opts: SnOpts) => {
       tracker_id: header.tracker_id,
       verification_data: header.verification_data,
       compression: header.compression,
+      route: routeOf(header),
     }
 
     return _wrappedSendAug(port, address, augData, timeout, onResponse, onTimeout, {
-      version: HEADER_OPTS.sendHeaderVersion,
+      version: headerVersionFor([header]),
       headerData: combinedHeader,
     })
   }
@@ -345,140 +539,182 @@ export const Sn = (opts: SnOpts) => {
     return _wrappedSendAug(port, address, augData, timeout, onResponse, onTimeout)
   }
 
-  const listen = async (
-    handleData: (
-      data: unknown,
-      remote: RemoteSender,
-      respond: ListenerResponder,
-      header?: AppHeader,
-      sign?: Sign
-    ) => void
+  // This is a wrapped form of the 'handleData' callback the user supplied, to listen or to registerRoute.
+  // Its job is to determine if the incoming data is a response to a request
+  // the user sent. It does this by referencing the UUID map object.
+  const extractUUIDHandleData = (
+    handleData: RouteHandler,
+    augDataStr: string,
+    remote: RemoteSender,
+    header?: AppHeader,
+    sign?: Sign
   ) => {
-    // This is a wrapped form of the 'handleData' callback the user supplied.
-    // Its job is to determine if the incoming data is a response to a request
-    // the user sent. It does this by referencing the UUID map object.
-    const extractUUIDHandleData = (
-      augDataStr: string,
-      remote: RemoteSender,
-      header?: AppHeader,
-      sign?: Sign
-    ) => {
-      // [TODO] Secure this with validation
-      let augData: AugmentedData = JSON.parse(augDataStr, base64BufferReviver)
-
-      //here we will log the received message.  note we exploit an aspect of augData
-      //that the data part is the first value and will be close enough to the start ot the string
-      //to save us from an expensive re-stringify just to get log data of the message
-      /* prettier-ignore */ if(logFlags.net_verbose) logMessageInfo(augData, augDataStr, false, Date.now())
-
-      const { PORT, UUID, data } = augData
-      const address = remote.address
-
-      const receivedTime = Date.now()
-      // This is the return send function. A user will call this if they want
-      // to "reply" or "respond" to an incoming message.
-      const respond: ListenerResponder = (data?: unknown, header?: AppHeader) => {
-        //we can do some timestamp work here for better logging.
-        const replyTime = Date.now()
-        if (replyTime > augData.sendTime + augData.timeout) {
-          /* prettier-ignore */ if(logFlags.net_verbose) console.log(`listen: extractUUIDHandleData: request id ${UUID}: reply time ${replyTime} is greater than timeout ${augData.sendTime + augData.timeout}. ignoring respond call`)
-          return
-        }
-        const sendData = {
-          data,
-          UUID,
-          PORT,
-          ADDRESS: undefined,
-          sendTime: augData.sendTime,
-          receivedTime,
-          replyTime,
-          replyReceivedTime: 0,
-          msgDir: 'resp',
-        }
+    // [TODO] Secure this with validation
+    let augData: AugmentedData = JSON.parse(augDataStr, base64BufferReviver)
+
+    //here we will log the received message.  note we exploit an aspect of augData
+    //that the data part is the first value and will be close enough to the start ot the string
+    //to save us from an expensive re-stringify just to get log data of the message
+    /* prettier-ignore */ if(logFlags.net_verbose) logMessageInfo(augData, augDataStr, false, Date.now())
+
+    const { PORT, UUID, data } = augData
+    // unix domain socket peers connect from an unnamed socket, so reply to the path they listen on
+    const address = remote.address?.startsWith(UNIX_SOCKET_PREFIX) ? augData.ADDRESS : remote.address
+
+    const receivedTime = Date.now()
+    // This is the return send function. A user will call this if they want
+    // to "reply" or "respond" to an incoming message.
+    const respond: ListenerResponder = (data?: unknown, header?: AppHeader) => {
+      //we can do some timestamp work here for better logging.
+      const replyTime = Date.now()
+      if (replyTime > augData.sendTime + augData.timeout) {
+        /* prettier-ignore */ if(logFlags.net_verbose) console.log(`listen: extractUUIDHandleData: request id ${UUID}: reply time ${replyTime} is greater than timeout ${augData.sendTime + augData.timeout}. ignoring respond call`)
+        return
+      }
+      const sendData = {
+        data,
+        UUID,
+        PORT,
+        ADDRESS: undefined,
+        sendTime: augData.sendTime,
+        receivedTime,
+        replyTime,
+        replyReceivedTime: 0,
+        msgDir: 'resp',
+      }
 
-        const combinedHeader: CombinedHeader = {
-          uuid: UUID,
-        }
-        if (header) {
-          combinedHeader.sender_id = header.sender_id
-          combinedHeader.tracker_id = header.tracker_id
-          combinedHeader.verification_data = header.verification_data
-          combinedHeader.compression = header.compression
-        }
+      const combinedHeader: CombinedHeader = {
+        uuid: UUID,
+      }
+      if (header) {
+        combinedHeader.sender_id = header.sender_id
+        combinedHeader.tracker_id = header.tracker_id
+        combinedHeader.verification_data = header.verification_data
+        combinedHeader.compression = header.compression
+      }
 
-        //@ts-ignore TODO: FIX THISSSSSS (Remove the ignore flag and make typescript not complain about address being possibly undefined)
-        // @TODO: This error should be properly propagated and logged.
-        return _wrappedSendAug(PORT, address, sendData, 0, noop, noop, {
-          version: HEADER_OPTS.sendHeaderVersion,
-          headerData: combinedHeader,
-        }).catch(console.error)
+      //@ts-ignore TODO: FIX THISSSSSS (Remove the ignore flag and make typescript not complain about address being possibly undefined)
+      // @TODO: This error should be properly propagated and logged.
+      return _wrappedSendAug(PORT, address, sendData, 0, noop, noop, {
+        version: HEADER_OPTS.sendHeaderVersion,
+        headerData: combinedHeader,
+      }).catch(console.error)
+    }
+
+    // If we are expecting a response, go through the respond mechanism.
+    // Otherwise, it's a normal incoming message.
+    if (responseUUIDMapping[UUID]) {
+      /* prettier-ignore */ if(logFlags.net_verbose) console.log(`listen: extractUUIDHandleData: request id ${UUID}: incoming message found in responseUUIDMapping`)
+      /* prettier-ignore */ if(logFlags.net_verbose) console.log(`listen: extractUUIDHandleData: request id ${UUID}: actual time taken for operation ${Date.now() - responseUUIDMapping[UUID].timestamp}ms`)
+      /* prettier-ignore */ if(logFlags.net_stats) histogram.logData((Date.now() - responseUUIDMapping[UUID].timestamp) / 1000)
+
+      const handle = responseUUIDMapping[UUID].callback
+      // Clear the respond mechanism.
+      delete responseUUIDMapping[UUID]
+      return handle(data, header, sign)
+    } else {
+      // check if the UUID is in the timedOutUUIDMapping
+      const entry = timedOutUUIDMapping.get(UUID)
+      if (entry != undefined) {
+        /* prettier-ignore */ if(logFlags.net_verbose) console.log(`listen: extractUUIDHandleData: request id ${UUID}: incoming message was found in timedOutUUIDMapping, timed out at ${entry.timedOutAt}, request created at ${entry.requestCreatedAt}, response received at ${Date.now()}`)
+        /* prettier-ignore */ if(logFlags.net_verbose) console.log(`listen: extractUUIDHandleData: request id ${UUID}: actual time taken for operation ${Date.now() - entry.requestCreatedAt}ms`)
+        /* prettier-ignore */ if(logFlags.net_stats) histogram.logData((Date.now() - entry.requestCreatedAt) / 1000)
+        timedOutUUIDMapping.delete(UUID)
       }
 
-      // If we are expecting a response, go through the respond mechanism.
-      // Otherwise, it's a normal incoming message.
-      if (responseUUIDMapping[UUID]) {
-        /* prettier-ignore */ if(logFlags.net_verbose) console.log(`listen: extractUUIDHandleData: request id ${UUID}: incoming message found in responseUUIDMapping`)
-        /* prettier-ignore */ if(logFlags.net_verbose) console.log(`listen: extractUUIDHandleData: request id ${UUID}: actual time taken for operation ${Date.now() - responseUUIDMapping[UUID].timestamp}ms`)
-        /* prettier-ignore */ if(logFlags.net_stats) histogram.logData((Date.now() - responseUUIDMapping[UUID].timestamp) / 1000)
-
-        const handle = responseUUIDMapping[UUID].callback
-        // Clear the respond mechanism.
-        delete responseUUIDMapping[UUID]
-        return handle(data, header, sign)
-      } else {
-        // check if the UUID is in the timedOutUUIDMapping
-        const entry = timedOutUUIDMapping.get(UUID)
-        if (entry != undefined) {
-          /* prettier-ignore */ if(logFlags.net_verbose) console.log(`listen: extractUUIDHandleData: request id ${UUID}: incoming message was found in timedOutUUIDMapping, timed out at ${entry.timedOutAt}, request created at ${entry.requestCreatedAt}, response received at ${Date.now()}`)
-          /* prettier-ignore */ if(logFlags.net_verbose) console.log(`listen: extractUUIDHandleData: request id ${UUID}: actual time taken for operation ${Date.now() - entry.requestCreatedAt}ms`)
-          /* prettier-ignore */ if(logFlags.net_stats) histogram.logData((Date.now() - entry.requestCreatedAt) / 1000)
-          timedOutUUIDMapping.delete(UUID)
-        }
+      return handleData(data, remote, respond, header, sign)
+    }
+  }
 
-        return handleData(data, remote, respond, header, sign)
+  // onData: the native callback for messages to handleData, parsing the header and sign that come as JSON
+  const onData = (handleData: RouteHandler) => (data, remoteIp, remotePort, headerVersion?, headerData?, signData?) => {
+    try {
+      if (headerVersion && headerData && signData) {
+        /* prettier-ignore */ if (logFlags.net_verbose) console.log(`received with header version: ${headerVersion}`)
+        const header: AppHeader = JSON.parse(headerData)
+        /* prettier-ignore */ if (logFlags.net_verbose) console.log(`received with header: ${JSON.stringify(header)}`)
+        /* prettier-ignore */ if (logFlags.net_verbose) console.log(`received with sign: ${signData}`)
+        const sign: Sign = JSON.parse(signData)
+        extractUUIDHandleData(
+          handleData,
+          data,
+          {
+            address: remoteIp,
+            port: remotePort,
+          },
+          header,
+          sign
+        )
+        return
       }
+
+      extractUUIDHandleData(handleData, data, {
+        address: remoteIp,
+        port: remotePort,
+      })
+    } catch (e) {
+      console.error("Error in shardus-net's listen callback:", e)
     }
+  }
 
+  const listen = async (handleData: RouteHandler, listenOpts?: ListenOpts): Promise<ListeningServer> => {
     // OLD comment from initial implementation:
     // TODO these should be spun up in parallel, but that convolutes code
     // and doesn't save hardly any startup time, so skipping for now.
     // const server = await _net.listen(PORT, ADDRESS, extractUUIDHandleData)
-    const server = await _net.listen((data, remoteIp, remotePort, headerVersion?, headerData?, signData?) => {
-      try {
-        if (headerVersion && headerData && signData) {
-          /* prettier-ignore */ if (logFlags.net_verbose) console.log(`received with header version: ${headerVersion}`)
-          const header: AppHeader = JSON.parse(headerData)
-          /* prettier-ignore */ if (logFlags.net_verbose) console.log(`received with header: ${JSON.stringify(header)}`)
-          /* prettier-ignore */ if (logFlags.net_verbose) console.log(`received with sign: ${signData}`)
-          const sign: Sign = JSON.parse(signData)
-          extractUUIDHandleData(
-            data,
-            {
-              address: remoteIp,
-              port: remotePort,
-            },
-            header,
-            sign
-          )
-          return
+    const additionalAddresses = (listenOpts && listenOpts.additionalAddresses) || []
+    return new Promise((resolve, reject) => {
+      _net.listen(
+        onData(handleData),
+        additionalAddresses.map(({ port }) => port),
+        additionalAddresses.map(({ address }) => address),
+        (error: Error | null, addresses?: ListenAddress[]) => {
+          if (error) {
+            reject(error)
+            return
+          }
+          // peers reply to the port in our messages, which has to be the one the OS picked for port 0
+          PORT = addresses[0].port
+          resolve({ addresses })
         }
-
-        extractUUIDHandleData(data, {
-          address: remoteIp,
-          port: remotePort,
-        })
-      } catch (e) {
-        console.error("Error in shardus-net's listen callback:", e)
-      }
+      )
     })
-
-    return server
   }
 
   const evictSocket = (port: number, address: string) => {
     return _net.evict_socket(port, address)
   }
 
+  const setPeerTransport = (port: number, address: string, transport: 'tcp' | 'quic') => {
+    return _net.set_peer_transport(port, address, transport)
+  }
+
+  const updateSigningKey = (signingSecretKeyHex: string, gracePeriodMs?: number) => {
+    return _net.update_signing_key(signingSecretKeyHex, gracePeriodMs)
+  }
+
+  // replaces the inbound rate limits, every remote starts over with a full bucket
+  const updateRateLimits = (rateLimitOpts: RateLimitOpts) => {
+    _net.update_rate_limits(toNativeRateLimits(rateLimitOpts))
+  }
+
+  // replaces the CIDR allow and deny lists, open connections are left alone. throws on an invalid block.
+  const updateIpFilter = (ipFilterOpts: IpFilterOpts) => {
+    _net.update_ip_filter(ipFilterOpts)
+  }
+
+  // messages sent with header.route go to handleData instead of the listen callback. messages to routes
+  // nobody registered, over routeOpts.maxSize or unsigned when routeOpts.requireSignature is set are dropped
+  // before reaching JS. registering a route again replaces its handler and options.
+  const registerRoute = (route: string | number, handleData: RouteHandler, routeOpts?: RouteOpts) => {
+    _net.register_route(String(route), onData(handleData), routeOpts?.maxSize, routeOpts?.requireSignature)
+  }
+
+  // false when the route was not registered
+  const unregisterRoute = (route: string | number): boolean => {
+    return _net.unregister_route(String(route))
+  }
+
   const stopListening = (server: any) => {
     return _net.stopListening(server)
   }
@@ -489,6 +725,19 @@ export const Sn = (opts: SnOpts) => {
 
   const stats = () => _net.stats()
 
+  const peerStats = (opts?: PeerStatsOpts): PeerStats[] => _net.peer_stats(opts?.top, opts?.orderBy)
+
+  // counters and handler latency per route, the route '' holds the messages sent and received without one
+  const routeStats = (): RouteStats[] => _net.route_stats()
+
+  // all stats in the OpenMetrics text format, as served on statsOpts.metricsPort
+  const metrics = (): string => _net.metrics()
+
+  // calls back with every connection lifecycle event from now on
+  const onConnectionEvent = (callback: (event: ConnectionEvent) => void) => {
+    _net.subscribe_events(callback)
+  }
+
   /**
    * This allows shardus core to set log flags for shardus net
    * If you use any additional flags they need to be added here
@@ -526,10 +775,21 @@ export const Sn = (opts: SnOpts) => {
     send,
     sendWithHeader,
     multiSendWithHeader,
+    multiSendWithDistinctPayloads,
     listen,
+    registerRoute,
+    unregisterRoute,
     stopListening,
     stats,
+    peerStats,
+    routeStats,
+    metrics,
+    onConnectionEvent,
     evictSocket,
+    setPeerTransport,
+    updateSigningKey,
+    updateRateLimits,
+    updateIpFilter,
     updateHeaderOpts,
     setLogFlags,
   }
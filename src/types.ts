@@ -46,21 +46,124 @@ export const NewAugData = (
 
 export type SnOpts = {
   port: number
+  // an IP/hostname, or `unix:/path/to/socket` to listen on a unix domain socket
   address?: string
   senderOpts?: {
     useLruCache?: boolean
     lruSize: number
+    // how long the resolved addresses of peers given by host name are reused, defaults to 60000. 0 resolves on every connect.
+    dnsCacheTtlMs?: number
+    // dial outbound TCP connections through a socks5:// or http:// (CONNECT) proxy, credentials go in the URL
+    proxy?: {
+      url: string
+      // CIDR blocks, addresses and host names (matching their subdomains too) connected to directly, `*` for all
+      bypass?: string[]
+    }
   }
   headerOpts?: {
     sendHeaderVersion: number
   }
+  quicOpts?: {
+    // also accept QUIC on the UDP port matching the TCP port. peers are switched over with setPeerTransport.
+    enabled?: boolean
+  }
+  verifyOpts?: {
+    // check received signatures in batches of up to this many messages. 0 or 1 checks each message on its own.
+    batchSize?: number
+    // longest time in microseconds a message waits for its batch to fill up, defaults to 500
+    batchWindowUs?: number
+  }
+  cpuPoolOpts?: {
+    // threads that verify, sign and (de)compress messages. defaults to one less than the number of cores.
+    threads?: number
+  }
+  statsOpts?: {
+    // recent windows latency percentiles are reported over, next to the lifetime ones. defaults to [10000, 60000].
+    latencyWindowsMs?: number[]
+    // serve the metrics() text on http://127.0.0.1:<metricsPort>/metrics. no endpoint when unset.
+    metricsPort?: number
+  }
+  rateLimitOpts?: RateLimitOpts
+  ipFilterOpts?: IpFilterOpts
+  // CIDR blocks of load balancers whose TCP connections open with a PROXY protocol v1 or v2 header. Messages
+  // on them are attributed to the client named in the header. Connections from elsewhere are never parsed.
+  trustedProxies?: string[]
+  socketOpts?: {
+    // connections the kernel queues until they are accepted, defaults to 1024
+    backlog?: number
+    // SO_REUSEADDR on the listener, defaults to true
+    reuseAddress?: boolean
+    // SO_REUSEPORT on the listener, so several processes can listen on the same port
+    reusePort?: boolean
+    // false lets a listener on an IPv6 address like '::' accept IPv4 peers too. unset keeps the OS default.
+    onlyV6?: boolean
+    // TCP_NODELAY on accepted and outbound sockets, defaults to false
+    nodelay?: boolean
+    // idle time before TCP keepalive probes on accepted and outbound sockets, no keepalive when unset
+    keepaliveMs?: number
+    // SO_SNDBUF and SO_RCVBUF in bytes on the listener and outbound sockets, OS defaults when unset
+    sendBufferSize?: number
+    recvBufferSize?: number
+  }
+  connectionLimitOpts?: {
+    // inbound connections over both transports, unlimited when unset
+    maxConnections?: number
+    // inbound connections from one remote IP, unlimited when unset
+    maxPerIp?: number
+    // refuse the new connection (default), or close the least recently active one to make room
+    onLimit?: 'refuse' | 'evict_idle'
+    // close a connection that sends no frame for this long, no timeout when unset
+    idleTimeoutMs?: number
+    // close a connection that takes longer than this to send a frame once its length is read
+    frameTimeoutMs?: number
+  }
   customStringifier?: (val) => string
   crypto: {
     hashKey: string
     signingSecretKeyHex: string
+    // encrypt traffic in Noise sessions keyed by the signing key instead of signing every message.
+    // both ends must enable this.
+    useNoiseSessions?: boolean
   }
 }
 
+// a sustained rate with bursts of up to one second's worth. unset rates are unlimited.
+export type RateLimit = {
+  messagesPerSecond?: number
+  bytesPerSecond?: number
+}
+
+export type RateLimitOpts = {
+  // per remote IP, checked for every received frame. unix socket peers are not limited.
+  perIp?: RateLimit
+  // per verified signer (Sign.owner), counting header and payload bytes
+  perSigner?: RateLimit
+  // also close the connection a frame over a limit arrived on
+  disconnect?: boolean
+}
+
+// CIDR blocks like '10.0.0.0/8' or 'fd00::/8', or single addresses. a denied IP is refused even when it
+// is allowed too, and with a non-empty allow list every IP outside of it is refused.
+export type ListenAddress = {
+  address: string
+  port: number
+}
+
+export type ListenOpts = {
+  // listen on these too, e.g. an internal next to an external interface. each gets its own QUIC endpoint when enabled.
+  additionalAddresses?: ListenAddress[]
+}
+
+// the addresses actually bound, the first one being SnOpts.address and port. port 0 is reported as the port the OS picked.
+export type ListeningServer = {
+  addresses: ListenAddress[]
+}
+
+export type IpFilterOpts = {
+  allow?: string[]
+  deny?: string[]
+}
+
 /**
  * Validates the provided options object for the SnOpts type.
  * @param opts - The options object to validate.
@@ -96,6 +199,8 @@ export interface AppHeader {
   tracker_id?: string
   verification_data?: string
   compression?: string
+  // deliver to the handler the receiver registered for this route instead of its listen callback
+  route?: string | number
 }
 
 export interface CombinedHeader {
@@ -104,6 +209,25 @@ export interface CombinedHeader {
   tracker_id?: string
   verification_data?: string
   compression?: string
+  route?: string
+}
+
+// the header version that carries a route, used for every header naming one
+export const ROUTE_HEADER_VERSION = 2
+
+export type RouteHandler = (
+  data: unknown,
+  remote: RemoteSender,
+  respond: ListenerResponder,
+  header?: AppHeader,
+  sign?: Sign
+) => void
+
+export type RouteOpts = {
+  // payload bytes, larger messages are dropped
+  maxSize?: number
+  // drop messages that are neither signed nor sent inside a noise session
+  requireSignature?: boolean
 }
 
 export type CompressionTechnique = 'Gzip' | 'Brotli'
@@ -114,3 +238,111 @@ export interface Sign {
 }
 
 export type GetSenderAddressResult = { address: string; isValid: boolean }
+
+export type RuntimeOpts = {
+  // async worker threads, defaults to one per core
+  workerThreads?: number
+  // cap on threads used for blocking work such as JS callbacks
+  maxBlockingThreads?: number
+  threadNamePrefix?: string
+  // run all async work on a single thread, leaving the other cores to Node
+  currentThread?: boolean
+}
+
+export type LogLevel = 'off' | 'error' | 'warn' | 'info' | 'debug' | 'trace'
+
+export type RustLogRecord = {
+  level: Exclude<LogLevel, 'off'>
+  target: string
+  module: string | null
+  message: string
+}
+
+export type TracingOpts = {
+  // append one JSON line per finished span to this file
+  jsonFile?: string
+  // export spans over OTLP/gRPC, e.g. http://127.0.0.1:4317
+  otlpEndpoint?: string
+  // service.name reported to the collector, defaults to shardus-net
+  serviceName?: string
+}
+
+export type PeerStatsOrderBy =
+  | 'bytes_sent'
+  | 'bytes_received'
+  | 'messages_sent'
+  | 'messages_received'
+  | 'send_errors'
+  | 'send_latency'
+  | 'last_activity'
+
+export type PeerStatsOpts = {
+  // only return the top N peers, sorted descending by orderBy (send_errors by default)
+  top?: number
+  orderBy?: PeerStatsOrderBy
+}
+
+export type PeerStats = {
+  address: string
+  port: number
+  bytes_sent: number
+  bytes_received: number
+  messages_sent: number
+  messages_received: number
+  // counts keyed by error kind: connection_failed, send_failed, handshake_failed, quic_send_failed
+  send_errors: { [kind: string]: number }
+  connects: number
+  reconnects: number
+  avg_send_latency_us: number
+  last_activity_ms: number
+}
+
+// latency percentiles in microseconds over one window
+export type LatencySummary = {
+  count: number
+  min: number
+  max: number
+  mean: number
+  p50: number
+  p90: number
+  p99: number
+  p999: number
+}
+
+export type RouteStats = {
+  route: string
+  messages_received: number
+  bytes_received: number
+  messages_sent: number
+  bytes_sent: number
+  // received messages dropped by the maxSize or requireSignature checks of the route
+  dropped: number
+  send_errors: number
+  // from a message being queued for JS until its handler runs, per latency window
+  callback_latency_us: { [window: string]: LatencySummary }
+}
+
+export type ConnectionEventType =
+  | 'opened'
+  | 'refused'
+  | 'closed'
+  | 'connect_failed'
+  | 'reconnect'
+  | 'evicted'
+  | 'bound'
+  | 'rebound'
+  | 'bind_failed'
+  | 'listener_failed'
+  | 'signature_rejected'
+
+export type ConnectionEvent = {
+  type: ConnectionEventType
+  // unset for listener events (bound, rebound, bind_failed, listener_failed)
+  direction?: 'inbound' | 'outbound'
+  transport: 'tcp' | 'quic' | 'unix'
+  // the remote peer, or the local listening address for listener events
+  address: string
+  port: number
+  reason?: string
+  timestamp_ms: number
+}